@@ -14,8 +14,10 @@ mod root_logic;
 use root_logic::{
     
     
-     BACK_WALL_MESH_DIMENSIONS, CUE_BALL_RADIUS, ClientMessage, GamePhase, GameState, STANDARD_BALL_RADIUS, TABLE_LENGTH, TABLE_WIDTH, TARGET_BALL_TORUS_DIMENSIONS, WALL_MESH_DIMENSIONS
+     BallData, CAMERA_HEIGHT, CUE_BALL_RADIUS, ClientMessage, CueTip, GamePhase, GameState, Pocket, ServerMessage, STANDARD_BALL_RADIUS, TableSpec, PROTOCOL_VERSION, TARGET_BALL_TORUS_DIMENSIONS
 };
+use root_logic::interpolation::SnapshotBuffer;
+use root_logic::snapshot::ReceivedSnapshots;
 use meshtext::{MeshGenerator, MeshText, TextSection as _};
 use serde::{Deserialize, Serialize};
 
@@ -32,7 +34,31 @@ struct NetworkClient {
 unsafe impl Send for NetworkClient {}
 unsafe impl Sync for NetworkClient {}
 
-const CAMERA_HEIGHT: Vec3 = Vec3 {x: 0.0, y: 1.97, z: 0.0};
+// Reconnecting after the socket drops: wait RECONNECT_FIRST_DELAY, doubling
+// on every failed attempt up to RECONNECT_MAX_DELAY
+const RECONNECT_FIRST_DELAY: f32 = 0.5;
+const RECONNECT_MAX_DELAY: f32 = 10.0;
+
+#[derive(Resource, Default)]
+struct Reconnect {
+    attempts: u32,
+    retry: Option<Timer>,
+    // the server turned us away or the match is over, so stay disconnected
+    give_up: bool,
+}
+
+impl Reconnect {
+    fn schedule(&mut self) {
+        if self.give_up || self.retry.is_some() {
+            return;
+        }
+        let delay = (RECONNECT_FIRST_DELAY * 2f32.powi(self.attempts.min(16) as i32)).min(RECONNECT_MAX_DELAY);
+        self.attempts += 1;
+        println!("Reconnecting in {:.1}s (attempt {})", delay, self.attempts);
+        self.retry = Some(Timer::from_seconds(delay, TimerMode::Once));
+    }
+}
+
 // --- Components (Visual Only) ---
 #[derive(Component)]
 struct VisualBall {
@@ -74,12 +100,16 @@ struct ContactAngleVisual;
 #[derive(Component)]
 struct BallReactionVector;
 #[derive(Component)]
-struct FloatingNumber(usize);
+struct FloatingNumber(u32);
 #[derive(Component)]
 struct TargetBallTorus;
 
+// Where the tip will strike the cue ball on the next shot
 #[derive(Component)]
-struct SpinSelector;
+struct SpinSelector(CueTip);
+
+const SPIN_STEP: f32 = 0.05; // fraction of the ball radius per key press
+const ELEVATION_STEP: f32 = 5.0; // degrees per key press
 #[derive(Component)]
 struct SecondWindow;
 
@@ -99,37 +129,10 @@ fn setup_physics(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>,   mut
       .insert(MaterialMeshBundle {mesh: meshes.add(Cuboid::from_corners(Vec3::new(12.25, 0.0, 14.5), Vec3::new(-12.25, 0.0, -14.5))), material: materials.add(StandardMaterial::from_color(Color::Hsla(Hsla::new(120.0 , 0.68, 0.93, 1.0)))), ..default()})
         .insert(TransformBundle::from(Transform::from_xyz(0.0, 3.0, 0.0)));
 
-    //create the walls
-    commands
-    .spawn(RigidBody::Fixed)
-    .insert(MaterialMeshBundle {mesh: meshes.add(WALL_MESH_DIMENSIONS), material: materials.add(StandardMaterial::from_color(Color::Hsla(Hsla::new(30.0 ,0.60, 0.20, 1.0)))), ..default()})
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(TABLE_WIDTH, 0.0, TABLE_WIDTH))));
-
-    commands
-    .spawn(RigidBody::Fixed)
-    .insert(MaterialMeshBundle {mesh: meshes.add(WALL_MESH_DIMENSIONS), material: materials.add(StandardMaterial::from_color(Color::Hsla(Hsla::new(30.0 ,0.60, 0.20, 1.0)))), ..default()})
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(TABLE_WIDTH, 0.0, -TABLE_WIDTH))));
-commands
-    .spawn(RigidBody::Fixed)
-    .insert(MaterialMeshBundle {mesh: meshes.add(WALL_MESH_DIMENSIONS), material: materials.add(StandardMaterial::from_color(Color::Hsla(Hsla::new(30.0 ,0.60, 0.20, 1.0)))), ..default()})
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(-TABLE_WIDTH, 0.0, TABLE_WIDTH))));
-
-    commands
-    .spawn(RigidBody::Fixed)
-    .insert(MaterialMeshBundle {mesh: meshes.add(WALL_MESH_DIMENSIONS), material: materials.add(StandardMaterial::from_color(Color::Hsla(Hsla::new(30.0 ,0.60, 0.20, 1.0)))), ..default()})
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(-TABLE_WIDTH, 0.0, -TABLE_WIDTH))));
-    commands
-    .spawn(RigidBody::Fixed)
-    .insert(MaterialMeshBundle {mesh: meshes.add(BACK_WALL_MESH_DIMENSIONS), material: materials.add(StandardMaterial::from_color(Color::Hsla(Hsla::new(30.0 ,0.60, 0.20, 1.0)))), ..default()})
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(0.0, 0.0, -TABLE_LENGTH))));
-    commands
-    .spawn(RigidBody::Fixed)
-    .insert(MaterialMeshBundle {mesh: meshes.add(BACK_WALL_MESH_DIMENSIONS), material: materials.add(StandardMaterial::from_color(Color::Hsla(Hsla::new(30.0 ,0.60, 0.20, 1.0)))), ..default()})
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(0.0, 0.0, TABLE_LENGTH))));
-
     //make aimer
     commands.spawn(ShotPower(1.0, true));
     commands.spawn(Aimer).insert(Sensor);
+    commands.spawn(SpinSelector(CueTip::center())).insert(Sensor);
     
  //   commands.spawn(TargetBallTorus)
    // .insert(MaterialMeshBundle{mesh: meshes.add(TARGET_BALL_TORUS_DIMENSIONS), material: materials.add(StandardMaterial::from_color(Color::Hsla(Hsla::new(30.0 ,0.60, 0.20, 1.0)))), ..default()});
@@ -138,6 +141,25 @@ commands
 
 }
 
+// Runs once the server's Handshake has told us which table we are playing on
+fn spawn_table(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, mut materials: ResMut<Assets<StandardMaterial>>, table_spec: Res<TableSpec>) {
+    //create the cushions, with openings for the six pockets
+    let rail_material = materials.add(StandardMaterial::from_color(Color::Hsla(Hsla::new(30.0 ,0.60, 0.20, 1.0))));
+    let jaws = Pocket::ALL.iter().flat_map(|p| p.jaw_boxes(&table_spec));
+    for rail in table_spec.rail_boxes().into_iter().chain(jaws) {
+        commands
+        .spawn(RigidBody::Fixed)
+        .insert(MaterialMeshBundle {mesh: meshes.add(Cuboid {half_size: rail.half_size}), material: rail_material.clone(), transform: rail.transform(), ..default()});
+    }
+
+    //the holes in the slate
+    let pocket_material = materials.add(StandardMaterial::from_color(Color::BLACK));
+    for pocket in Pocket::ALL {
+        commands
+        .spawn(MaterialMeshBundle {mesh: meshes.add(Cylinder::new(pocket.hole_radius(&table_spec), 0.002)), material: pocket_material.clone(), transform: Transform::from_translation(pocket.hole_center(&table_spec) + Vec3::Y * 0.001), ..default()});
+    }
+}
+
 
 
 #[derive(Resource)]
@@ -151,7 +173,7 @@ use wasm_bindgen::prelude::*;
 
 
 #[wasm_bindgen]
-pub fn run_game(canvas_id: String, gateway_url: String, handoff_token: String) {
+pub fn run_game(canvas_id: String, gateway_url: String, handoff_token: String, interpolation_delay_ms: Option<u32>) {
     let mut app = App::new();
     
     app.add_plugins(DefaultPlugins.set(WindowPlugin {
@@ -170,6 +192,11 @@ pub fn run_game(canvas_id: String, gateway_url: String, handoff_token: String) {
     });
 
     configure_app(&mut app);
+
+    // How far behind the server to draw; the default suits most connections
+    if let Some(delay) = interpolation_delay_ms {
+        app.insert_resource(SnapshotBuffer::new(delay as f64 / 1000.0));
+    }
     
     // In WASM, we use the specialized system that reads the Resource
     app.add_systems(Startup, connect_to_server_system);
@@ -185,15 +212,38 @@ fn connect_to_server_system(
     connect_to_server(&mut commands, &ticket.gateway_url, &ticket.handoff_token);
 }
 
+// Reconnects with the same handoff token once the backoff has run out; the
+// server gives the seat back and sends the current snapshot
+fn reconnect_with_backoff(
+    mut commands: Commands,
+    time: Res<Time>,
+    ticket: Option<Res<ConnectionTicket>>,
+    mut reconnect: ResMut<Reconnect>,
+) {
+    let (Some(ticket), Some(retry)) = (ticket, reconnect.retry.as_mut()) else {
+        return;
+    };
+    if retry.tick(time.delta()).finished() {
+        reconnect.retry = None;
+        connect_to_server(&mut commands, &ticket.gateway_url, &ticket.handoff_token);
+    }
+}
+
 fn configure_app(app: &mut App) {
     app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
        .add_plugins(RapierDebugRenderPlugin::default())
        .insert_resource(GameState::default())
-       .add_systems(Startup, (setup, spawn_pool_balls, setup_physics))
-       .add_systems(Startup, setup_numbers_above_pool_balls.after(setup))
+       .init_resource::<Reconnect>()
+       .init_resource::<ReceivedSnapshots>()
+       .init_resource::<SnapshotBuffer>()
+       .init_resource::<CalledBall>()
+       .add_systems(Startup, (setup, spawn_cue_ball, setup_physics))
+       .add_systems(Update, spawn_table.run_if(resource_added::<TableSpec>))
        .add_systems(Update, (
            handle_network, 
-           render_gamestate,
+           reconnect_with_backoff,
+           spawn_pool_balls.after(handle_network),
+           render_gamestate.after(spawn_pool_balls),
            show_numbers_above_pool_balls, 
            rotate_numbers_around_pool_balls
        ))
@@ -201,9 +251,12 @@ fn configure_app(app: &mut App) {
            aim_system, 
  //          rotate_torus, 
            display_shot_power, 
-           increase_shot_power
+           increase_shot_power,
+           select_spin,
+           display_spin_selector
        ).run_if(should_show_player_shot_controls))
        .add_systems(Update, ball_in_hand.run_if(should_show_player_shot_controls))
+       .add_systems(Update, (select_called_ball, push_out_or_pass).chain().run_if(should_show_player_shot_controls))
        .add_systems(Update, despawn_aimer_polyline.run_if(should_not_show_player_shot_controls));
 }
 
@@ -252,8 +305,14 @@ fn connect_to_server(commands: &mut Commands, url: &str, token: &str) {
 }
 
 fn handle_network(
+    mut commands: Commands,
     mut client: Option<ResMut<NetworkClient>>, 
     mut game_state: ResMut<GameState>,
+    table_spec: Option<Res<TableSpec>>,
+    mut reconnect: ResMut<Reconnect>,
+    mut snapshots: ResMut<ReceivedSnapshots>,
+    mut buffer: ResMut<SnapshotBuffer>,
+    time: Res<Time>,
 ) {
     if let Some(client) = client.as_mut() {
         // Loop through all available events
@@ -262,18 +321,97 @@ fn handle_network(
                 // 1. Connection is officially Open
                 WsEvent::Opened => {
                     println!("WebSocket Connection Established!");
-                    // IF your server requires a "join" message, send it HERE:
-                    // client.sender.send(WsMessage::Text(r#"{"join": "WebPlayer"}"#.to_string()));
+                    reconnect.attempts = 0;
+                    // a new connection starts over from a keyframe
+                    snapshots.clear();
+                    buffer.clear();
+                    // the server will not listen to anything else until it has this
+                    let hello = bincode::serialize(&ClientMessage::Hello { protocol_version: PROTOCOL_VERSION }).unwrap();
+                    client.sender.send(WsMessage::Binary(hello));
                 }
 
-                // 2. Binary Data (GameState updates)
-                WsEvent::Message(WsMessage::Binary(data)) => {
-                    if let Ok(new_state) = bincode::deserialize::<GameState>(&data) {
+                // 2. Binary Data (ServerMessages)
+                WsEvent::Message(WsMessage::Binary(data)) => match bincode::deserialize::<ServerMessage>(&data) {
+                    Ok(ServerMessage::Welcome { protocol_version, table, seat }) => {
+                        if protocol_version != PROTOCOL_VERSION {
+                            eprintln!("Server speaks protocol {}, this client speaks {}; reload the page", protocol_version, PROTOCOL_VERSION);
+                            reconnect.give_up = true;
+                            client.sender.close();
+                        // The table is only built once, keep it if the server sends it again
+                        } else if table_spec.is_none() {
+                            println!("Seated as {:?}, playing on table: {}", seat, table.name);
+                            commands.insert_resource(table);
+                        }
+                    }
+                    Ok(ServerMessage::Snapshot(snapshot)) => {
+                        let new_state = match snapshots.apply(&snapshot) {
+                            Ok(new_state) => new_state,
+                            Err(e) => {
+                                // not acknowledged, so the server keeps building on an older tick
+                                eprintln!("Dropping snapshot: {}", e);
+                                continue;
+                            }
+                        };
+                        let ack = bincode::serialize(&ClientMessage::Ack { tick: snapshot.tick }).unwrap();
+                        client.sender.send(WsMessage::Binary(ack));
+                        buffer.push(snapshot.server_time, time.elapsed_seconds_f64(), new_state.balls.clone());
+                        if new_state.groups != game_state.groups {
+                            println!("Groups: Player1 {:?}, Player2 {:?}", new_state.groups[0], new_state.groups[1]);
+                        }
+                        if new_state.called_pocket != game_state.called_pocket {
+                            if let Some(pocket) = new_state.called_pocket {
+                                println!("{} called in {:?}", new_state.called_ball.unwrap_or(8), pocket);
+                            }
+                        }
+                        if new_state.racks_won != game_state.racks_won {
+                            println!("Racks: Player1 {}, Player2 {} (race to {})", new_state.racks_won[0], new_state.racks_won[1], new_state.race_to);
+                        }
+                        if new_state.owned_pockets != game_state.owned_pockets {
+                            println!("Pockets: Player1 {:?}, Player2 {:?}", new_state.owned_pockets[0], new_state.owned_pockets[1]);
+                        }
+                        if new_state.away != game_state.away {
+                            println!("Away: Player1 {}, Player2 {}", new_state.away[0], new_state.away[1]);
+                        }
+                        if new_state.extensions_left != game_state.extensions_left {
+                            println!("Extensions left: Player1 {}, Player2 {}", new_state.extensions_left[0], new_state.extensions_left[1]);
+                        }
+                        if new_state.spectators != game_state.spectators {
+                            println!("{} watching", new_state.spectators);
+                        }
+                        if new_state.breaking && !game_state.breaking && new_state.innings > 1 {
+                            println!("{:?} has to break again", new_state.whose_move);
+                        }
+                        if new_state.scores != game_state.scores || new_state.innings != game_state.innings {
+                            println!("Score: Player1 {}, Player2 {} (inning {})", new_state.scores[0], new_state.scores[1], new_state.innings);
+                        }
                         *game_state = new_state;
-                    } else {
-                        eprintln!("Failed to deserialize GameState (size: {} bytes)", data.len());
                     }
-                }
+                    Ok(ServerMessage::ShotResult(outcome)) => {
+                        println!("{:?} pocketed {:?}", outcome.shooter, outcome.pocketed.iter().map(|p| p.number).collect::<Vec<_>>());
+                    }
+                    Ok(ServerMessage::ShotReplay { start_time, trajectory, outcome }) => {
+                        // the server already knows how it ends; play it back as the clock gets there
+                        buffer.replay(start_time, trajectory.states());
+                        println!("{:?} pocketed {:?}", outcome.shooter, outcome.pocketed.iter().map(|p| p.number).collect::<Vec<_>>());
+                    }
+                    Ok(ServerMessage::FoulCalled { shooter, reason }) => println!("Foul by {:?}: {}", shooter, reason),
+                    Ok(ServerMessage::TurnChanged { shooter }) => println!("{:?} to shoot", shooter),
+                    Ok(ServerMessage::BallInHandGranted { player }) => println!("{:?} has ball in hand", player),
+                    Ok(ServerMessage::MatchOver { winner, racks_won }) => {
+                        println!("{:?} wins the match {}-{}", winner, racks_won[0], racks_won[1]);
+                        reconnect.give_up = true;
+                    }
+                    Ok(ServerMessage::MatchAbandoned { racks_won }) => {
+                        println!("Match abandoned at {}-{}", racks_won[0], racks_won[1]);
+                        reconnect.give_up = true;
+                    }
+                    Ok(ServerMessage::Rejected { message }) => println!("Not allowed: {}", message),
+                    Ok(ServerMessage::Error { message }) => {
+                        eprintln!("Server error: {}", message);
+                        reconnect.give_up = true;
+                    }
+                    Err(_) => eprintln!("Failed to deserialize ServerMessage (size: {} bytes)", data.len()),
+                },
 
                 // 3. Text Data (Debugging/Chat)
                 WsEvent::Message(WsMessage::Text(text)) => {
@@ -283,9 +421,11 @@ fn handle_network(
                 // 4. Errors & Closing
                 WsEvent::Error(e) => {
                     eprintln!("WebSocket Error: {}", e);
+                    reconnect.schedule();
                 }
                 WsEvent::Closed => {
                     println!("WebSocket Disconnected.");
+                    reconnect.schedule();
                 }
                 _ => {} // Handle Ping/Pong or Unknown
             }
@@ -308,37 +448,37 @@ fn should_show_ball_in_hand(gamestate: Res<GameState>) -> bool {
 
 // --- Network and State Handling ---
 
-fn render_gamestate(mut exit: EventWriter<AppExit>, mut commands: Commands, gamestate: Res<GameState>, cue_ball_query: Query<Entity, With<CueBall>>, pool_ball_query: Query<(Entity, &PoolBalls)>) {
-     
-     for i in &gamestate.balls{
+fn render_gamestate(mut exit: EventWriter<AppExit>, mut commands: Commands, gamestate: Res<GameState>, buffer: Res<SnapshotBuffer>, time: Res<Time>, cue_ball_query: Query<Entity, With<CueBall>>, pool_ball_query: Query<(Entity, &PoolBalls, &Visibility)>) {
+     // drawn a little behind the server, smoothed between snapshots
+     let balls = buffer.balls_at(time.elapsed_seconds_f64());
+
+     for i in &balls{
         if i.is_cue {
             let cue_ball = cue_ball_query.single();
             commands.entity(cue_ball).insert(TransformBundle::from_transform(Transform {translation: i.position, rotation: i.rotation, ..default()}));
         } else {
-        if let Some( pool_ball )= pool_ball_query.iter().find(|(entity, pool_ball)| pool_ball.0 as u32 == i.number) {
+        if let Some( pool_ball )= pool_ball_query.iter().find(|(entity, pool_ball, _)| pool_ball.0 as u32 == i.number) {
            
             commands.entity(pool_ball.0).insert(TransformBundle::from_transform(Transform {translation: i.position, rotation: i.rotation, ..default()}));
         } 
         }
     }
 
-    if gamestate.balls.is_empty() {
+    if balls.is_empty() {
         return;
     }
 
-    //remove pocketed balls from stale client render
-
-    for (entity, pool_ball) in pool_ball_query.iter() {
-    
-    // Check if this specific ball ID exists in the incoming server gamestate
-    // We use .any() which returns true if found, false if not.
-    let exists_on_server = gamestate.balls.iter().any(|ball_data| ball_data.number == pool_ball.0);
-
-    // If it does NOT exist on the server, it has been pocketed. Despawn it locally.
-    if !exists_on_server {
-        commands.entity(entity).despawn_recursive();
+    // Balls off the table are hidden rather than despawned: the next rack,
+    // a straight pool rerack or a spotted ball brings the same number back
+    for (entity, pool_ball, visibility) in pool_ball_query.iter() {
+        let exists_on_server = balls.iter().any(|ball_data| ball_data.number == pool_ball.0);
+        if exists_on_server && *visibility == Visibility::Hidden {
+            commands.entity(entity).insert(Visibility::Visible).remove::<ColliderDisabled>();
+        } else if !exists_on_server && *visibility != Visibility::Hidden {
+            // and kept out of the aiming line's shape cast
+            commands.entity(entity).insert((Visibility::Hidden, ColliderDisabled));
+        }
     }
-}
 
     
 
@@ -350,21 +490,21 @@ fn render_gamestate(mut exit: EventWriter<AppExit>, mut commands: Commands, game
 
 // --- Ball Spawning (Including User's Preferred Color and Placement Fix) ---
 
-fn ball_color(n: usize) -> Color {
+fn ball_color(n: u32) -> Color {
     match n {
-        1 | 9 => Color::rgb(1.0, 1.0, 0.0), // Yellow (1 = solid, 9 = stripe)
-        2     => Color::rgb(0.0, 0.0, 1.0), // Blue
-        3     => Color::rgb(1.0, 0.0, 0.0), // Red
-        4     => Color::rgb(0.5, 0.0, 0.5), // Purple
-        5     => Color::rgb(1.0, 0.5, 0.0), // Orange
-        6     => Color::rgb(0.0, 1.0, 0.0), // Green
-        7     => Color::rgb(0.5, 0.0, 0.0), // Maroon
-        8     => Color::BLACK,             // Black
-        _     => Color::WHITE,             // Fallback
+        1 | 9  => Color::rgb(1.0, 1.0, 0.0), // Yellow (1 = solid, 9 = stripe)
+        2 | 10 => Color::rgb(0.0, 0.0, 1.0), // Blue
+        3 | 11 => Color::rgb(1.0, 0.0, 0.0), // Red
+        4 | 12 => Color::rgb(0.5, 0.0, 0.5), // Purple
+        5 | 13 => Color::rgb(1.0, 0.5, 0.0), // Orange
+        6 | 14 => Color::rgb(0.0, 1.0, 0.0), // Green
+        7 | 15 => Color::rgb(0.5, 0.0, 0.0), // Maroon
+        8      => Color::BLACK,             // Black
+        _      => Color::WHITE,             // Fallback
     }
 }
 
-fn spawn_pool_balls(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>,   mut materials: ResMut<Assets<StandardMaterial>>, mut fonts: ResMut<Assets<Font>>, gamestate: Res<GameState>) {
+fn spawn_cue_ball(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>,   mut materials: ResMut<Assets<StandardMaterial>>) {
 
     // Spawn Cue Ball with correct height
     commands.spawn(CueBall).insert(MaterialMeshBundle {
@@ -372,19 +512,26 @@ fn spawn_pool_balls(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>,
         material: materials.add(StandardMaterial::from_color(WHITE)), 
         ..default()
     }).insert(Collider::ball(CUE_BALL_RADIUS)).insert(Sensor).insert(Transform::from_translation(Vec3::from((0.0,CUE_BALL_RADIUS, 0.0))));
-    
-    // Spawn Object Balls (1-9) with correct color and height
-    for i in 1 as usize..=9 as usize{
-        let color = ball_color(i);
-        commands.spawn(PoolBalls(i as u32))
+}
+
+// Object balls come from the snapshots: nine-ball racks 1-9, ten-ball 1-10
+// and eight-ball, straight pool and one-pocket 1-15, so a ball is made the
+// first time its number shows up
+fn spawn_pool_balls(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>,   mut materials: ResMut<Assets<StandardMaterial>>, gamestate: Res<GameState>, pool_ball_query: Query<&PoolBalls>) {
+    for ball in gamestate.balls.iter().filter(|b| !b.is_cue) {
+        if pool_ball_query.iter().any(|pool_ball| pool_ball.0 == ball.number) {
+            continue;
+        }
+        let color = ball_color(ball.number);
+        commands.spawn(PoolBalls(ball.number))
             .insert(MaterialMeshBundle {
                 mesh: meshes.add(Sphere::new(STANDARD_BALL_RADIUS)), 
                 material: materials.add(StandardMaterial::from_color(color)), 
-                // FIX: Setting Y-translation to STANDARD_BALL_RADIUS to lift the ball off the table.
-                transform: Transform::from_translation(Vec3::new(0.0, STANDARD_BALL_RADIUS, 0.0)),
+                transform: Transform::from_translation(ball.position),
                 ..default()
             })
             .insert(Collider::ball(STANDARD_BALL_RADIUS));
+        commands.spawn(FloatingNumber(ball.number)).insert(MaterialMeshBundle {mesh: meshes.add(number_mesh(ball.number)), material: materials.add(StandardMaterial::from_color(color)), transform: Transform {translation: ball.position + Vec3::Y * 0.04, rotation: Quat::from_rotation_x(0.0), scale: Vec3::ONE * 1.5},visibility: Visibility::Visible,..default()});
     }
 }
 
@@ -420,7 +567,7 @@ fn get_vec3_of_local_cursor_position_from_global(camera: &Camera, camera_transfo
 }
 
 
-fn despawn_aimer_polyline(mut commands: Commands, mut aimer_query: Query<Entity, With< Aimer>>, ball_reaction_angle_query:  Query<Entity, With<BallReactionVector>>, reaction_angle_query: Query<Entity, With<ContactAngleVisual>>, shot_power_query: Query<Entity, With<ShotPower>>,) {
+fn despawn_aimer_polyline(mut commands: Commands, mut aimer_query: Query<Entity, With< Aimer>>, ball_reaction_angle_query:  Query<Entity, With<BallReactionVector>>, reaction_angle_query: Query<Entity, With<ContactAngleVisual>>, shot_power_query: Query<Entity, With<ShotPower>>, spin_selector_query: Query<Entity, With<SpinSelector>>,) {
     if let Ok(aimer) = aimer_query.get_single() {
         commands.entity(aimer).remove::<Collider>();
     }
@@ -437,17 +584,21 @@ fn despawn_aimer_polyline(mut commands: Commands, mut aimer_query: Query<Entity,
         commands.entity(shot_power).remove::<Collider>();
     }
 
+    if let Ok(spin_selector) = spin_selector_query.get_single() {
+        commands.entity(spin_selector).remove::<Collider>();
+    }
+
 
 }
 
-fn ball_in_hand(connection_ticket: Res<ConnectionTicket>, mut network_client: ResMut<NetworkClient>,camera_query:  Query<(&Camera, &GlobalTransform), With<MyGameCamera>>,keys: Res<ButtonInput<KeyCode>>, q_window: Query<&Window, With<PrimaryWindow>> ) {
+fn ball_in_hand(mut network_client: ResMut<NetworkClient>,camera_query:  Query<(&Camera, &GlobalTransform), With<MyGameCamera>>,keys: Res<ButtonInput<KeyCode>>, q_window: Query<&Window, With<PrimaryWindow>> ) {
     
      
         let window = q_window.single();
         let (camera, camera_transform) = camera_query.single();
         if let Ok(mut local_cursor) = get_vec3_of_local_cursor_position_from_global(camera, camera_transform, window) {
         if keys.just_pressed(KeyCode::KeyA) {
-            let message = ClientMessage::BallPlacement { position: local_cursor, token: connection_ticket.handoff_token.clone() };
+            let message = ClientMessage::BallPlacement { position: local_cursor };
             
             let payload = bincode::serialize(&message).unwrap();
             let ws_message = WsMessage::Binary(payload);
@@ -466,6 +617,70 @@ fn ball_in_hand(connection_ticket: Res<ConnectionTicket>, mut network_client: Re
 
 
 
+// The ball the player has clicked on to call with the next pocket key
+#[derive(Resource, Default)]
+struct CalledBall(Option<u32>);
+
+// Clicking on or near an object ball picks it as the ball to call
+fn select_called_ball(mut called_ball: ResMut<CalledBall>, gamestate: Res<GameState>, mouse: Res<ButtonInput<MouseButton>>, camera_query:  Query<(&Camera, &GlobalTransform), With<MyGameCamera>>, q_window: Query<&Window, With<PrimaryWindow>>) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let window = q_window.single();
+    let (camera, camera_transform) = camera_query.single();
+    let Ok(local_cursor) = get_vec3_of_local_cursor_position_from_global(camera, camera_transform, window) else {
+        return;
+    };
+    let distance = |ball: &&BallData| Vec2::new(ball.position.x - local_cursor.x, ball.position.z - local_cursor.z).length();
+    let nearest = gamestate.balls.iter()
+        .filter(|b| !b.is_cue)
+        .filter(|b| distance(b) <= STANDARD_BALL_RADIUS * 2.0)
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)));
+    if let Some(ball) = nearest {
+        println!("Calling the {}, now pick the pocket", ball.number);
+        called_ball.0 = Some(ball.number);
+    }
+}
+
+// Keys 1-6 call a pocket, for the 8 in eight-ball or the clicked ball in
+// ten-ball and straight pool (the lowest if none was clicked in ten-ball):
+// head left, head right, side left, side right, foot left, foot right
+const POCKET_KEYS: [KeyCode; 6] = [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4, KeyCode::Digit5, KeyCode::Digit6];
+
+fn called_pocket(keys: &ButtonInput<KeyCode>) -> Option<Pocket> {
+    POCKET_KEYS.iter().position(|key| keys.just_pressed(*key)).map(|i| Pocket::ALL[i])
+}
+
+// P calls a push-out, O hands the table back after the opponent's push-out,
+// E takes the shot clock extension
+fn push_out_or_pass(mut network_client: ResMut<NetworkClient>, gamestate: Res<GameState>, keys: Res<ButtonInput<KeyCode>>, mut called_ball: ResMut<CalledBall>) {
+    let message = if keys.just_pressed(KeyCode::KeyP) && gamestate.push_out_available {
+        ClientMessage::PushOut
+    } else if keys.just_pressed(KeyCode::KeyO) && gamestate.may_pass {
+        ClientMessage::Pass
+    } else if keys.just_pressed(KeyCode::KeyE) && gamestate.extensions_left[gamestate.whose_move.index()] {
+        ClientMessage::Extension
+    } else if let Some(pocket) = called_pocket(&keys) {
+        if gamestate.groups[gamestate.whose_move.index()].is_some() {
+            ClientMessage::CallPocket { pocket }
+        } else {
+            // any ball on the table may be called, for a combination or a
+            // carom too; rotation games call the lowest ball unless told otherwise
+            let clicked = called_ball.0.take().filter(|ball| gamestate.balls.iter().any(|b| !b.is_cue && b.number == *ball));
+            let Some(ball) = clicked.or(gamestate.target_ball) else {
+                println!("Click the ball you are calling first");
+                return;
+            };
+            ClientMessage::CallShot { ball, pocket }
+        }
+    } else {
+        return;
+    };
+
+    let payload = bincode::serialize(&message).unwrap();
+    let _ = network_client.sender.send(WsMessage::Binary(payload));
+}
+
 fn calculate_z(x: f32, y: f32, r: f32) -> Option<f32> {
     let z_squared = r.powi(2) - x.powi(2) - y.powi(2);
     if z_squared >= 0.0 {
@@ -475,7 +690,7 @@ fn calculate_z(x: f32, y: f32, r: f32) -> Option<f32> {
     }
 }
 
-fn aim_system(connection_ticket: Res<ConnectionTicket>, mut network_client: ResMut<NetworkClient>,shot_power_query: Query<(Entity, &ShotPower)>,cue_ball_query: Query<(&Transform, Entity), With<CueBall>> , pool_ball_query: Query<(Entity, &Transform),With<PoolBalls>>, mut ball_reaction_angle_query:  Query<Entity, With<BallReactionVector>>, reaction_angle_query: Query<Entity, With<ContactAngleVisual>>, rapier_context: Res<RapierContext>, mut commands: Commands, keys: Res<ButtonInput<KeyCode>>,  camera_query:  Query<(&Camera, &GlobalTransform), With<MyGameCamera>>,  q_window: Query<&Window, With<PrimaryWindow>>, mut aimer_query: Query<Entity, With< Aimer>>) {
+fn aim_system(mut network_client: ResMut<NetworkClient>,shot_power_query: Query<(Entity, &ShotPower)>, spin_selector_query: Query<&SpinSelector>,cue_ball_query: Query<(&Transform, Entity), With<CueBall>> , pool_ball_query: Query<(Entity, &Transform),With<PoolBalls>>, mut ball_reaction_angle_query:  Query<Entity, With<BallReactionVector>>, reaction_angle_query: Query<Entity, With<ContactAngleVisual>>, rapier_context: Res<RapierContext>, mut commands: Commands, keys: Res<ButtonInput<KeyCode>>,  camera_query:  Query<(&Camera, &GlobalTransform), With<MyGameCamera>>,  q_window: Query<&Window, With<PrimaryWindow>>, mut aimer_query: Query<Entity, With< Aimer>>) {
     // There is only one primary window, so we can similarly get it from the query:
 
     let (shot_power_entity, shot_power) = shot_power_query.single();
@@ -574,8 +789,7 @@ fn aim_system(connection_ticket: Res<ConnectionTicket>, mut network_client: ResM
                         let message = ClientMessage::Shot{ 
                               power: shot_power.0 * 1.25, 
                               direction: direction_vector.normalize_or_zero(), 
-                              angvel: Vec3::ZERO,
-                            token: connection_ticket.handoff_token.clone()  
+                              tip: spin_selector_query.get_single().map(|spin| spin.0).unwrap_or_default(),
                           };
                           let payload = bincode::serialize(&message).unwrap();
                           let ws_message = WsMessage::Binary(payload);
@@ -612,27 +826,28 @@ fn rotate_numbers_around_pool_balls(mut commands: Commands, mut number_query: Qu
     }
 }
 
-fn show_numbers_above_pool_balls(mut commands: Commands, mut ball_query: Query<(Entity, &Transform, &PoolBalls)>, mut floater_query: Query<(&mut Transform, &FloatingNumber), Without<PoolBalls>>) {
-    for (pool_ball_entity,  pool_ball_transform, pool_ball_itself) in ball_query.iter_mut() {
-        for  (mut floater_transform, number_itself) in floater_query.iter_mut() {
-            if number_itself.0 as u32  == pool_ball_itself.0 {
+fn show_numbers_above_pool_balls(mut commands: Commands, mut ball_query: Query<(Entity, &Transform, &Visibility, &PoolBalls)>, mut floater_query: Query<(&mut Transform, &mut Visibility, &FloatingNumber), Without<PoolBalls>>) {
+    for (pool_ball_entity,  pool_ball_transform, pool_ball_visibility, pool_ball_itself) in ball_query.iter_mut() {
+        for  (mut floater_transform, mut floater_visibility, number_itself) in floater_query.iter_mut() {
+            if number_itself.0 == pool_ball_itself.0 {
                 floater_transform.translation = pool_ball_transform.translation + Vec3::Y * 0.04;
                 floater_transform.align(-Dir3::Y, Dir3::X, -Dir3::X, Dir3::Z);
+                // a pocketed ball's number goes with it
+                if *floater_visibility != *pool_ball_visibility {
+                    *floater_visibility = *pool_ball_visibility;
+                }
             }
         }
     }
 }
 
-fn setup_numbers_above_pool_balls( mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, mut materials: ResMut<Assets<StandardMaterial>>, ball_query: Query<(Entity, &Transform, &PoolBalls)>) {
+// The number that floats over a ball
+fn number_mesh(number: u32) -> Mesh {
     let font_data = include_bytes!("../../assets/fonts/Roboto-Black.ttf");
     let mut generator = MeshGenerator::new(font_data);
-    for (pool_ball_entity, pool_ball_transform, pool_ball_itself) in ball_query.iter() {
-        let translation = pool_ball_transform.translation;
-        let raw_number = pool_ball_itself.0;
-        let number = raw_number.to_string().as_str().to_owned();
     let transform = Mat4::from_scale(Vec3::new(0.075, 0.075, 0.0075)).to_cols_array();
     let text_mesh: MeshText = generator
-        .generate_section(&number, false, Some(&transform))
+        .generate_section(&number.to_string(), false, Some(&transform))
         .unwrap();
 
     let vertices = text_mesh.vertices;
@@ -642,21 +857,78 @@ fn setup_numbers_above_pool_balls( mut commands: Commands, mut meshes: ResMut<As
     mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
     mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
     mesh.compute_flat_normals();
-    let material = match raw_number {
-          1 | 9 => StandardMaterial::from_color(Color::rgb(1.0, 1.0, 0.0)), // Yellow (1 = solid, 9 = stripe)
-        2     => StandardMaterial::from_color(Color::rgb(0.0, 0.0, 1.0)), // Blue
-        3     => StandardMaterial::from_color(Color::rgb(1.0, 0.0, 0.0)), // Red
-        4     => StandardMaterial::from_color(Color::rgb(0.5, 0.0, 0.5)), // Purple
-        5     => StandardMaterial::from_color(Color::rgb(1.0, 0.5, 0.0)), // Orange
-        6     => StandardMaterial::from_color(Color::rgb(0.0, 1.0, 0.0)), // Green
-        7     => StandardMaterial::from_color(Color::rgb(0.5, 0.0, 0.0)), // Maroon
-        8     => StandardMaterial::from_color(Color::BLACK),      
-        _ => StandardMaterial::from_color(Color::linear_rgb(0.60, 0.80, 1.0))
+    mesh
+}
+
+
+// Arrow keys move the tip around the face of the cue ball, Q/E lower and
+// raise the cue, C goes back to centre ball
+fn select_spin(keys: Res<ButtonInput<KeyCode>>, mut spin_selector_query: Query<&mut SpinSelector>) {
+    let Ok(mut spin_selector) = spin_selector_query.get_single_mut() else {
+        return;
     };
-    commands.spawn(FloatingNumber(raw_number as usize)).insert(MaterialMeshBundle {mesh: meshes.add(mesh), material: materials.add(material), transform: Transform {translation: Vec3::new(0.0,  0.00, 0.0), rotation: Quat::from_rotation_x(0.0), scale: Vec3::ONE * 1.5},visibility: Visibility::Visible,..default()});
+    let mut tip = spin_selector.0;
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        tip.offset.y += SPIN_STEP;
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        tip.offset.y -= SPIN_STEP;
+    }
+    if keys.just_pressed(KeyCode::ArrowRight) {
+        tip.offset.x += SPIN_STEP;
+    }
+    if keys.just_pressed(KeyCode::ArrowLeft) {
+        tip.offset.x -= SPIN_STEP;
+    }
+    if keys.just_pressed(KeyCode::KeyE) {
+        tip.elevation += ELEVATION_STEP;
+    }
+    if keys.just_pressed(KeyCode::KeyQ) {
+        tip.elevation -= ELEVATION_STEP;
+    }
+    if keys.just_pressed(KeyCode::KeyC) {
+        tip = CueTip::center();
+    }
+    if tip != spin_selector.0 {
+        spin_selector.0 = tip.clamped();
     }
 }
 
+// Draws a ring around the cue ball with a line out to where the tip will
+// hit: towards the shot for follow, behind it for draw, sideways for English
+fn display_spin_selector(mut commands: Commands, spin_selector_query: Query<(Entity, &SpinSelector)>, cue_ball_query: Query<&Transform, With<CueBall>>, camera_query:  Query<(&Camera, &GlobalTransform), With<MyGameCamera>>, q_window: Query<&Window, With<PrimaryWindow>>) {
+    let (Ok((spin_selector_entity, spin_selector)), Ok(cue_ball)) = (spin_selector_query.get_single(), cue_ball_query.get_single()) else {
+        return;
+    };
+    let window = q_window.single();
+    let (camera, camera_transform) = camera_query.single();
+    let Ok(local_cursor) = get_vec3_of_local_cursor_position_from_global(camera, camera_transform, window) else {
+        return;
+    };
+
+    let mut aim = local_cursor - cue_ball.translation;
+    aim.y = 0.0;
+    let aim = aim.normalize_or(Vec3::Z);
+    let side = aim.cross(Vec3::Y);
+
+    let center = cue_ball.translation + Vec3::Y * (CUE_BALL_RADIUS + 0.005);
+    let ring_radius = CUE_BALL_RADIUS * 2.0;
+    const RING_SEGMENTS: u32 = 24;
+    let mut vertices: Vec<Vec3> = (0..RING_SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (aim * angle.cos() + side * angle.sin()) * ring_radius
+        })
+        .collect();
+    let mut indices: Vec<[u32; 2]> = (0..RING_SEGMENTS).map(|i| [i, (i + 1) % RING_SEGMENTS]).collect();
+
+    let tip = spin_selector.0.offset;
+    vertices.push(center);
+    vertices.push(center + (side * tip.x + aim * tip.y) * ring_radius);
+    indices.push([RING_SEGMENTS, RING_SEGMENTS + 1]);
+
+    commands.entity(spin_selector_entity).insert(Collider::polyline(vertices, Some(indices)));
+}
 
 fn increase_shot_power(mut commands: Commands,  mut shot_power_query: Query<(Entity, &mut ShotPower)>) {
     if let Ok((shot_power_entity, mut shot_power)) = shot_power_query.get_single_mut() {
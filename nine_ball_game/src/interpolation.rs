@@ -0,0 +1,209 @@
+// src/interpolation.rs
+// Where the client draws the balls. Snapshots arrive unevenly, so the
+// client draws a little in the past, between the two snapshots either side
+// of that moment, and runs the balls on at their last velocity when the
+// next snapshot is late. Shot replays go in the same buffer, ahead of the
+// server clock, and play out as it catches up.
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use super::BallData;
+
+// How far behind the server the client draws, in seconds, unless told otherwise
+pub const DEFAULT_INTERPOLATION_DELAY: f64 = 0.1;
+// How far past a snapshot the balls are run on before they are held still.
+// The server sends every frame while anything moves, so a longer gap
+// between two snapshots means nothing changed until the second one.
+pub const MAX_EXTRAPOLATION: f64 = 0.25;
+// How quickly the clock estimate gives up a fast sample for slower ones
+const CLOCK_RELAXATION: f64 = 0.01;
+
+/// Snapshots stamped with server time, and the estimate of the server clock
+/// that says which of them to draw.
+#[derive(Resource, Debug)]
+pub struct SnapshotBuffer {
+    pub delay: f64,
+    snapshots: VecDeque<(f64, Vec<BallData>)>,
+    // local clock minus server clock, leaning on the quickest snapshots
+    offset: Option<f64>,
+}
+
+impl Default for SnapshotBuffer {
+    fn default() -> Self {
+        SnapshotBuffer::new(DEFAULT_INTERPOLATION_DELAY)
+    }
+}
+
+impl SnapshotBuffer {
+    pub fn new(delay: f64) -> Self {
+        SnapshotBuffer { delay, snapshots: VecDeque::new(), offset: None }
+    }
+
+    pub fn push(&mut self, server_time: f64, local_time: f64, balls: Vec<BallData>) {
+        // a packet that took longer than the others says nothing about the
+        // clocks; one that was quicker moves the estimate straight away
+        let sample = local_time - server_time;
+        self.offset = Some(match self.offset {
+            Some(offset) if sample >= offset => offset + (sample - offset) * CLOCK_RELAXATION,
+            _ => sample,
+        });
+        self.insert(server_time, balls);
+
+        // keep one snapshot at or before the render time to start from
+        let render_time = self.render_time(local_time).unwrap_or(f64::MIN);
+        while self.snapshots.len() > 2 && self.snapshots[1].0 <= render_time {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Queues a replayed shot struck at `start_time` to be drawn as the
+    /// server clock reaches each frame.
+    pub fn replay(&mut self, start_time: f64, frames: Vec<(f32, Vec<BallData>)>) {
+        for (time, balls) in frames {
+            self.insert(start_time + time as f64, balls);
+        }
+    }
+
+    fn insert(&mut self, server_time: f64, balls: Vec<BallData>) {
+        match self.snapshots.back_mut() {
+            // a replay still playing out is ahead of the server clock; the
+            // state that follows it is where its last frame ends up
+            Some((newest, last)) if server_time <= *newest => *last = balls,
+            _ => self.snapshots.push_back((server_time, balls)),
+        }
+    }
+
+    /// The server time being drawn at `local_time`.
+    pub fn render_time(&self, local_time: f64) -> Option<f64> {
+        self.offset.map(|offset| local_time - offset - self.delay)
+    }
+
+    /// The balls as they should be drawn at `local_time`; empty until the
+    /// first snapshot arrives.
+    pub fn balls_at(&self, local_time: f64) -> Vec<BallData> {
+        let Some(render_time) = self.render_time(local_time) else {
+            return Vec::new();
+        };
+        let Some(later) = self.snapshots.iter().position(|(time, _)| *time > render_time) else {
+            // late: run the newest on
+            return self.snapshots.back().map(|(time, balls)| extrapolate(balls, render_time - time)).unwrap_or_default();
+        };
+        if later == 0 {
+            // nothing that old is kept, draw the oldest there is
+            return self.snapshots[0].1.clone();
+        }
+
+        let (t0, before) = &self.snapshots[later - 1];
+        let (t1, after) = &self.snapshots[later];
+        if t1 - t0 > MAX_EXTRAPOLATION {
+            return extrapolate(before, render_time - t0);
+        }
+        let alpha = ((render_time - t0) / (t1 - t0)) as f32;
+        before
+            .iter()
+            .map(|ball| match after.iter().find(|next| next.number == ball.number) {
+                Some(next) => BallData {
+                    position: ball.position.lerp(next.position, alpha),
+                    velocity: ball.velocity.lerp(next.velocity, alpha),
+                    rotation: ball.rotation.slerp(next.rotation, alpha),
+                    ..ball.clone()
+                },
+                // pocketed by the later snapshot, gone once it is reached
+                None => ball.clone(),
+            })
+            .collect()
+    }
+
+    // a new connection starts over, clock estimate and all
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.offset = None;
+    }
+}
+
+fn extrapolate(balls: &[BallData], elapsed: f64) -> Vec<BallData> {
+    let elapsed = elapsed.clamp(0.0, MAX_EXTRAPOLATION) as f32;
+    balls.iter().map(|ball| BallData { position: ball.position + ball.velocity * elapsed, ..ball.clone() }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ball_at(x: f32, velocity: f32) -> Vec<BallData> {
+        vec![BallData { number: 1, position: Vec3::new(x, 0.0, 0.0), velocity: Vec3::new(velocity, 0.0, 0.0), rotation: Quat::IDENTITY, is_cue: false }]
+    }
+
+    // snapshots every 50 ms with no network delay, the ball moving at 1 m/s
+    fn moving_buffer() -> SnapshotBuffer {
+        let mut buffer = SnapshotBuffer::new(0.1);
+        for i in 0..=4 {
+            let t = i as f64 * 0.05;
+            buffer.push(t, 10.0 + t, ball_at(t as f32, 1.0));
+        }
+        buffer
+    }
+
+    #[test]
+    fn balls_are_drawn_between_the_snapshots_either_side_of_the_render_time() {
+        let buffer = moving_buffer();
+        // local 10.175 is server 0.175, drawn at 0.075
+        let balls = buffer.balls_at(10.175);
+        assert!((buffer.render_time(10.175).unwrap() - 0.075).abs() < 1e-9);
+        assert!((balls[0].position.x - 0.075).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rotations_are_slerped() {
+        let mut buffer = SnapshotBuffer::new(0.0);
+        let mut turned = ball_at(0.0, 0.0);
+        turned[0].rotation = Quat::from_rotation_y(1.0);
+        buffer.push(0.0, 0.0, ball_at(0.0, 0.0));
+        buffer.push(0.1, 0.1, turned);
+        let balls = buffer.balls_at(0.05);
+        assert!(balls[0].rotation.angle_between(Quat::from_rotation_y(0.5)) < 1e-4);
+    }
+
+    #[test]
+    fn late_snapshots_are_extrapolated_for_a_while_then_held() {
+        let buffer = moving_buffer();
+        // newest is server 0.2; at local 10.35 the render time is 0.25
+        assert!((buffer.balls_at(10.35)[0].position.x - 0.25).abs() < 1e-5);
+        let held = buffer.balls_at(20.0)[0].position.x;
+        assert!((held - (0.2 + MAX_EXTRAPOLATION as f32)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn a_long_gap_means_the_balls_sat_still_until_the_next_snapshot() {
+        let mut buffer = SnapshotBuffer::new(0.0);
+        buffer.push(0.0, 0.0, ball_at(0.0, 0.0));
+        buffer.push(30.0, 30.0, ball_at(1.0, 0.0));
+        // ball in hand moved the cue ball; it jumps rather than sliding over
+        assert_eq!(buffer.balls_at(29.9)[0].position.x, 0.0);
+        assert_eq!(buffer.balls_at(30.0)[0].position.x, 1.0);
+    }
+
+    #[test]
+    fn replays_play_out_and_the_state_after_them_waits_its_turn() {
+        let mut buffer = SnapshotBuffer::new(0.0);
+        buffer.push(0.0, 0.0, ball_at(0.0, 0.0));
+        buffer.replay(1.0, vec![(0.0, ball_at(0.0, 1.0)), (0.5, ball_at(0.5, 1.0)), (1.0, ball_at(1.0, 0.0))]);
+        // the server has judged the shot by 1.1 and spotted the ball at 0.9
+        buffer.push(1.1, 1.1, ball_at(0.9, 0.0));
+
+        assert!((buffer.balls_at(1.25)[0].position.x - 0.25).abs() < 1e-5);
+        assert_eq!(buffer.balls_at(2.5)[0].position.x, 0.9);
+    }
+
+    #[test]
+    fn the_clock_estimate_follows_the_quickest_packets() {
+        let mut buffer = SnapshotBuffer::new(0.0);
+        buffer.push(0.0, 5.3, Vec::new());
+        buffer.push(0.1, 5.2, Vec::new());
+        buffer.push(0.2, 5.9, Vec::new());
+        // the second packet set the offset to 5.1; the slow third one barely moves it
+        let offset = 5.3 - buffer.render_time(5.3).unwrap();
+        assert!((offset - 5.1).abs() < 0.01);
+    }
+}
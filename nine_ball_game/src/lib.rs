@@ -5,36 +5,63 @@ use std::default;
 // This allows the compiler to see the contents of src/client/
 // src/lib.rs
 use bevy::prelude::*;
-use bevy_rapier3d::prelude::{Damping, Restitution, Velocity};
+use bevy_rapier3d::prelude::{Restitution, Velocity};
 use serde::{Deserialize, Serialize};
 
+pub mod interpolation;
+pub mod physics;
+pub mod rules;
+pub mod simulation;
+pub mod snapshot;
+pub mod table;
+pub mod trajectory;
+pub use physics::cue::CueTip;
+pub use rules::{eight_ball::BallGroup, Foul, ShotOutcome};
+pub use snapshot::Snapshot;
+pub use table::{Pocket, PocketedBall, TableSpec};
+pub use trajectory::Trajectory;
+
 // --- Physics Constants ---
-pub const TABLE_LENGTH: f32  = 1.3716 * 1.05;
-pub const TABLE_WIDTH: f32  = TABLE_LENGTH / 2.0;
 pub const STANDARD_BALL_RADIUS: f32 = 5.7 / 100.0 / 2.0;
 pub const CUE_BALL_RADIUS: f32 = 5.7127 / 100.0 / 2.0;
 pub const BALL_MASS: f32 = 0.17;
-pub const TABLE_FRICTION_COEFF: f32 = 1.00;
+pub const GRAVITY: f32 = 9.81;
 pub const FRICTION_COEFF: f32 = 1.0;
 pub const BALL_FRICTION_COEFF:f32 = 1.0;
 pub const BALL_RESTITUTION: Restitution = Restitution::coefficient(1.00);
+pub const BALL_BALL_RESTITUTION: f32 = 0.95;
 pub const DEFAULT_VELOCITY: Velocity =  Velocity {
     linvel: Vec3::ZERO,
     angvel: Vec3::ZERO
 
 };
-pub const BALL_DAMPING: Damping = Damping {
-linear_damping: 0.2533301,
-angular_damping: 0.253301
-};
-pub const WALL_DIMENSIONS: Cuboid = Cuboid{half_size: Vec3::new(0.02, 120.55, 0.6096)};
-pub const WALL_MESH_DIMENSIONS: Cuboid = Cuboid{half_size: Vec3::new(0.02, 5.55, 0.6096)};
-pub const BACK_WALL_MESH_DIMENSIONS: Cuboid = Cuboid{half_size: Vec3::new(0.6096, 120.55, 0.002)};
+
+// --- Cue ---
+pub const CUE_MASS: f32 = 0.54; // 19 oz
+pub const CUE_END_MASS: f32 = 0.009; // shaft mass that moves with the tip, sets squirt
+pub const CUE_TIP_EFFICIENCY: f32 = 0.75; // tip/ball coefficient of restitution
+pub const MISCUE_LIMIT: f32 = 0.5; // furthest tip offset from centre, fraction of radius
+pub const MAX_CUE_ELEVATION: f32 = 80.0; // degrees
+pub const MAX_SHOT_POWER: f32 = 12.0; // cue ball speed off a centre-ball hit (m/s), a hard break
+
+// --- Table Geometry (WPA pocket specs) ---
+// Table size, pocket mouths and cloth are per table, see TableSpec
+pub const CORNER_POCKET_MOUTH: f32 = 0.1143; // 4.5", regulation default
+pub const SIDE_POCKET_MOUTH: f32 = 0.1302; // 5.125", regulation default
+pub const CORNER_JAW_ANGLE: f32 = 142.0; // degrees between cushion nose and jaw facing
+pub const SIDE_JAW_ANGLE: f32 = 104.0;
+pub const CORNER_POCKET_SHELF: f32 = 0.02; // mouth line to the edge of the slate hole
+pub const SIDE_POCKET_SHELF: f32 = 0.01;
+pub const RAIL_WIDTH: f32 = 0.05;
+pub const RAIL_HEIGHT: f32 = 0.045;
+pub const CUSHION_NOSE_HEIGHT: f32 = 0.0362; // 63.5% of the ball diameter, regulation default
+pub const CUSHION_RESTITUTION_FALLOFF: f32 = 0.05; // per m/s of approach speed
+pub const BED_HALF_THICKNESS: f32 = 0.025;
 pub const TARGET_BALL_TORUS_DIMENSIONS: Torus = Torus{ minor_radius: 0.002 , major_radius: 0.06 };
 pub const CAMERA_HEIGHT: Vec3 = Vec3 {x: 0.0, y: 1.97, z: 0.0};
 
 // --- Shared Data Protocol ---
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct BallData {
     pub number: u32,
     pub position: Vec3,
@@ -43,20 +70,127 @@ pub struct BallData {
     pub is_cue: bool,
 }
 // ... (rest of GameState, GamePhase, ClientMessage remain the same)
-#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct GameState {
     pub balls: Vec<BallData>,
     pub phase: GamePhase,
     pub should_show_shot_controls: bool,
-    pub whose_move: WhoseMove
+    pub whose_move: WhoseMove,
+    // How the rules judged the most recent shot
+    pub last_outcome: Option<ShotOutcome>,
+    pub consecutive_fouls: [u8; 2],
+    pub push_out_available: bool,
+    // Nine-ball and ten-ball: the shooter has called the coming shot a push-out
+    pub push_out_declared: bool,
+    pub may_pass: bool,
+    // Eight-ball: each player's group once the table is no longer open,
+    // and the pocket called for the 8
+    pub groups: [Option<BallGroup>; 2],
+    pub called_pocket: Option<Pocket>,
+    // Eight-ball: ball in hand goes behind the head string, after a foul on the break
+    pub in_hand_in_kitchen: bool,
+    // Ten-ball: the ball called for the coming shot
+    pub called_ball: Option<u32>,
+    // Rotation games: the ball that has to be hit first
+    pub target_ball: Option<u32>,
+    // Straight pool: running score per player and the inning being played
+    pub scores: [i32; 2],
+    pub innings: u32,
+    // The shooter has to break: the first shot of a rack, or in straight
+    // pool the one after a third foul in a row
+    pub breaking: bool,
+    // One-pocket: the pocket each player scores in
+    pub owned_pockets: [Option<Pocket>; 2],
+    // One-pocket: the balls each player has made and kept, and the balls
+    // each owes for fouls they had nothing to spot for
+    pub balls_made: [Vec<u32>; 2],
+    pub balls_owed: [u32; 2],
+    // Racks won per player in the match, and how many it takes to win it
+    pub racks_won: [u32; 2],
+    pub race_to: u32,
+    // Players whose connection has dropped and who have not come back yet
+    pub away: [bool; 2],
+    // Spectators watching, on however long a delay
+    pub spectators: u32,
+    // Seconds left on the shot clock; None while the balls roll or with no clock
+    pub shot_clock: Option<f32>,
+    // Whether each player still has this rack's extension
+    pub extensions_left: [bool; 2],
+}
+
+
+// Bumped whenever ServerMessage or ClientMessage change shape, so a client
+// built against another version is turned away instead of misreading frames
+pub const PROTOCOL_VERSION: u32 = 14;
+
+// Who a connection is, decided once from the token it connected with.
+// Everything it sends is attributed to its seat.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Seat {
+    Player1,
+    Player2,
+    Spectator,
+}
+
+impl Seat {
+    pub fn player(&self) -> Option<WhoseMove> {
+        match self {
+            Seat::Player1 => Some(WhoseMove::Player1),
+            Seat::Player2 => Some(WhoseMove::Player2),
+            Seat::Spectator => None,
+        }
+    }
 }
 
+// Everything the server sends, as bincode binary frames
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ServerMessage {
+    // First message on every connection. Keep it the first variant, with the
+    // version first, so every build can read it.
+    Welcome { protocol_version: u32, table: TableSpec, seat: Seat },
+    // The game whenever it changes, as a delta against the last tick the
+    // client acknowledged or as a keyframe (see snapshot.rs)
+    Snapshot(Snapshot),
+    // How the rules judged a shot, once the balls have stopped
+    ShotResult(ShotOutcome),
+    FoulCalled { shooter: WhoseMove, reason: Foul },
+    TurnChanged { shooter: WhoseMove },
+    BallInHandGranted { player: WhoseMove },
+    MatchOver { winner: WhoseMove, racks_won: [u32; 2] },
+    Error { message: String },
+    // Nobody played for the whole inactivity limit; the match ends without a winner
+    MatchAbandoned { racks_won: [u32; 2] },
+    // A move the server turned down, sent only to whoever made it; unlike
+    // Error the connection stays open
+    Rejected { message: String },
+    // With --instant-shots: the whole shot, resolved as soon as it was
+    // struck, for playback from start_time (server time) on, and how the
+    // rules judged it. Takes the place of ShotResult.
+    ShotReplay { start_time: f64, trajectory: Trajectory, outcome: ShotOutcome },
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ClientMessage {
+    // First message on every connection; answered with an Error and a close
+    // when the versions differ. Keep it the first variant, with the version
+    // first, so every build can read it.
+    Hello { protocol_version: u32 },
     Join { name: String },
-    Shot { power: f32, direction: Vec3, angvel: Vec3, token: String }, 
-    BallPlacement { position: Vec3, token: String },
+    // power is the cue ball speed (m/s) a centre-ball hit would give
+    Shot { power: f32, direction: Vec3, tip: CueTip },
+    BallPlacement { position: Vec3 },
+    // call the next shot a push-out (nine-ball, first shot after the break)
+    PushOut,
+    // hand the table back after the opponent's push-out
+    Pass,
+    // name the pocket for the 8 (eight-ball)
+    CallPocket { pocket: Pocket },
+    // name the ball and pocket for the coming shot (ten-ball)
+    CallShot { ball: u32, pocket: Pocket },
+    // add time to the shot clock, once per rack
+    Extension,
+    // the client has rebuilt this snapshot tick, so later ones may build on it
+    Ack { tick: u32 },
 }
 
 #[derive(States,Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -66,6 +200,23 @@ pub enum WhoseMove {
     Player2
 }
 
+impl WhoseMove {
+    pub fn other(&self) -> WhoseMove {
+        match self {
+            WhoseMove::Player1 => WhoseMove::Player2,
+            WhoseMove::Player2 => WhoseMove::Player1,
+        }
+    }
+
+    // position in per-player arrays
+    pub fn index(&self) -> usize {
+        match self {
+            WhoseMove::Player1 => 0,
+            WhoseMove::Player2 => 1,
+        }
+    }
+}
+
 
 
 #[derive(States, Default,Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -0,0 +1,204 @@
+// src/physics/cloth.rs
+// Ball on cloth: the ball skids until the contact point stops slipping,
+// then rolls, while spin about the vertical axis wears off on its own.
+//
+// With u the slip velocity of the contact point, sliding friction gives
+//     dv/dt = -μs g û,   dω/dt = (5 μs g / 2R) (Y × û)
+// which takes u to zero at a steady (7/2) μs g. A rolling ball slows at
+// μr g and keeps ω locked to v; vertical spin slows at (5 μsp g / 2R).
+use bevy::prelude::*;
+
+use super::super::GRAVITY;
+
+/// Slip below this (m/s) counts as rolling.
+const SLIP_EPS: f32 = 1e-4;
+
+/// Ball–cloth coefficients for one table, see `TableSpec::cloth`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cloth {
+    pub sliding_friction: f32,
+    pub rolling_resistance: f32,
+    pub spin_friction: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Sliding,
+    Rolling,
+    Spinning,
+    Stationary,
+}
+
+/// Velocity of the point of the ball touching the cloth, on the table plane.
+pub fn slip_velocity(linvel: Vec3, angvel: Vec3, radius: f32) -> Vec3 {
+    Vec3::new(linvel.x + radius * angvel.z, 0.0, linvel.z - radius * angvel.x)
+}
+
+pub fn motion(linvel: Vec3, angvel: Vec3, radius: f32) -> Motion {
+    let horizontal = Vec3::new(linvel.x, 0.0, linvel.z);
+    if slip_velocity(linvel, angvel, radius).length() > SLIP_EPS {
+        Motion::Sliding
+    } else if horizontal.length() > SLIP_EPS {
+        Motion::Rolling
+    } else if angvel.y.abs() > SLIP_EPS / radius {
+        Motion::Spinning
+    } else {
+        Motion::Stationary
+    }
+}
+
+impl Cloth {
+    /// Advance a ball that is sitting on the cloth by `dt` seconds. The
+    /// vertical component of `linvel` is left to the physics engine.
+    pub fn step(&self, linvel: Vec3, angvel: Vec3, radius: f32, dt: f32) -> (Vec3, Vec3) {
+        let mut v = linvel;
+        let mut w = angvel;
+        let mut remaining = dt;
+
+        // sliding, possibly reaching natural roll part way through the step
+        let slip = slip_velocity(v, w, radius);
+        let slip_speed = slip.length();
+        if slip_speed > SLIP_EPS && self.sliding_friction > 0.0 {
+            let slip_dir = slip / slip_speed;
+            let time_to_roll = 2.0 * slip_speed / (7.0 * self.sliding_friction * GRAVITY);
+            let t = remaining.min(time_to_roll);
+            v -= slip_dir * (self.sliding_friction * GRAVITY * t);
+            w += Vec3::Y.cross(slip_dir) * (2.5 * self.sliding_friction * GRAVITY / radius * t);
+            remaining -= t;
+            if remaining > 0.0 {
+                w = rolling_angvel(v, w, radius);
+            }
+        }
+
+        // rolling, with omega following v
+        if remaining > 0.0 {
+            let horizontal = Vec3::new(v.x, 0.0, v.z);
+            let speed = horizontal.length();
+            if speed > 0.0 {
+                let new_speed = (speed - self.rolling_resistance * GRAVITY * remaining).max(0.0);
+                let horizontal = horizontal * (new_speed / speed);
+                v = Vec3::new(horizontal.x, v.y, horizontal.z);
+            }
+            w = rolling_angvel(v, w, radius);
+        }
+
+        // spin about the vertical wears off whatever the ball is doing
+        let spin_loss = 2.5 * self.spin_friction * GRAVITY / radius * dt;
+        w.y = w.y.signum() * (w.y.abs() - spin_loss).max(0.0);
+
+        (v, w)
+    }
+}
+
+// angular velocity with no slip at the cloth, keeping the vertical spin
+fn rolling_angvel(linvel: Vec3, angvel: Vec3, radius: f32) -> Vec3 {
+    Vec3::new(linvel.z / radius, angvel.y, -linvel.x / radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const R: f32 = 0.028575;
+    const DT: f32 = 1.0 / 60.0;
+
+    fn cloth() -> Cloth {
+        Cloth { sliding_friction: 0.2, rolling_resistance: 0.01, spin_friction: 0.013 }
+    }
+
+    // roll the ball out the way Rapier would integrate it and report how
+    // far it went and how long it took
+    fn run_out(cloth: &Cloth, mut v: Vec3, mut w: Vec3) -> (Vec3, f32) {
+        let mut position = Vec3::ZERO;
+        let mut time = 0.0;
+        while motion(v, w, R) != Motion::Stationary {
+            (v, w) = cloth.step(v, w, R, DT);
+            position += v * DT;
+            time += DT;
+            assert!(time < 120.0, "ball never stopped");
+        }
+        (position, time)
+    }
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * tolerance,
+            "expected {} within {}%, got {}",
+            expected,
+            tolerance * 100.0,
+            actual
+        );
+    }
+
+    #[test]
+    fn rolling_ball_stops_at_v_squared_over_two_mu_r_g() {
+        let c = cloth();
+        for speed in [0.5, 1.0, 2.0] {
+            let v = Vec3::Z * speed;
+            let (travel, time) = run_out(&c, v, rolling_angvel(v, Vec3::ZERO, R));
+            assert_close(travel.z, speed * speed / (2.0 * c.rolling_resistance * GRAVITY), 0.01);
+            assert_close(time, speed / (c.rolling_resistance * GRAVITY), 0.01);
+            assert!(travel.x.abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn stun_shot_slides_then_rolls_at_five_sevenths() {
+        let c = cloth();
+        let speed = 2.0;
+        let (v, w) = c.step(Vec3::Z * speed, Vec3::ZERO, R, 1.0);
+        assert_eq!(motion(v, w, R), Motion::Rolling);
+        assert_close(v.z, speed * 5.0 / 7.0 - c.rolling_resistance * GRAVITY * (1.0 - 2.0 * speed / (7.0 * c.sliding_friction * GRAVITY)), 0.001);
+
+        // slide: (v0² - (5/7 v0)²) / 2 μs g, then roll (5/7 v0)² / 2 μr g
+        let rolled = speed * 5.0 / 7.0;
+        let expected = (speed * speed - rolled * rolled) / (2.0 * c.sliding_friction * GRAVITY)
+            + rolled * rolled / (2.0 * c.rolling_resistance * GRAVITY);
+        let (travel, _) = run_out(&c, Vec3::Z * speed, Vec3::ZERO);
+        assert_close(travel.z, expected, 0.01);
+    }
+
+    #[test]
+    fn stun_shot_matches_the_published_sliding_distance() {
+        // D. G. Alciatore, "The Illustrated Principles of Pool and Billiards"
+        // (2004), TP 4.1: a centre-ball hit at v0 slides 12 v0² / (49 μ g) in
+        // 2 v0 / (7 μ g), then rolls at 5/7 v0. For v0 = 2 m/s and μ = 0.2
+        // that is 0.499 m in 0.291 s, rolling on at 1.43 m/s.
+        let c = cloth();
+        let dt = 1e-4;
+        let (mut v, mut w) = (Vec3::Z * 2.0, Vec3::ZERO);
+        let (mut slid, mut time) = (0.0, 0.0);
+        while motion(v, w, R) == Motion::Sliding {
+            let before = v.z;
+            (v, w) = c.step(v, w, R, dt);
+            slid += (before + v.z) / 2.0 * dt;
+            time += dt;
+        }
+        assert_close(slid, 0.499, 0.005);
+        assert_close(time, 0.291, 0.005);
+        assert_close(v.z, 1.43, 0.005);
+    }
+
+    #[test]
+    fn draw_comes_back_and_follow_goes_further() {
+        let c = cloth();
+        let speed = 1.0;
+        let roll = speed / R;
+        let (stun, _) = run_out(&c, Vec3::Z * speed, Vec3::ZERO);
+        let (follow, _) = run_out(&c, Vec3::Z * speed, Vec3::X * roll * 1.5);
+        // heavy draw: enough backspin that the ball ends up rolling backwards
+        let (draw, _) = run_out(&c, Vec3::Z * speed, -Vec3::X * roll * 4.0);
+        assert!(follow.z > stun.z);
+        assert!(draw.z < 0.0, "draw should pull the ball back, went {}", draw.z);
+    }
+
+    #[test]
+    fn english_alone_wears_off() {
+        let c = cloth();
+        let (v, w) = c.step(Vec3::ZERO, Vec3::Y * 50.0, R, DT);
+        assert_eq!(v, Vec3::ZERO);
+        assert_close(w.y, 50.0 - 2.5 * c.spin_friction * GRAVITY / R * DT, 0.001);
+        let (_, time) = run_out(&c, Vec3::ZERO, Vec3::Y * 50.0);
+        assert_close(time, 50.0 / (2.5 * c.spin_friction * GRAVITY / R), 0.02);
+    }
+}
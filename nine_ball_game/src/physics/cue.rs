@@ -0,0 +1,188 @@
+// src/physics/cue.rs
+// Cue strike: turns where the tip hits the cue ball into its initial
+// linear and angular velocity.
+//
+// The tip delivers an impulse J along the cue axis d at a point r on the
+// ball. Treating the tip as a point mass gives
+//     J = (1 + e) v / (1/M + (1/m)(1 + 5(a² + b²) / 2R²))
+// where a and b are the tip offsets across the cue axis, then
+//     linvel = J d / m,   angvel = r × J d / I.
+// Side English also pushes the ball off the aim line (squirt), by the angle
+// the shaft's end mass soaks up as the tip slides sideways.
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::super::{BALL_MASS, CUE_END_MASS, CUE_MASS, CUE_TIP_EFFICIENCY, MAX_CUE_ELEVATION, MAX_SHOT_POWER, MISCUE_LIMIT};
+
+/// Where the tip meets the cue ball, as chosen with the spin selector.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct CueTip {
+    /// x: right (+) / left (-) English, y: follow (+) / draw (-), as fractions
+    /// of the ball radius seen from behind the cue ball.
+    pub offset: Vec2,
+    /// Angle of the cue above horizontal, in degrees.
+    pub elevation: f32,
+}
+
+impl CueTip {
+    pub fn center() -> Self {
+        CueTip::default()
+    }
+
+    /// The same tip, moved back inside the miscue limit and elevation range.
+    pub fn clamped(self) -> Self {
+        CueTip {
+            offset: self.offset.clamp_length_max(MISCUE_LIMIT),
+            elevation: self.elevation.clamp(0.0, MAX_CUE_ELEVATION),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Strike {
+    pub linvel: Vec3,
+    pub angvel: Vec3,
+}
+
+/// A shot as the server will play it, from the numbers a client sent: the
+/// aim flattened onto the bed and normalised, the power cut to
+/// `MAX_SHOT_POWER` and the tip clamped. Refuses anything that is not a
+/// finite number, and shots with no aim or no power.
+pub fn sanitize_shot(power: f32, direction: Vec3, tip: CueTip) -> Result<(f32, Vec3, CueTip), String> {
+    if !(power.is_finite() && direction.is_finite() && tip.offset.is_finite() && tip.elevation.is_finite()) {
+        return Err("shot is not made of finite numbers".to_string());
+    }
+    if power <= 0.0 {
+        return Err(format!("power {} is not a forward stroke", power));
+    }
+    let aim = Vec3::new(direction.x, 0.0, direction.z).try_normalize().ok_or("shot has no aim along the table")?;
+    Ok((power.min(MAX_SHOT_POWER), aim, tip.clamped()))
+}
+
+/// Cue speed needed for a centre-ball hit to send the cue ball off at `ball_speed`.
+pub fn cue_speed_for(ball_speed: f32) -> f32 {
+    ball_speed * (1.0 + BALL_MASS / CUE_MASS) / (1.0 + CUE_TIP_EFFICIENCY)
+}
+
+/// Squirt angle in radians for side offset `a` (fraction of R). Positive
+/// offsets (right English) push the ball to the left of the aim line.
+pub fn squirt_angle(a: f32) -> f32 {
+    let a = a.clamp(-1.0, 1.0);
+    let lever = 2.5 * a * (1.0 - a * a).sqrt();
+    (lever / (1.0 + BALL_MASS / CUE_END_MASS + 2.5 * (1.0 - a * a))).atan()
+}
+
+/// Velocity of a cue ball of `radius` hit with the cue moving at `cue_speed`
+/// along horizontal `aim`, with the tip at `tip`.
+pub fn strike(aim: Vec3, cue_speed: f32, tip: CueTip, radius: f32) -> Strike {
+    let tip = tip.clamped();
+    let aim = Vec3::new(aim.x, 0.0, aim.z).normalize_or_zero();
+    if aim == Vec3::ZERO || cue_speed <= 0.0 {
+        return Strike { linvel: Vec3::ZERO, angvel: Vec3::ZERO };
+    }
+
+    // cue frame: d along the cue (tilted down by the elevation), s to the
+    // shooter's right, u up and perpendicular to the cue
+    let theta = tip.elevation.to_radians();
+    let side = aim.cross(Vec3::Y);
+    let d = aim * theta.cos() - Vec3::Y * theta.sin();
+    let u = aim * theta.sin() + Vec3::Y * theta.cos();
+
+    let a = tip.offset.x * radius;
+    let b = tip.offset.y * radius;
+    let c = (radius * radius - a * a - b * b).max(0.0).sqrt();
+    let r = side * a + u * b - d * c;
+
+    let inertia = 0.4 * BALL_MASS * radius * radius;
+    let inv_mass_at_tip = (1.0 + 2.5 * (a * a + b * b) / (radius * radius)) / BALL_MASS;
+    let impulse = (1.0 + CUE_TIP_EFFICIENCY) * cue_speed / (1.0 / CUE_MASS + inv_mass_at_tip);
+
+    let angvel = r.cross(d * impulse) / inertia;
+
+    // squirt turns the ball's path away from the English; a positive turn
+    // about +Y swings any horizontal aim towards the shooter's left
+    let squirt = Quat::from_rotation_y(squirt_angle(tip.offset.x));
+    let linvel = squirt * (d * impulse / BALL_MASS);
+
+    Strike { linvel, angvel }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::STANDARD_BALL_RADIUS;
+
+    const R: f32 = STANDARD_BALL_RADIUS;
+
+    fn hit(offset: Vec2) -> Strike {
+        strike(Vec3::Z, cue_speed_for(2.0), CueTip { offset, elevation: 0.0 }, R)
+    }
+
+    // the axis a ball rolling along +z turns about
+    fn topspin_axis() -> Vec3 {
+        Vec3::Y.cross(Vec3::Z)
+    }
+
+    #[test]
+    fn a_centre_hit_goes_at_the_power_with_no_spin() {
+        let centre = hit(Vec2::ZERO);
+        assert!((centre.linvel - Vec3::Z * 2.0).length() < 1e-4, "{}", centre.linvel);
+        assert!(centre.angvel.length() < 1e-4, "{}", centre.angvel);
+    }
+
+    #[test]
+    fn high_and_low_tips_spin_about_the_rolling_axis() {
+        let follow = hit(Vec2::new(0.0, 0.4)).angvel;
+        let draw = hit(Vec2::new(0.0, -0.4)).angvel;
+        assert!(follow.dot(topspin_axis()) > 0.0, "follow {}", follow);
+        assert!(draw.dot(topspin_axis()) < 0.0, "draw {}", draw);
+        for spin in [follow, draw] {
+            assert!(spin.normalize().cross(topspin_axis()).length() < 1e-4, "off axis: {}", spin);
+        }
+    }
+
+    #[test]
+    fn side_english_spins_about_the_vertical() {
+        let right = hit(Vec2::new(0.4, 0.0)).angvel;
+        let left = hit(Vec2::new(-0.4, 0.0)).angvel;
+        assert!(right.y > 0.0 && left.y < 0.0);
+        for spin in [right, left] {
+            assert!(spin.x.abs() < 1e-4 && spin.z.abs() < 1e-4, "off axis: {}", spin);
+        }
+    }
+
+    #[test]
+    fn squirt_pushes_the_ball_away_from_the_english() {
+        let linvel = hit(Vec2::new(0.3, 0.0)).linvel;
+        let shooters_right = Vec3::Z.cross(Vec3::Y);
+        assert!(linvel.dot(shooters_right) < 0.0, "right English went right: {}", linvel);
+        assert!((linvel.angle_between(Vec3::Z) - squirt_angle(0.3)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn a_tip_past_the_miscue_limit_is_pulled_back_to_it() {
+        assert_eq!(hit(Vec2::new(2.0, 0.0)), hit(Vec2::new(MISCUE_LIMIT, 0.0)));
+        assert_eq!(hit(Vec2::new(0.0, -5.0)), hit(Vec2::new(0.0, -MISCUE_LIMIT)));
+    }
+
+    #[test]
+    fn shots_are_flattened_and_bounded() {
+        let tip = CueTip { offset: Vec2::new(3.0, 0.0), elevation: 120.0 };
+        let (power, aim, tip) = sanitize_shot(1e9, Vec3::new(3.0, 4.0, 4.0), tip).unwrap();
+        assert_eq!(power, MAX_SHOT_POWER);
+        assert!((aim - Vec3::new(0.6, 0.0, 0.8)).length() < 1e-6);
+        assert_eq!(tip, CueTip { offset: Vec2::new(MISCUE_LIMIT, 0.0), elevation: MAX_CUE_ELEVATION });
+    }
+
+    #[test]
+    fn broken_shots_are_refused() {
+        let tip = CueTip::center();
+        assert!(sanitize_shot(f32::NAN, Vec3::Z, tip).is_err());
+        assert!(sanitize_shot(f32::INFINITY, Vec3::Z, tip).is_err());
+        assert!(sanitize_shot(2.0, Vec3::new(f32::NAN, 0.0, 1.0), tip).is_err());
+        assert!(sanitize_shot(2.0, Vec3::Z, CueTip { offset: Vec2::new(f32::INFINITY, 0.0), elevation: 0.0 }).is_err());
+        assert!(sanitize_shot(2.0, Vec3::Y, tip).is_err(), "straight down");
+        assert!(sanitize_shot(-2.0, Vec3::Z, tip).is_err());
+        assert!(sanitize_shot(0.0, Vec3::Z, tip).is_err());
+    }
+}
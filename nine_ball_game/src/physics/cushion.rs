@@ -0,0 +1,140 @@
+// src/physics/cushion.rs
+// Ball against cushion, after Han (2005). The nose of the cushion sits
+// above the ball's centre, so the ball is struck at an angle θ above its
+// equator with sin θ = h/R - 1. The normal impulse (1 + e) m c, with c the
+// approach speed along the contact normal, does not spin the ball; friction
+// at the nose does, and either stops the slip there or slides at μ times
+// the normal impulse. That friction is what makes running English open up
+// the rebound angle and reverse English shorten it.
+use bevy::prelude::*;
+
+/// Slower approaches than this (m/s) are left to the physics engine.
+const MIN_APPROACH_SPEED: f32 = 1e-3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CushionModel {
+    /// Height of the cushion nose above the bed (m)
+    pub nose_height: f32,
+    /// Coefficient of restitution for a very slow ball
+    pub restitution: f32,
+    /// How much restitution drops per m/s of approach speed
+    pub restitution_falloff: f32,
+}
+
+impl CushionModel {
+    /// Angle of the contact point above the ball's equator.
+    pub fn contact_angle(&self, radius: f32) -> f32 {
+        (self.nose_height / radius - 1.0).clamp(-1.0, 1.0).asin()
+    }
+
+    /// Cushions give back less of a hard hit than a soft one.
+    pub fn restitution_at(&self, approach_speed: f32) -> f32 {
+        (self.restitution - self.restitution_falloff * approach_speed).clamp(0.0, 1.0)
+    }
+
+    /// Ball–cushion friction, falling off with the angle of incidence
+    /// (Mathavan et al. 2010). `incidence` is 0 for a ball hitting square on.
+    pub fn friction_at(incidence: f32) -> f32 {
+        (0.471 - 0.241 * incidence).max(0.0)
+    }
+
+    /// Velocities just after the ball hits the cushion. `into_cushion` is the
+    /// horizontal unit vector from the ball towards the cushion. Returns
+    /// `None` if the ball is not moving into the cushion.
+    pub fn rebound(&self, linvel: Vec3, angvel: Vec3, into_cushion: Vec3, radius: f32, mass: f32) -> Option<(Vec3, Vec3)> {
+        let x = Vec3::new(into_cushion.x, 0.0, into_cushion.z).normalize_or_zero();
+        let horizontal = Vec3::new(linvel.x, 0.0, linvel.z);
+        let approach = horizontal.dot(x);
+        if x == Vec3::ZERO || approach < MIN_APPROACH_SPEED {
+            return None;
+        }
+
+        let theta = self.contact_angle(radius);
+        // contact point, relative to the centre, and the normal it pushes along
+        let r = (x * theta.cos() + Vec3::Y * theta.sin()) * radius;
+        let normal = -r / radius;
+
+        let c = approach * theta.cos();
+        let e = self.restitution_at(c);
+        let normal_impulse = (1.0 + e) * mass * c;
+
+        // slip of the contact point across the cushion face
+        let slip = linvel + angvel.cross(r);
+        let slip = slip - normal * slip.dot(normal);
+        let slip_speed = slip.length();
+
+        // a sphere resists sideways slip at its contact with 2/7 of its mass
+        let stick_impulse = 2.0 / 7.0 * mass * slip_speed;
+        let incidence = (approach / horizontal.length()).clamp(-1.0, 1.0).acos();
+        let max_friction = Self::friction_at(incidence) * normal_impulse;
+        let friction_impulse = if slip_speed <= f32::EPSILON {
+            Vec3::ZERO
+        } else if stick_impulse <= max_friction {
+            -slip / slip_speed * stick_impulse
+        } else {
+            -slip / slip_speed * max_friction
+        };
+
+        let impulse = normal * normal_impulse + friction_impulse;
+        let inertia = 0.4 * mass * radius * radius;
+
+        // the bed takes up anything pushing the ball down, so the rebound stays on the table
+        let mut new_linvel = linvel + impulse / mass;
+        new_linvel.y = linvel.y;
+        let new_angvel = angvel + r.cross(impulse) / inertia;
+        Some((new_linvel, new_angvel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::{BALL_MASS, CUSHION_NOSE_HEIGHT, CUSHION_RESTITUTION_FALLOFF, STANDARD_BALL_RADIUS};
+
+    const R: f32 = STANDARD_BALL_RADIUS;
+
+    fn cushion() -> CushionModel {
+        CushionModel { nose_height: CUSHION_NOSE_HEIGHT, restitution: 0.9, restitution_falloff: CUSHION_RESTITUTION_FALLOFF }
+    }
+
+    // angle off the cushion's normal the ball leaves at, for a cushion along +x
+    fn rebound_angle(linvel: Vec3) -> f32 {
+        linvel.z.abs().atan2(-linvel.x)
+    }
+
+    #[test]
+    fn a_square_hit_with_no_spin_comes_straight_back() {
+        let (linvel, _) = cushion().rebound(Vec3::new(2.0, 0.0, 0.0), Vec3::ZERO, Vec3::X, R, BALL_MASS).unwrap();
+        assert!(linvel.x < 0.0);
+        assert!(linvel.y.abs() < 1e-6 && linvel.z.abs() < 1e-6, "went off line: {}", linvel);
+    }
+
+    #[test]
+    fn running_english_opens_the_angle_and_reverse_shortens_it() {
+        // 45° into the cushion along +z; spin about +Y drags the contact
+        // point back along -z, so the cushion throws the ball on along +z
+        let approach = Vec3::new(1.5, 0.0, 1.5);
+        let spin = 1.5 / R * Vec3::Y;
+        let angle_with = |angvel: Vec3| rebound_angle(cushion().rebound(approach, angvel, Vec3::X, R, BALL_MASS).unwrap().0);
+
+        let plain = angle_with(Vec3::ZERO);
+        let running = angle_with(spin);
+        let reverse = angle_with(-spin);
+        assert!(running > plain, "running {} vs plain {}", running, plain);
+        assert!(reverse < plain, "reverse {} vs plain {}", reverse, plain);
+    }
+
+    #[test]
+    fn a_harder_hit_keeps_less_of_its_speed() {
+        let model = cushion();
+        let kept = |speed: f32| -model.rebound(Vec3::new(speed, 0.0, 0.0), Vec3::ZERO, Vec3::X, R, BALL_MASS).unwrap().0.x / speed;
+
+        assert!(model.restitution_at(3.0) < model.restitution_at(0.5));
+        assert!(kept(3.0) < kept(0.5), "hard {} vs soft {}", kept(3.0), kept(0.5));
+    }
+
+    #[test]
+    fn a_ball_leaving_the_cushion_is_not_rebounded() {
+        assert!(cushion().rebound(Vec3::new(-1.0, 0.0, 1.0), Vec3::ZERO, Vec3::X, R, BALL_MASS).is_none());
+    }
+}
@@ -0,0 +1,7 @@
+// src/physics/mod.rs
+// Ball physics that Rapier does not model on its own. These are plain
+// functions so the server systems and the client preview share one answer.
+pub mod cloth;
+pub mod cue;
+pub mod cushion;
+pub mod throw;
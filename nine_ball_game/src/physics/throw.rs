@@ -0,0 +1,127 @@
+// src/physics/throw.rs
+// Ball against ball, with friction at the contact (Alciatore, "Throw").
+// The normal impulse is the usual equal-mass collision along the line of
+// centres. On top of it, the contact points rub past each other - because
+// of the cut angle and because of any spin - and friction between the
+// balls pushes the object ball off the line of centres (throw) and hands
+// some spin across (gearing, spin transfer). Ball-ball friction is highest
+// for slow slip, which is why soft cut shots throw the most.
+use bevy::prelude::*;
+
+use super::super::BALL_BALL_RESTITUTION;
+
+// μ(v) = a + b e^(-c v), fitted to measured ball-ball friction
+const FRICTION_A: f32 = 9.951e-3;
+const FRICTION_B: f32 = 0.108;
+const FRICTION_C: f32 = 1.088;
+
+/// Ball-ball friction for contact points slipping past each other at `slip_speed` (m/s).
+pub fn friction_at(slip_speed: f32) -> f32 {
+    FRICTION_A + FRICTION_B * (-FRICTION_C * slip_speed).exp()
+}
+
+/// Velocities of two equal balls just after they collide. `line_of_centres`
+/// points from the first ball to the second. Returns `None` if the balls
+/// are not moving towards each other.
+pub fn collide(
+    first: (Vec3, Vec3),
+    second: (Vec3, Vec3),
+    line_of_centres: Vec3,
+    radius: f32,
+    mass: f32,
+) -> Option<((Vec3, Vec3), (Vec3, Vec3))> {
+    let n = Vec3::new(line_of_centres.x, 0.0, line_of_centres.z).normalize_or_zero();
+    let (v1, w1) = first;
+    let (v2, w2) = second;
+    let approach = (v1 - v2).dot(n);
+    if n == Vec3::ZERO || approach <= 0.0 {
+        return None;
+    }
+
+    let normal_impulse = 0.5 * mass * (1.0 + BALL_BALL_RESTITUTION) * approach;
+
+    // contact points rubbing past each other, across the line of centres
+    let r = n * radius;
+    let slip = (v1 + w1.cross(r)) - (v2 + w2.cross(-r));
+    let slip = slip - n * slip.dot(n);
+    let slip_speed = slip.length();
+
+    // each ball resists slip at its contact point with 2/7 of its mass
+    let stick_impulse = mass * slip_speed / 7.0;
+    let friction = if slip_speed <= f32::EPSILON {
+        Vec3::ZERO
+    } else {
+        -slip / slip_speed * stick_impulse.min(friction_at(slip_speed) * normal_impulse)
+    };
+
+    let inertia = 0.4 * mass * radius * radius;
+    // friction on the first ball is `friction`, on the second `-friction`;
+    // both act at the contact point, so both balls pick up the same spin
+    let spin = r.cross(friction) / inertia;
+    // the bed takes up any vertical part of the impulse
+    let push = Vec3::new(friction.x, 0.0, friction.z) / mass;
+    let normal = n * (normal_impulse / mass);
+
+    Some(((v1 - normal + push, w1 + spin), (v2 + normal - push, w2 + spin)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::{BALL_MASS, STANDARD_BALL_RADIUS};
+
+    const R: f32 = STANDARD_BALL_RADIUS;
+
+    // a half-ball hit on a ball sitting to the right of the cue ball's path along +z
+    fn half_ball() -> Vec3 {
+        Vec3::new(30f32.to_radians().sin(), 0.0, 30f32.to_radians().cos())
+    }
+
+    // object ball velocity after a cue ball moving along +z at `speed` hits it
+    fn object_ball(speed: f32, spin: Vec3, line_of_centres: Vec3) -> Vec3 {
+        let (_, (object, _)) = collide((Vec3::Z * speed, spin), (Vec3::ZERO, Vec3::ZERO), line_of_centres, R, BALL_MASS).unwrap();
+        object
+    }
+
+    // how far the object ball leaves off the line of centres, positive
+    // towards the cue ball's path
+    fn throw(object: Vec3, line_of_centres: Vec3) -> f32 {
+        let across = object.cross(line_of_centres).y;
+        across.atan2(object.dot(line_of_centres))
+    }
+
+    #[test]
+    fn a_straight_in_hit_with_no_spin_has_no_throw() {
+        let object = object_ball(2.0, Vec3::ZERO, Vec3::Z);
+        assert!(object.z > 0.0);
+        assert!(object.x.abs() < 1e-6, "thrown by {}", object.x);
+    }
+
+    #[test]
+    fn a_cut_throws_the_object_ball_towards_the_cue_balls_path() {
+        let n = half_ball();
+        let object = object_ball(1.0, Vec3::ZERO, n);
+        let thrown = throw(object, n);
+        assert!(thrown > 0.01, "throw {}", thrown);
+        // so the object ball is cut less than the 30° the line of centres says
+        assert!(object.x.atan2(object.z) < 30f32.to_radians());
+    }
+
+    #[test]
+    fn outside_english_takes_off_cut_throw() {
+        let n = half_ball();
+        // spin about +Y turns the contact point along the cue ball's slip on the object ball
+        let outside = 0.25 / R * Vec3::Y;
+        let plain = throw(object_ball(1.0, Vec3::ZERO, n), n);
+        let with_english = throw(object_ball(1.0, outside, n), n);
+        assert!(with_english.abs() < plain.abs(), "outside {} vs plain {}", with_english, plain);
+    }
+
+    #[test]
+    fn a_harder_cut_throws_less() {
+        let n = half_ball();
+        let soft = throw(object_ball(0.5, Vec3::ZERO, n), n);
+        let hard = throw(object_ball(3.0, Vec3::ZERO, n), n);
+        assert!(hard.abs() < soft.abs(), "hard {} vs soft {}", hard, soft);
+    }
+}
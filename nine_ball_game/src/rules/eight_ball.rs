@@ -0,0 +1,391 @@
+// src/rules/eight_ball.rs
+// WPA eight-ball. The table is open after the break; the first player to
+// pocket a ball legally takes that group, solids (1-7) or stripes (9-15),
+// and must then hit one of their own balls first. Once the group is
+// cleared they call a pocket and shoot the 8. Pocketing the 8 early, on a
+// foul, in another pocket or off the table loses. Fouls give ball in hand.
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use super::super::{GameState, Pocket, TableSpec, WhoseMove};
+use super::{rack, Declaration, Foul, GameResult, Ruleset, ShotLog, ShotOutcome, WinReason, CUE_BALL};
+use super::nine_ball::BREAK_BALLS_TO_RAIL;
+
+pub const EIGHT_BALL: u32 = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BallGroup {
+    Solids,
+    Stripes,
+}
+
+impl BallGroup {
+    pub fn of(ball: u32) -> Option<BallGroup> {
+        match ball {
+            1..=7 => Some(BallGroup::Solids),
+            9..=15 => Some(BallGroup::Stripes),
+            _ => None,
+        }
+    }
+
+    pub fn other(&self) -> BallGroup {
+        match self {
+            BallGroup::Solids => BallGroup::Stripes,
+            BallGroup::Stripes => BallGroup::Solids,
+        }
+    }
+
+    pub fn balls(&self) -> RangeInclusive<u32> {
+        match self {
+            BallGroup::Solids => 1..=7,
+            BallGroup::Stripes => 9..=15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EightBallRules {
+    pub shooter: WhoseMove,
+    /// Object balls still on the table
+    pub on_table: Vec<u32>,
+    /// Each player's group; both `None` while the table is open
+    pub groups: [Option<BallGroup>; 2],
+    pub breaking: bool,
+    /// Where the shooter says the 8 will go
+    pub called_pocket: Option<Pocket>,
+    /// Ball in hand after a foul on the break goes behind the head string
+    pub kitchen: bool,
+}
+
+impl EightBallRules {
+    pub fn new(breaker: WhoseMove) -> Self {
+        EightBallRules {
+            shooter: breaker,
+            on_table: (1..=15).collect(),
+            groups: [None; 2],
+            breaking: true,
+            called_pocket: None,
+            kitchen: false,
+        }
+    }
+
+    pub fn is_open_table(&self) -> bool {
+        self.groups[0].is_none()
+    }
+
+    /// Whether `player` has cleared their group and is shooting the 8.
+    pub fn on_the_eight(&self, player: &WhoseMove) -> bool {
+        match self.groups[player.index()] {
+            Some(group) => !self.on_table.iter().any(|ball| group.balls().contains(ball)),
+            None => false,
+        }
+    }
+
+    /// Balls `player` may hit first.
+    pub fn legal_targets(&self, player: &WhoseMove) -> Vec<u32> {
+        if self.on_the_eight(player) {
+            return vec![EIGHT_BALL];
+        }
+        match self.groups[player.index()] {
+            Some(group) => self.on_table.iter().copied().filter(|ball| group.balls().contains(ball)).collect(),
+            // anything but the 8 on an open table
+            None => self.on_table.iter().copied().filter(|&ball| ball != EIGHT_BALL).collect(),
+        }
+    }
+
+    /// Name the pocket for the 8.
+    pub fn call_pocket(&mut self, pocket: Pocket) -> Result<(), String> {
+        if !self.on_the_eight(&self.shooter) {
+            return Err("a pocket is only called when shooting the 8".to_string());
+        }
+        self.called_pocket = Some(pocket);
+        Ok(())
+    }
+
+    pub fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome {
+        let shooter = self.shooter.clone();
+        let breaking = self.breaking;
+        let on_the_eight = self.on_the_eight(&shooter);
+        let called_pocket = self.called_pocket.take();
+        let mut fouls = Vec::new();
+
+        match log.first_contact() {
+            None if log.timed_out() => fouls.push(Foul::ShotClockExpired),
+            None => fouls.push(Foul::NoBallHit),
+            // the break may hit anything
+            Some(hit) if !breaking && !self.legal_targets(&shooter).contains(&hit) => {
+                fouls.push(Foul::IllegalFirstContact { hit })
+            }
+            Some(_) => {}
+        }
+        if log.first_contact().is_some() {
+            let balls_to_rail = log.object_balls_to_rail().len() as u32;
+            if breaking {
+                if log.object_balls_pocketed().is_empty() && balls_to_rail < BREAK_BALLS_TO_RAIL {
+                    fouls.push(Foul::IllegalBreak { balls_to_rail });
+                }
+            } else if !log.rail_after_contact() {
+                fouls.push(Foul::NoRailAfterContact);
+            }
+        }
+        if log.cue_ball_pocketed() {
+            fouls.push(Foul::CueBallPocketed);
+        }
+        let jumped = log.off_table();
+        for &ball in &jumped {
+            if ball == CUE_BALL {
+                fouls.push(Foul::CueBallOffTable);
+            } else {
+                fouls.push(Foul::BallOffTable { ball });
+            }
+        }
+
+        let pocketed = log.object_balls_pocketed();
+        self.on_table.retain(|ball| !pocketed.contains(ball) && !jumped.contains(ball));
+        let foul = !fouls.is_empty();
+
+        let eight_pocket = log.pocketed().iter().find(|p| p.number == EIGHT_BALL).map(|p| p.pocket);
+        let eight_jumped = jumped.contains(&EIGHT_BALL);
+        let mut respotted = Vec::new();
+        let mut result = None;
+        if breaking && (eight_pocket.is_some() || eight_jumped) {
+            // not a loss on the break, the 8 goes back on the foot spot
+            respotted.push(EIGHT_BALL);
+            self.on_table.push(EIGHT_BALL);
+        } else if eight_pocket.is_some() || eight_jumped {
+            let lost = |reason| Some(GameResult { winner: shooter.other(), reason });
+            result = if !on_the_eight {
+                lost(WinReason::EarlyEightBall)
+            } else if eight_jumped {
+                lost(WinReason::EightBallOffTable)
+            } else if foul {
+                lost(WinReason::EightBallOnFoul)
+            } else if eight_pocket != called_pocket {
+                lost(WinReason::EightBallWrongPocket)
+            } else {
+                Some(GameResult { winner: shooter.clone(), reason: WinReason::EightBallPocketed })
+            };
+        }
+
+        // the first ball pocketed legally after the break picks the groups
+        if !breaking && !foul && self.is_open_table() {
+            if let Some(group) = pocketed.iter().find_map(|&ball| BallGroup::of(ball)) {
+                self.groups[shooter.index()] = Some(group);
+                self.groups[shooter.other().index()] = Some(group.other());
+            }
+        }
+
+        let own_ball_down = match self.groups[shooter.index()] {
+            Some(group) => pocketed.iter().any(|ball| group.balls().contains(ball)),
+            None => breaking && !pocketed.is_empty(),
+        };
+        let keeps_table = !foul && result.is_none() && own_ball_down;
+        let next_shooter = if keeps_table { shooter.clone() } else { shooter.other() };
+
+        self.kitchen = breaking && foul;
+        self.breaking = false;
+        self.shooter = next_shooter.clone();
+
+        ShotOutcome {
+            shooter,
+            fouls,
+            pocketed: log.pocketed(),
+            respotted,
+            rerack: Vec::new(),
+            points: 0,
+            push_out: false,
+            next_shooter,
+            ball_in_hand: foul && result.is_none(),
+            result,
+        }
+    }
+}
+
+impl Ruleset for EightBallRules {
+    fn shooter(&self) -> WhoseMove {
+        self.shooter.clone()
+    }
+
+    // apex on the foot spot, the 8 in the middle of the third row and one
+    // ball from each group in the back corners
+    fn rack(&self, spec: &TableSpec, rng: &mut dyn RngCore) -> Vec<(u32, Vec3)> {
+        let positions = rack::triangle(spec.foot_spot(), 5);
+        let mut solids: Vec<u32> = BallGroup::Solids.balls().collect();
+        let mut stripes: Vec<u32> = BallGroup::Stripes.balls().collect();
+        let (solid, stripe) = (solids.remove(rng.random_range(0..solids.len())), stripes.remove(rng.random_range(0..stripes.len())));
+        let corners = if rng.random::<bool>() { [(solid, 10), (stripe, 14)] } else { [(stripe, 10), (solid, 14)] };
+        let fixed = [(EIGHT_BALL, 4), corners[0], corners[1]];
+        let others: Vec<u32> = solids.into_iter().chain(stripes).collect();
+        rack::fill(&positions, &fixed, &others, rng)
+    }
+
+    fn new_rack(&mut self, breaker: WhoseMove) {
+        *self = EightBallRules::new(breaker);
+    }
+
+    fn declare(&mut self, declaration: Declaration) -> Result<(), String> {
+        match declaration {
+            Declaration::CallPocket(pocket) | Declaration::CallShot { ball: EIGHT_BALL, pocket } => self.call_pocket(pocket),
+            Declaration::CallShot { .. } => Err("only the 8 is called in eight-ball".to_string()),
+            Declaration::PushOut | Declaration::Pass => Err("there is no push out in eight-ball".to_string()),
+        }
+    }
+
+    fn in_hand_behind_head_string(&self) -> bool {
+        self.kitchen
+    }
+
+    fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome {
+        EightBallRules::evaluate(self, log)
+    }
+
+    fn report(&self, state: &mut GameState) {
+        state.groups = self.groups;
+        state.called_pocket = self.called_pocket;
+        state.in_hand_in_kitchen = self.kitchen;
+        state.breaking = self.breaking;
+    }
+
+    fn restore(&mut self, state: &GameState, on_table: &[u32]) {
+        self.shooter = state.whose_move.clone();
+        self.on_table = on_table.to_vec();
+        self.groups = state.groups;
+        self.called_pocket = state.called_pocket;
+        self.kitchen = state.in_hand_in_kitchen;
+        self.breaking = state.breaking;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_support::{case, contact, jump, pot, rail, round_trip};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn breaking() -> EightBallRules {
+        EightBallRules::new(WhoseMove::Player1)
+    }
+
+    fn open_table() -> EightBallRules {
+        EightBallRules { breaking: false, ..breaking() }
+    }
+
+    // player 1 has solids
+    fn on_solids() -> EightBallRules {
+        EightBallRules { groups: [Some(BallGroup::Solids), Some(BallGroup::Stripes)], ..open_table() }
+    }
+
+    // player 1 has cleared the solids and called the foot left for the 8
+    fn on_the_eight() -> EightBallRules {
+        let mut rules = EightBallRules { on_table: (8..=15).collect(), ..on_solids() };
+        rules.call_pocket(Pocket::FootLeft).unwrap();
+        rules
+    }
+
+    #[test]
+    fn rules_table() {
+        use Foul::*;
+        use Pocket::*;
+        use WhoseMove::*;
+        let cases = vec![
+            case("break pocketing a ball keeps the table", breaking, vec![contact(0, 3), pot(11, FootLeft)]).keeps_table(),
+            case("break with four balls to a rail", breaking, vec![contact(0, 3), rail(1), rail(2), rail(9), rail(10)]),
+            case("break with three balls to a rail", breaking, vec![contact(0, 3), rail(1), rail(2), rail(9)])
+                .fouls(vec![IllegalBreak { balls_to_rail: 3 }]),
+            case("8 on the break is spotted", breaking, vec![contact(0, 3), pot(8, SideLeft)]).respots(vec![8]).keeps_table(),
+            case("scratch on the break", breaking, vec![contact(0, 3), pot(5, FootLeft), pot(0, HeadLeft)]).fouls(vec![CueBallPocketed]),
+            case("open table takes either group", open_table, vec![contact(0, 12), pot(12, FootRight)]).keeps_table(),
+            case("open table may not hit the 8 first", open_table, vec![contact(0, 8), rail(8)])
+                .fouls(vec![IllegalFirstContact { hit: 8 }]),
+            case("own group first", on_solids, vec![contact(0, 3), rail(3)]),
+            case("opponent's group first", on_solids, vec![contact(0, 12), rail(12)]).fouls(vec![IllegalFirstContact { hit: 12 }]),
+            case("pocketing only the opponent's ball loses the table", on_solids, vec![contact(0, 3), contact(3, 12), pot(12, SideLeft)]),
+            case("pocketing an own ball keeps the table", on_solids, vec![contact(0, 3), pot(3, SideLeft)]).keeps_table(),
+            case("no rail after contact", on_solids, vec![contact(0, 3)]).fouls(vec![NoRailAfterContact]),
+            case("no ball hit", on_solids, vec![rail(0)]).fouls(vec![NoBallHit]),
+            case("object ball jumped", on_solids, vec![contact(0, 3), rail(3), jump(12)]).fouls(vec![BallOffTable { ball: 12 }]),
+            case("early 8 loses", on_solids, vec![contact(0, 3), contact(3, 8), pot(8, FootLeft)]).ends(Player2, WinReason::EarlyEightBall),
+            case("8 in the called pocket wins", on_the_eight, vec![contact(0, 8), pot(8, FootLeft)]).ends(Player1, WinReason::EightBallPocketed),
+            case("8 in another pocket loses", on_the_eight, vec![contact(0, 8), pot(8, FootRight)])
+                .ends(Player2, WinReason::EightBallWrongPocket),
+            case("8 with a scratch loses", on_the_eight, vec![contact(0, 8), pot(8, FootLeft), pot(0, SideRight)])
+                .fouls(vec![CueBallPocketed])
+                .ends(Player2, WinReason::EightBallOnFoul),
+            case("8 off the table loses", on_the_eight, vec![contact(0, 8), rail(8), jump(8)])
+                .fouls(vec![BallOffTable { ball: 8 }])
+                .ends(Player2, WinReason::EightBallOffTable),
+            case("on the 8, hitting a stripe first", on_the_eight, vec![contact(0, 12), rail(12)])
+                .fouls(vec![IllegalFirstContact { hit: 12 }]),
+            case("on the 8, scratch without pocketing it", on_the_eight, vec![contact(0, 8), rail(8), pot(0, HeadLeft)])
+                .fouls(vec![CueBallPocketed]),
+        ];
+
+        for case in cases {
+            case.check();
+        }
+    }
+
+    #[test]
+    fn groups_go_to_the_first_legal_pocket_after_the_break() {
+        let mut rules = breaking();
+        rules.evaluate(&ShotLog { events: vec![contact(0, 1), pot(2, Pocket::FootLeft), pot(10, Pocket::SideLeft)] });
+        assert!(rules.is_open_table(), "balls on the break do not pick groups");
+
+        rules.evaluate(&ShotLog { events: vec![contact(0, 12), contact(12, 4), pot(4, Pocket::SideRight)] });
+        assert_eq!(rules.groups, [Some(BallGroup::Solids), Some(BallGroup::Stripes)]);
+
+        let mut rules = open_table();
+        rules.evaluate(&ShotLog { events: vec![contact(0, 4), pot(4, Pocket::SideRight), pot(0, Pocket::HeadLeft)] });
+        assert!(rules.is_open_table(), "a foul does not pick groups");
+    }
+
+    #[test]
+    fn foul_on_the_break_puts_ball_in_hand_in_the_kitchen() {
+        let mut rules = breaking();
+        let outcome = rules.evaluate(&ShotLog { events: vec![contact(0, 1), pot(3, Pocket::FootLeft), pot(0, Pocket::SideLeft)] });
+        assert!(outcome.ball_in_hand);
+        assert!(rules.in_hand_behind_head_string());
+
+        let outcome = rules.evaluate(&ShotLog { events: vec![contact(0, 1), pot(0, Pocket::SideLeft)] });
+        assert!(outcome.ball_in_hand);
+        assert!(!rules.in_hand_behind_head_string(), "only after the break");
+    }
+
+    #[test]
+    fn the_kitchen_after_a_foul_on_the_break_is_picked_up_from_the_game_state() {
+        let mut rules = breaking();
+        rules.evaluate(&ShotLog { events: vec![contact(0, 1), pot(3, Pocket::FootLeft), pot(0, Pocket::SideLeft)] });
+        let restored = round_trip(&rules, EightBallRules::new(WhoseMove::Player1), &rules.on_table);
+        assert!(restored.in_hand_behind_head_string());
+        assert_eq!(restored, rules);
+    }
+
+    #[test]
+    fn pocket_is_only_called_on_the_eight() {
+        let mut rules = on_solids();
+        assert!(rules.call_pocket(Pocket::FootLeft).is_err());
+        rules.on_table.retain(|&ball| ball >= 8);
+        assert!(rules.call_pocket(Pocket::FootLeft).is_ok());
+        // the call is for one shot
+        rules.evaluate(&ShotLog { events: vec![contact(0, 8), rail(8)] });
+        assert_eq!(rules.called_pocket, None);
+    }
+
+    #[test]
+    fn rack_has_eight_in_the_middle_and_both_groups_in_the_corners() {
+        let spec = TableSpec::default();
+        let rack = breaking().rack(&spec, &mut StdRng::seed_from_u64(8));
+        let mut numbers: Vec<u32> = rack.iter().map(|(n, _)| *n).collect();
+        numbers.sort();
+        assert_eq!(numbers, (1..=15).collect::<Vec<_>>());
+
+        let positions = rack::triangle(spec.foot_spot(), 5);
+        let at = |i: usize| rack.iter().find(|(_, p)| p.distance(positions[i]) < 1e-3).unwrap().0;
+        assert_eq!(at(4), EIGHT_BALL);
+        assert_ne!(BallGroup::of(at(10)), BallGroup::of(at(14)));
+    }
+}
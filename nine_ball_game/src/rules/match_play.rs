@@ -0,0 +1,135 @@
+// src/rules/match_play.rs
+// The match above the rack: racks won per player, who breaks the next rack
+// and when the race is over. The rulesets only ever see one rack.
+use std::str::FromStr;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::super::WhoseMove;
+
+/// Who breaks after the first rack.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BreakRule {
+    #[default]
+    Alternate,
+    WinnerBreaks,
+}
+
+impl FromStr for BreakRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alternate" => Ok(BreakRule::Alternate),
+            "winner" => Ok(BreakRule::WinnerBreaks),
+            _ => Err(format!("unknown break rule {}: expected alternate or winner", s)),
+        }
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MatchScore {
+    /// Racks needed to win the match
+    pub race_to: u32,
+    pub break_rule: BreakRule,
+    pub racks_won: [u32; 2],
+    /// Breaker of the rack being played
+    pub breaker: WhoseMove,
+    /// Set when a player gives up the match, e.g. by not coming back
+    pub forfeited_by: Option<WhoseMove>,
+    /// Nobody played for too long: the match is over with no winner
+    pub abandoned: bool,
+}
+
+impl MatchScore {
+    pub fn new(race_to: u32, break_rule: BreakRule, first_breaker: WhoseMove) -> Self {
+        MatchScore { race_to: race_to.max(1), break_rule, racks_won: [0; 2], breaker: first_breaker, forfeited_by: None, abandoned: false }
+    }
+
+    pub fn winner(&self) -> Option<WhoseMove> {
+        if let Some(loser) = &self.forfeited_by {
+            return Some(loser.other());
+        }
+        [WhoseMove::Player1, WhoseMove::Player2].into_iter().find(|player| self.racks_won[player.index()] >= self.race_to)
+    }
+
+    /// Score a rack. Returns the match winner once the race is over, and
+    /// otherwise moves the break on to whoever breaks the next rack.
+    pub fn record_rack(&mut self, rack_winner: &WhoseMove) -> Option<WhoseMove> {
+        self.racks_won[rack_winner.index()] += 1;
+        if let Some(winner) = self.winner() {
+            return Some(winner);
+        }
+        self.breaker = match self.break_rule {
+            BreakRule::Alternate => self.breaker.other(),
+            BreakRule::WinnerBreaks => rack_winner.clone(),
+        };
+        None
+    }
+
+    /// End the match in the opponent's favour, whatever the racks say.
+    /// Returns the winner.
+    pub fn forfeit(&mut self, loser: &WhoseMove) -> WhoseMove {
+        if self.winner().is_none() {
+            self.forfeited_by = Some(loser.clone());
+        }
+        self.winner().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternating_breaks() {
+        let mut score = MatchScore::new(3, BreakRule::Alternate, WhoseMove::Player1);
+        assert_eq!(score.record_rack(&WhoseMove::Player1), None);
+        assert_eq!(score.breaker, WhoseMove::Player2);
+        assert_eq!(score.record_rack(&WhoseMove::Player1), None);
+        assert_eq!(score.breaker, WhoseMove::Player1);
+        assert_eq!(score.racks_won, [2, 0]);
+    }
+
+    #[test]
+    fn winner_breaks() {
+        let mut score = MatchScore::new(3, BreakRule::WinnerBreaks, WhoseMove::Player1);
+        score.record_rack(&WhoseMove::Player2);
+        assert_eq!(score.breaker, WhoseMove::Player2);
+        score.record_rack(&WhoseMove::Player2);
+        assert_eq!(score.breaker, WhoseMove::Player2);
+    }
+
+    #[test]
+    fn match_ends_at_the_race() {
+        let mut score = MatchScore::new(2, BreakRule::Alternate, WhoseMove::Player1);
+        assert_eq!(score.record_rack(&WhoseMove::Player2), None);
+        assert_eq!(score.record_rack(&WhoseMove::Player1), None);
+        assert_eq!(score.record_rack(&WhoseMove::Player2), Some(WhoseMove::Player2));
+        assert_eq!(score.racks_won, [1, 2]);
+    }
+
+    #[test]
+    fn single_rack_by_default() {
+        let mut score = MatchScore::new(0, BreakRule::default(), WhoseMove::Player1);
+        assert_eq!(score.record_rack(&WhoseMove::Player1), Some(WhoseMove::Player1));
+    }
+
+    #[test]
+    fn forfeit_ends_the_match() {
+        let mut score = MatchScore::new(3, BreakRule::Alternate, WhoseMove::Player1);
+        score.record_rack(&WhoseMove::Player1);
+        assert_eq!(score.forfeit(&WhoseMove::Player1), WhoseMove::Player2);
+        assert_eq!(score.winner(), Some(WhoseMove::Player2));
+        // a finished match stays finished
+        assert_eq!(score.forfeit(&WhoseMove::Player2), WhoseMove::Player2);
+    }
+
+    #[test]
+    fn parses_break_rule() {
+        assert_eq!("winner".parse(), Ok(BreakRule::WinnerBreaks));
+        assert_eq!("alternate".parse(), Ok(BreakRule::Alternate));
+        assert!("loser".parse::<BreakRule>().is_err());
+    }
+}
@@ -0,0 +1,339 @@
+// src/rules/mod.rs
+// Rule engines. While the balls are moving the server writes down what
+// happens in a ShotLog; once they stop, the ruleset for the game being
+// played reads the log and answers with a ShotOutcome.
+use std::fmt;
+
+use bevy::prelude::*;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use super::{GameState, Pocket, PocketedBall, TableSpec, WhoseMove};
+
+pub mod eight_ball;
+pub mod match_play;
+pub mod nine_ball;
+pub mod one_pocket;
+pub mod rack;
+pub mod straight_pool;
+pub mod ten_ball;
+
+pub const CUE_BALL: u32 = 0;
+
+/// Something that happened during a shot. Balls are identified by number,
+/// with 0 for the cue ball.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShotEvent {
+    BallContact { first: u32, second: u32 },
+    Cushion { ball: u32 },
+    Pocketed { ball: u32, pocket: Pocket },
+    OffTable { ball: u32 },
+    // the shooter let the shot clock run out instead of shooting; the only
+    // event in its log
+    ShotClockExpired,
+}
+
+/// Everything that happened during one shot, in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShotLog {
+    pub events: Vec<ShotEvent>,
+}
+
+impl ShotLog {
+    pub fn push(&mut self, event: ShotEvent) {
+        self.events.push(event);
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Whether the shot was never taken because time ran out.
+    pub fn timed_out(&self) -> bool {
+        self.events.contains(&ShotEvent::ShotClockExpired)
+    }
+
+    // index of the event where the cue ball first touched another ball
+    fn first_contact_index(&self) -> Option<(usize, u32)> {
+        self.events.iter().enumerate().find_map(|(i, event)| match *event {
+            ShotEvent::BallContact { first: CUE_BALL, second } => Some((i, second)),
+            ShotEvent::BallContact { first, second: CUE_BALL } => Some((i, first)),
+            _ => None,
+        })
+    }
+
+    /// The first object ball the cue ball touched.
+    pub fn first_contact(&self) -> Option<u32> {
+        self.first_contact_index().map(|(_, ball)| ball)
+    }
+
+    /// Every ball that dropped, cue ball included, in the order they dropped.
+    pub fn pocketed(&self) -> Vec<PocketedBall> {
+        self.events
+            .iter()
+            .filter_map(|event| match *event {
+                ShotEvent::Pocketed { ball, pocket } => Some(PocketedBall { number: ball, pocket }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Object balls that dropped.
+    pub fn object_balls_pocketed(&self) -> Vec<u32> {
+        self.pocketed().iter().map(|p| p.number).filter(|&n| n != CUE_BALL).collect()
+    }
+
+    pub fn cue_ball_pocketed(&self) -> bool {
+        self.pocketed().iter().any(|p| p.number == CUE_BALL)
+    }
+
+    /// Balls that left the table over a rail, cue ball included.
+    pub fn off_table(&self) -> Vec<u32> {
+        self.events
+            .iter()
+            .filter_map(|event| match *event {
+                ShotEvent::OffTable { ball } => Some(ball),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether a ball was pocketed or any ball touched a cushion after the
+    /// cue ball's first contact.
+    pub fn rail_after_contact(&self) -> bool {
+        let Some((start, _)) = self.first_contact_index() else {
+            return false;
+        };
+        self.events[start..]
+            .iter()
+            .any(|event| matches!(event, ShotEvent::Cushion { .. } | ShotEvent::Pocketed { .. }))
+    }
+
+    /// Distinct object balls that touched a cushion during the shot.
+    pub fn object_balls_to_rail(&self) -> Vec<u32> {
+        let mut balls = Vec::new();
+        for event in &self.events {
+            if let ShotEvent::Cushion { ball } = *event {
+                if ball != CUE_BALL && !balls.contains(&ball) {
+                    balls.push(ball);
+                }
+            }
+        }
+        balls
+    }
+}
+
+/// Something a player says before a shot, where the game lets them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Declaration {
+    PushOut,
+    Pass,
+    CallPocket(Pocket),
+    CallShot { ball: u32, pocket: Pocket },
+}
+
+/// One game's rules, as the server drives them: it racks with `rack`,
+/// passes on the shooter's declarations, and once the balls stop hands
+/// over the shot log to `evaluate` and carries out the outcome.
+pub trait Ruleset: fmt::Debug + Send + Sync {
+    /// Who is at the table.
+    fn shooter(&self) -> WhoseMove;
+
+    /// The ball that has to be hit first, where the rules name one.
+    fn target_ball(&self) -> Option<u32> {
+        None
+    }
+
+    /// Object balls and where they start, as ball centres at rest on the bed,
+    /// shuffled with `rng`.
+    fn rack(&self, spec: &TableSpec, rng: &mut dyn RngCore) -> Vec<(u32, Vec3)>;
+
+    /// Start the next rack of a match, with `breaker` to break.
+    fn new_rack(&mut self, breaker: WhoseMove);
+
+    /// Where `balls` go when they are racked again part way through a game.
+    fn rerack(&self, spec: &TableSpec, balls: &[u32], rng: &mut dyn RngCore) -> Vec<(u32, Vec3)> {
+        self.rack(spec, rng).into_iter().filter(|(ball, _)| balls.contains(ball)).collect()
+    }
+
+    fn declare(&mut self, declaration: Declaration) -> Result<(), String>;
+
+    /// Whether ball in hand is restricted to the kitchen, behind the head
+    /// string, rather than anywhere on the table.
+    fn in_hand_behind_head_string(&self) -> bool {
+        false
+    }
+
+    /// Judge a shot from what happened on the table and move the game on.
+    fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome;
+
+    /// Copy what clients need to show about the game into the game state.
+    fn report(&self, state: &mut GameState);
+
+    /// Take back what `report` wrote, along with the shooter, for a game
+    /// picked up from a snapshot. `on_table` is the object balls still on
+    /// the table.
+    fn restore(&mut self, state: &GameState, on_table: &[u32]);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Foul {
+    WrongBallFirst { expected: u32, hit: u32 },
+    IllegalFirstContact { hit: u32 },
+    NoBallHit,
+    NoRailAfterContact,
+    CueBallPocketed,
+    CueBallOffTable,
+    BallOffTable { ball: u32 },
+    IllegalBreak { balls_to_rail: u32 },
+    ShotClockExpired,
+}
+
+impl fmt::Display for Foul {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Foul::WrongBallFirst { expected, hit } => write!(f, "hit the {} first, needed the {}", hit, expected),
+            Foul::IllegalFirstContact { hit } => write!(f, "hit the {} first, which was not a legal ball to hit", hit),
+            Foul::NoBallHit => write!(f, "cue ball did not hit a ball"),
+            Foul::NoRailAfterContact => write!(f, "no ball pocketed or driven to a rail after contact"),
+            Foul::CueBallPocketed => write!(f, "cue ball pocketed"),
+            Foul::CueBallOffTable => write!(f, "cue ball jumped off the table"),
+            Foul::BallOffTable { ball } => write!(f, "the {} jumped off the table", ball),
+            Foul::IllegalBreak { balls_to_rail } => {
+                write!(f, "illegal break: nothing pocketed and only {} balls to a rail", balls_to_rail)
+            }
+            Foul::ShotClockExpired => write!(f, "ran out of time on the shot clock"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WinReason {
+    NineBallPocketed,
+    ThreeConsecutiveFouls,
+    EightBallPocketed,
+    // the loser pocketed the 8 before clearing their group
+    EarlyEightBall,
+    EightBallOnFoul,
+    EightBallWrongPocket,
+    EightBallOffTable,
+    TenBallPocketed,
+    TargetScoreReached,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GameResult {
+    pub winner: WhoseMove,
+    pub reason: WinReason,
+}
+
+/// What a shot did to the game, as decided by the rules.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShotOutcome {
+    pub shooter: WhoseMove,
+    /// Every foul committed on the shot; empty for a legal shot
+    pub fouls: Vec<Foul>,
+    pub pocketed: Vec<PocketedBall>,
+    /// Balls to put back on the table, on the foot spot
+    pub respotted: Vec<u32>,
+    /// Balls to rack again, see `Ruleset::rerack`
+    pub rerack: Vec<u32>,
+    /// Points the shooter scored, or lost, on the shot
+    pub points: i32,
+    pub push_out: bool,
+    pub next_shooter: WhoseMove,
+    pub ball_in_hand: bool,
+    pub result: Option<GameResult>,
+}
+
+impl ShotOutcome {
+    pub fn is_foul(&self) -> bool {
+        !self.fouls.is_empty()
+    }
+}
+
+/// Shot log events, and a table of shots to judge, for the rules tests.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::{Foul, GameResult, GameState, Pocket, Ruleset, ShotEvent, ShotLog, WhoseMove, WinReason};
+
+    /// One row of a rules table: a game as `rules` sets it up with Player1
+    /// to shoot, the shot, and what the rules should make of it. Player2
+    /// takes the table with no foul and no result unless the row says so.
+    pub struct Case<R> {
+        pub name: &'static str,
+        rules: fn() -> R,
+        events: Vec<ShotEvent>,
+        pub fouls: Vec<Foul>,
+        pub next: WhoseMove,
+        pub respotted: Vec<u32>,
+        pub result: Option<GameResult>,
+    }
+
+    pub fn case<R>(name: &'static str, rules: fn() -> R, events: Vec<ShotEvent>) -> Case<R> {
+        Case { name, rules, events, fouls: vec![], next: WhoseMove::Player2, respotted: vec![], result: None }
+    }
+
+    impl<R: Ruleset> Case<R> {
+        pub fn fouls(mut self, fouls: Vec<Foul>) -> Self {
+            self.fouls = fouls;
+            self
+        }
+
+        pub fn keeps_table(mut self) -> Self {
+            self.next = WhoseMove::Player1;
+            self
+        }
+
+        pub fn respots(mut self, balls: Vec<u32>) -> Self {
+            self.respotted = balls;
+            self
+        }
+
+        pub fn ends(mut self, winner: WhoseMove, reason: WinReason) -> Self {
+            self.result = Some(GameResult { winner, reason });
+            self
+        }
+
+        /// Judge the shot, check the outcome against the row and hand back
+        /// the rules as the shot left them.
+        pub fn check(&self) -> R {
+            let mut rules = (self.rules)();
+            let outcome = rules.evaluate(&ShotLog { events: self.events.clone() });
+            assert_eq!(outcome.fouls, self.fouls, "{}", self.name);
+            assert_eq!(outcome.respotted, self.respotted, "{}", self.name);
+            assert_eq!(outcome.result, self.result, "{}", self.name);
+            if self.result.is_none() {
+                assert_eq!(outcome.next_shooter, self.next, "{}", self.name);
+                assert_eq!(outcome.ball_in_hand, !self.fouls.is_empty(), "{}", self.name);
+            }
+            assert_eq!(rules.shooter(), outcome.next_shooter, "{}", self.name);
+            rules
+        }
+    }
+
+    pub fn contact(first: u32, second: u32) -> ShotEvent {
+        ShotEvent::BallContact { first, second }
+    }
+
+    pub fn rail(ball: u32) -> ShotEvent {
+        ShotEvent::Cushion { ball }
+    }
+
+    pub fn pot(ball: u32, pocket: Pocket) -> ShotEvent {
+        ShotEvent::Pocketed { ball, pocket }
+    }
+
+    pub fn jump(ball: u32) -> ShotEvent {
+        ShotEvent::OffTable { ball }
+    }
+
+    /// The game `rules` are in, reported into a game state and taken back
+    /// by `fresh`, as a table picked up from a snapshot would be.
+    pub fn round_trip<R: Ruleset>(rules: &R, mut fresh: R, on_table: &[u32]) -> R {
+        let mut state = GameState { whose_move: rules.shooter(), ..Default::default() };
+        rules.report(&mut state);
+        fresh.restore(&state, on_table);
+        fresh
+    }
+}
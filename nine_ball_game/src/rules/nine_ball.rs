@@ -0,0 +1,403 @@
+// src/rules/nine_ball.rs
+// WPA nine-ball. The lowest numbered ball on the table must be hit first,
+// and pocketing the 9 on a legal shot wins. Every shot also has to pocket
+// a ball or send a ball to a rail after contact. The break must drive four
+// object balls to a rail if it pockets nothing. The player after a legal
+// break may push out, and three fouls in a row lose the game.
+use bevy::prelude::*;
+use rand::RngCore;
+
+use super::super::{GameState, TableSpec, WhoseMove};
+use super::{rack, Declaration, Foul, GameResult, Ruleset, ShotLog, ShotOutcome, WinReason, CUE_BALL};
+
+pub const NINE_BALL: u32 = 9;
+/// Object balls that have to reach a rail on a break that pockets nothing.
+pub const BREAK_BALLS_TO_RAIL: u32 = 4;
+/// Fouls in a row, without a legal shot in between, that lose the game.
+pub const FOUL_LIMIT: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NineBallRules {
+    pub shooter: WhoseMove,
+    /// Object balls still on the table
+    pub on_table: Vec<u32>,
+    /// Fouls in a row, per player
+    pub consecutive_fouls: [u8; 2],
+    pub breaking: bool,
+    /// The shooter may call a push-out (first shot after a legal break)
+    pub push_out_available: bool,
+    pub push_out_declared: bool,
+    /// The shooter may hand the table back after the opponent's push-out
+    pub may_pass: bool,
+}
+
+/// Fouls in a game where the lowest ball on the table is hit first.
+pub(super) fn rotation_fouls(log: &ShotLog, lowest: u32, breaking: bool, push_out: bool) -> Vec<Foul> {
+    if log.timed_out() {
+        return vec![Foul::ShotClockExpired];
+    }
+    let mut fouls = Vec::new();
+
+    // a push-out may hit anything, or nothing
+    if !push_out {
+        match log.first_contact() {
+            None => fouls.push(Foul::NoBallHit),
+            Some(hit) if hit != lowest => fouls.push(Foul::WrongBallFirst { expected: lowest, hit }),
+            Some(_) => {}
+        }
+        if log.first_contact().is_some() {
+            let balls_to_rail = log.object_balls_to_rail().len() as u32;
+            if breaking {
+                if log.object_balls_pocketed().is_empty() && balls_to_rail < BREAK_BALLS_TO_RAIL {
+                    fouls.push(Foul::IllegalBreak { balls_to_rail });
+                }
+            } else if !log.rail_after_contact() {
+                fouls.push(Foul::NoRailAfterContact);
+            }
+        }
+    }
+    if log.cue_ball_pocketed() {
+        fouls.push(Foul::CueBallPocketed);
+    }
+    for ball in log.off_table() {
+        if ball == CUE_BALL {
+            fouls.push(Foul::CueBallOffTable);
+        } else {
+            fouls.push(Foul::BallOffTable { ball });
+        }
+    }
+    fouls
+}
+
+impl NineBallRules {
+    pub fn new(breaker: WhoseMove) -> Self {
+        NineBallRules {
+            shooter: breaker,
+            on_table: (1..=NINE_BALL).collect(),
+            consecutive_fouls: [0; 2],
+            breaking: true,
+            push_out_available: false,
+            push_out_declared: false,
+            may_pass: false,
+        }
+    }
+
+    /// The ball the shooter has to hit first.
+    pub fn lowest_ball(&self) -> u32 {
+        self.on_table.iter().copied().min().unwrap_or(NINE_BALL)
+    }
+
+    pub fn fouls_in_a_row(&self, player: &WhoseMove) -> u8 {
+        self.consecutive_fouls[player.index()]
+    }
+
+    /// Call the coming shot a push-out.
+    pub fn declare_push_out(&mut self) -> Result<(), String> {
+        if !self.push_out_available {
+            return Err("a push out is only allowed on the shot after the break".to_string());
+        }
+        self.push_out_declared = true;
+        Ok(())
+    }
+
+    /// Hand the table back to the player who pushed out.
+    pub fn pass(&mut self) -> Result<WhoseMove, String> {
+        if !self.may_pass {
+            return Err("can only pass after the opponent's push out".to_string());
+        }
+        self.may_pass = false;
+        self.shooter = self.shooter.other();
+        Ok(self.shooter.clone())
+    }
+
+    /// Judge a shot from what happened on the table and move the game on.
+    pub fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome {
+        let shooter = self.shooter.clone();
+        let push_out = self.push_out_declared;
+        let fouls = rotation_fouls(log, self.lowest_ball(), self.breaking, push_out);
+        let jumped = log.off_table();
+
+        let pocketed = log.object_balls_pocketed();
+        self.on_table.retain(|ball| !pocketed.contains(ball) && !jumped.contains(ball));
+
+        let foul = !fouls.is_empty();
+        let nine_down = pocketed.contains(&NINE_BALL);
+        let mut respotted = Vec::new();
+        if jumped.contains(&NINE_BALL) || (nine_down && (foul || push_out)) {
+            respotted.push(NINE_BALL);
+            self.on_table.push(NINE_BALL);
+        }
+
+        let mut result = None;
+        if nine_down && !foul && !push_out {
+            result = Some(GameResult { winner: shooter.clone(), reason: WinReason::NineBallPocketed });
+        }
+
+        let fouls_in_a_row = &mut self.consecutive_fouls[shooter.index()];
+        if foul {
+            *fouls_in_a_row += 1;
+            if *fouls_in_a_row >= FOUL_LIMIT {
+                result = Some(GameResult { winner: shooter.other(), reason: WinReason::ThreeConsecutiveFouls });
+            }
+        } else {
+            *fouls_in_a_row = 0;
+        }
+
+        let keeps_table = !foul && !push_out && !pocketed.is_empty();
+        let next_shooter = if keeps_table { shooter.clone() } else { shooter.other() };
+
+        self.push_out_available = self.breaking && !foul && result.is_none();
+        self.may_pass = push_out && !foul && result.is_none();
+        self.breaking = false;
+        self.push_out_declared = false;
+        self.shooter = next_shooter.clone();
+
+        ShotOutcome {
+            shooter,
+            fouls,
+            pocketed: log.pocketed(),
+            respotted,
+            rerack: Vec::new(),
+            points: 0,
+            push_out,
+            next_shooter,
+            ball_in_hand: foul && result.is_none(),
+            result,
+        }
+    }
+}
+
+impl Ruleset for NineBallRules {
+    fn shooter(&self) -> WhoseMove {
+        self.shooter.clone()
+    }
+
+    fn target_ball(&self) -> Option<u32> {
+        Some(self.lowest_ball())
+    }
+
+    // the 1 at the apex on the foot spot and the 9 in the middle
+    fn rack(&self, spec: &TableSpec, rng: &mut dyn RngCore) -> Vec<(u32, Vec3)> {
+        let positions = rack::diamond(spec.foot_spot());
+        rack::fill(&positions, &[(1, 0), (NINE_BALL, 4)], &[2, 3, 4, 5, 6, 7, 8], rng)
+    }
+
+    fn new_rack(&mut self, breaker: WhoseMove) {
+        *self = NineBallRules::new(breaker);
+    }
+
+    fn declare(&mut self, declaration: Declaration) -> Result<(), String> {
+        match declaration {
+            Declaration::PushOut => self.declare_push_out(),
+            Declaration::Pass => self.pass().map(|_| ()),
+            Declaration::CallPocket(_) | Declaration::CallShot { .. } => Err("nine-ball shots are not called".to_string()),
+        }
+    }
+
+    fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome {
+        NineBallRules::evaluate(self, log)
+    }
+
+    fn report(&self, state: &mut GameState) {
+        state.consecutive_fouls = self.consecutive_fouls;
+        state.push_out_available = self.push_out_available;
+        state.push_out_declared = self.push_out_declared;
+        state.may_pass = self.may_pass;
+        state.breaking = self.breaking;
+    }
+
+    fn restore(&mut self, state: &GameState, on_table: &[u32]) {
+        self.shooter = state.whose_move.clone();
+        self.on_table = on_table.to_vec();
+        self.consecutive_fouls = state.consecutive_fouls;
+        self.push_out_available = state.push_out_available;
+        self.push_out_declared = state.push_out_declared;
+        self.may_pass = state.may_pass;
+        self.breaking = state.breaking;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Pocket, ShotEvent};
+    use super::*;
+    use super::super::test_support::{case, contact, jump, pot, rail, round_trip};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn breaking() -> NineBallRules {
+        NineBallRules::new(WhoseMove::Player1)
+    }
+
+    fn mid_game() -> NineBallRules {
+        NineBallRules { breaking: false, ..breaking() }
+    }
+
+    fn pushing_out() -> NineBallRules {
+        let mut rules = NineBallRules { push_out_available: true, ..mid_game() };
+        rules.declare_push_out().unwrap();
+        rules
+    }
+
+    #[test]
+    fn rules_table() {
+        use Foul::*;
+        let cases = vec![
+            case("pocketing the lowest ball keeps the table", mid_game, vec![contact(0, 1), pot(1, Pocket::FootLeft)]).keeps_table(),
+            case("combination off the lowest ball", mid_game, vec![contact(0, 1), contact(1, 4), pot(4, Pocket::FootLeft)]).keeps_table(),
+            case("legal miss passes the turn", mid_game, vec![contact(0, 1), rail(1)]),
+            case("cue ball to a rail after contact counts", mid_game, vec![contact(0, 1), rail(0)]),
+            case("wrong ball first", mid_game, vec![contact(0, 2), rail(2)]).fouls(vec![WrongBallFirst { expected: 1, hit: 2 }]),
+            case("wrong ball first pockets nothing", mid_game, vec![contact(0, 2), pot(2, Pocket::FootLeft)])
+                .fouls(vec![WrongBallFirst { expected: 1, hit: 2 }]),
+            case("no ball hit", mid_game, vec![rail(0), rail(0)]).fouls(vec![NoBallHit]),
+            case("no rail after contact", mid_game, vec![contact(0, 1)]).fouls(vec![NoRailAfterContact]),
+            case("rail before contact does not count", mid_game, vec![rail(0), contact(0, 1)]).fouls(vec![NoRailAfterContact]),
+            case("scratch", mid_game, vec![contact(0, 1), pot(0, Pocket::FootLeft)]).fouls(vec![CueBallPocketed]),
+            case("scratch while pocketing a ball", mid_game, vec![contact(0, 1), pot(1, Pocket::FootLeft), pot(0, Pocket::FootLeft)]).fouls(vec![CueBallPocketed]),
+            case("cue ball jumped", mid_game, vec![contact(0, 1), rail(1), jump(0)]).fouls(vec![CueBallOffTable]),
+            case("object ball jumped", mid_game, vec![contact(0, 1), rail(1), jump(3)]).fouls(vec![BallOffTable { ball: 3 }]),
+            case("legal 9 wins", mid_game, vec![contact(0, 1), pot(9, Pocket::FootLeft)]).ends(WhoseMove::Player1, WinReason::NineBallPocketed),
+            case("9 on a foul is respotted", mid_game, vec![contact(0, 2), pot(9, Pocket::FootLeft)])
+                .fouls(vec![WrongBallFirst { expected: 1, hit: 2 }])
+                .respots(vec![9]),
+            case("9 with a scratch is respotted", mid_game, vec![contact(0, 1), pot(9, Pocket::FootLeft), pot(0, Pocket::FootLeft)])
+                .fouls(vec![CueBallPocketed])
+                .respots(vec![9]),
+            case("jumped 9 is respotted", mid_game, vec![contact(0, 1), rail(1), jump(9)])
+                .fouls(vec![BallOffTable { ball: 9 }])
+                .respots(vec![9]),
+            case("break with four balls to a rail", breaking, vec![contact(0, 1), rail(2), rail(3), rail(4), rail(5)]),
+            case("break counts each ball once", breaking, vec![contact(0, 1), rail(2), rail(2), rail(3), rail(3), rail(4)])
+                .fouls(vec![IllegalBreak { balls_to_rail: 3 }]),
+            case("break cue ball to a rail does not count", breaking, vec![contact(0, 1), rail(0), rail(2), rail(3), rail(4)])
+                .fouls(vec![IllegalBreak { balls_to_rail: 3 }]),
+            case("break pocketing a ball keeps the table", breaking, vec![contact(0, 1), pot(5, Pocket::FootLeft)]).keeps_table(),
+            case("break must hit the 1 first", breaking, vec![contact(0, 2), rail(1), rail(3), rail(4), rail(5)])
+                .fouls(vec![WrongBallFirst { expected: 1, hit: 2 }]),
+            case("9 on the break wins", breaking, vec![contact(0, 1), pot(9, Pocket::FootLeft)]).ends(WhoseMove::Player1, WinReason::NineBallPocketed),
+            case("push out may hit nothing", pushing_out, vec![rail(0)]),
+            case("push out needs no rail", pushing_out, vec![contact(0, 6)]),
+            case("balls pocketed on a push out stay down", pushing_out, vec![contact(0, 6), pot(6, Pocket::FootLeft)]),
+            case("9 on a push out is respotted", pushing_out, vec![contact(0, 6), pot(9, Pocket::FootLeft)]).respots(vec![9]),
+            case("scratch on a push out", pushing_out, vec![contact(0, 6), pot(0, Pocket::FootLeft)]).fouls(vec![CueBallPocketed]),
+            case("shot clock runs out", mid_game, vec![ShotEvent::ShotClockExpired]).fouls(vec![ShotClockExpired]),
+            case("shot clock runs out on a push out", pushing_out, vec![ShotEvent::ShotClockExpired]).fouls(vec![ShotClockExpired]),
+        ];
+
+        for case in cases {
+            let rules = case.check();
+            for ball in &case.respotted {
+                assert!(rules.on_table.contains(ball), "{}: {} not back on the table", case.name, ball);
+            }
+        }
+    }
+
+    #[test]
+    fn jumped_object_ball_stays_off() {
+        let mut rules = mid_game();
+        rules.evaluate(&ShotLog { events: vec![contact(0, 1), rail(1), jump(3)] });
+        assert!(!rules.on_table.contains(&3));
+    }
+
+    #[test]
+    fn lowest_ball_follows_the_table() {
+        let mut rules = mid_game();
+        rules.evaluate(&ShotLog { events: vec![contact(0, 1), pot(1, Pocket::FootLeft), pot(2, Pocket::FootLeft)] });
+        assert_eq!(rules.lowest_ball(), 3);
+        let outcome = rules.evaluate(&ShotLog { events: vec![contact(0, 1), rail(1)] });
+        assert_eq!(outcome.fouls, vec![Foul::WrongBallFirst { expected: 3, hit: 1 }]);
+    }
+
+    #[test]
+    fn push_out_only_after_a_legal_break() {
+        let mut rules = breaking();
+        assert!(rules.declare_push_out().is_err());
+        rules.evaluate(&ShotLog { events: vec![contact(0, 1), rail(2), rail(3), rail(4), rail(5)] });
+        assert!(rules.push_out_available);
+        rules.evaluate(&ShotLog { events: vec![contact(0, 1), rail(1)] });
+        assert!(!rules.push_out_available);
+        assert!(rules.declare_push_out().is_err());
+
+        let mut rules = breaking();
+        rules.evaluate(&ShotLog { events: vec![contact(0, 1), rail(2)] });
+        assert!(!rules.push_out_available, "no push out after an illegal break");
+    }
+
+    #[test]
+    fn opponent_may_pass_after_a_push_out() {
+        let mut rules = pushing_out();
+        assert!(rules.pass().is_err());
+        let outcome = rules.evaluate(&ShotLog { events: vec![contact(0, 6), rail(6)] });
+        assert!(outcome.push_out);
+        assert_eq!(rules.shooter, WhoseMove::Player2);
+        assert!(rules.may_pass);
+        assert_eq!(rules.pass(), Ok(WhoseMove::Player1));
+        assert!(rules.pass().is_err());
+
+        // shooting instead of passing uses up the option
+        let mut rules = pushing_out();
+        rules.evaluate(&ShotLog { events: vec![contact(0, 6)] });
+        rules.evaluate(&ShotLog { events: vec![contact(0, 1), rail(1)] });
+        assert!(!rules.may_pass);
+    }
+
+    #[test]
+    fn a_declared_push_out_is_picked_up_from_the_game_state() {
+        let rules = NineBallRules { on_table: vec![3, 5, 9], consecutive_fouls: [1, 2], ..pushing_out() };
+        assert_eq!(round_trip(&rules, NineBallRules::new(WhoseMove::Player2), &rules.on_table), rules);
+    }
+
+    #[test]
+    fn three_fouls_in_a_row_lose() {
+        let mut rules = mid_game();
+        let foul = ShotLog { events: vec![rail(0)] };
+        let miss = ShotLog { events: vec![contact(0, 1), rail(1)] };
+        for _ in 0..2 {
+            let outcome = rules.evaluate(&foul);
+            assert!(outcome.result.is_none());
+            // opponent takes ball in hand and misses legally
+            rules.evaluate(&miss);
+        }
+        assert_eq!(rules.fouls_in_a_row(&WhoseMove::Player1), 2);
+        let outcome = rules.evaluate(&foul);
+        assert_eq!(
+            outcome.result,
+            Some(GameResult { winner: WhoseMove::Player2, reason: WinReason::ThreeConsecutiveFouls })
+        );
+        assert!(!outcome.ball_in_hand);
+    }
+
+    #[test]
+    fn legal_shot_resets_the_foul_count() {
+        let mut rules = mid_game();
+        let foul = ShotLog { events: vec![rail(0)] };
+        let miss = ShotLog { events: vec![contact(0, 1), rail(1)] };
+        rules.evaluate(&foul);
+        rules.evaluate(&miss);
+        rules.evaluate(&foul);
+        rules.evaluate(&miss);
+        // player 1 shoots legally, then fouls again: only one in a row
+        rules.evaluate(&miss);
+        rules.evaluate(&miss);
+        let outcome = rules.evaluate(&foul);
+        assert!(outcome.result.is_none());
+        assert_eq!(rules.fouls_in_a_row(&WhoseMove::Player1), 1);
+    }
+
+    #[test]
+    fn rack_has_the_one_on_the_foot_spot_and_the_nine_in_the_middle() {
+        let spec = TableSpec::default();
+        let rack = breaking().rack(&spec, &mut StdRng::seed_from_u64(9));
+        let mut numbers: Vec<u32> = rack.iter().map(|(n, _)| *n).collect();
+        numbers.sort();
+        assert_eq!(numbers, (1..=9).collect::<Vec<_>>());
+
+        let at = |ball: u32| rack.iter().find(|(n, _)| *n == ball).unwrap().1;
+        let foot_spot = spec.foot_spot();
+        assert!(Vec2::new(at(1).x - foot_spot.x, at(1).z - foot_spot.z).length() < 1e-3, "the 1 is at {}", at(1));
+        let positions = rack::diamond(foot_spot);
+        let middle = positions.iter().sum::<Vec3>() / positions.len() as f32;
+        assert!(at(NINE_BALL).distance(middle) < 1e-3, "the 9 is at {}, the middle at {}", at(NINE_BALL), middle);
+    }
+}
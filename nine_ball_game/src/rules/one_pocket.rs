@@ -0,0 +1,288 @@
+// src/rules/one_pocket.rs
+// One-pocket. Each player owns one of the foot corner pockets and any ball
+// may be played. Every ball that drops in a player's pocket is a point for
+// that player, whoever shot it; balls in the other four pockets are spotted.
+// The shooter stays at the table while they keep making balls in their own
+// pocket. A foul costs a ball: the fouler spots one of theirs, or owes one
+// and pays it with the next ball they make. Three fouls in a row lose, and
+// the first to eight wins.
+use bevy::prelude::*;
+use rand::RngCore;
+
+use super::super::{GameState, Pocket, TableSpec, WhoseMove};
+use super::nine_ball::FOUL_LIMIT;
+use super::{rack, Declaration, Foul, GameResult, Ruleset, ShotLog, ShotOutcome, WinReason, CUE_BALL};
+
+pub const BALLS: u32 = 15;
+pub const RACE_TO: i32 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnePocketRules {
+    pub shooter: WhoseMove,
+    /// The pocket each player scores in
+    pub pockets: [Pocket; 2],
+    /// Balls each player has made and kept
+    pub made: [Vec<u32>; 2],
+    /// Balls each player owes for fouls they had nothing to spot for
+    pub owed: [u32; 2],
+    /// Fouls in a row, per player
+    pub consecutive_fouls: [u8; 2],
+}
+
+impl OnePocketRules {
+    /// The breaker gets the foot pocket on their left.
+    pub fn new(breaker: WhoseMove) -> Self {
+        let mut pockets = [Pocket::FootRight; 2];
+        pockets[breaker.index()] = Pocket::FootLeft;
+        OnePocketRules { shooter: breaker, pockets, made: [Vec::new(), Vec::new()], owed: [0; 2], consecutive_fouls: [0; 2] }
+    }
+
+    pub fn score(&self, player: &WhoseMove) -> i32 {
+        self.made[player.index()].len() as i32 - self.owed[player.index()] as i32
+    }
+
+    fn owner(&self, pocket: Pocket) -> Option<WhoseMove> {
+        [WhoseMove::Player1, WhoseMove::Player2].into_iter().find(|player| self.pockets[player.index()] == pocket)
+    }
+
+    // a ball made for `player` pays off a debt first, and is spotted if it does
+    fn credit(&mut self, player: &WhoseMove, ball: u32, respotted: &mut Vec<u32>) {
+        let i = player.index();
+        if self.owed[i] > 0 {
+            self.owed[i] -= 1;
+            respotted.push(ball);
+        } else {
+            self.made[i].push(ball);
+        }
+    }
+
+    pub fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome {
+        let shooter = self.shooter.clone();
+        let score_before = self.score(&shooter);
+        let mut fouls = Vec::new();
+        if log.timed_out() {
+            fouls.push(Foul::ShotClockExpired);
+        } else if log.first_contact().is_none() {
+            fouls.push(Foul::NoBallHit);
+        } else if !log.rail_after_contact() {
+            fouls.push(Foul::NoRailAfterContact);
+        }
+        if log.cue_ball_pocketed() {
+            fouls.push(Foul::CueBallPocketed);
+        }
+        let jumped = log.off_table();
+        for &ball in &jumped {
+            if ball == CUE_BALL {
+                fouls.push(Foul::CueBallOffTable);
+            } else {
+                fouls.push(Foul::BallOffTable { ball });
+            }
+        }
+        let foul = !fouls.is_empty();
+        let scratched = log.cue_ball_pocketed() || jumped.contains(&CUE_BALL);
+
+        // the opponent keeps what goes in their pocket even on a foul; the
+        // shooter's own balls only count on a legal shot
+        let mut respotted = Vec::new();
+        let mut made_own = false;
+        for pocketed in log.pocketed().into_iter().filter(|p| p.number != CUE_BALL) {
+            match self.owner(pocketed.pocket) {
+                Some(owner) if owner != shooter => self.credit(&owner, pocketed.number, &mut respotted),
+                Some(owner) if !foul => {
+                    made_own = true;
+                    self.credit(&owner, pocketed.number, &mut respotted);
+                }
+                _ => respotted.push(pocketed.number),
+            }
+        }
+        respotted.extend(jumped.iter().copied().filter(|&ball| ball != CUE_BALL));
+
+        let mut result = None;
+        let fouls_in_a_row = &mut self.consecutive_fouls[shooter.index()];
+        if foul {
+            *fouls_in_a_row += 1;
+            if *fouls_in_a_row >= FOUL_LIMIT {
+                result = Some(GameResult { winner: shooter.other(), reason: WinReason::ThreeConsecutiveFouls });
+            }
+            match self.made[shooter.index()].pop() {
+                Some(ball) => respotted.push(ball),
+                None => self.owed[shooter.index()] += 1,
+            }
+        } else {
+            *fouls_in_a_row = 0;
+        }
+
+        if result.is_none() {
+            // the shooter can win or lose the race on the same shot; their
+            // own pocket is checked first
+            result = [shooter.clone(), shooter.other()]
+                .into_iter()
+                .find(|player| self.score(player) >= RACE_TO)
+                .map(|winner| GameResult { winner, reason: WinReason::TargetScoreReached });
+        }
+
+        let next_shooter = if made_own { shooter.clone() } else { shooter.other() };
+        self.shooter = next_shooter.clone();
+        let points = self.score(&shooter) - score_before;
+
+        ShotOutcome {
+            shooter,
+            fouls,
+            pocketed: log.pocketed(),
+            respotted,
+            rerack: Vec::new(),
+            points,
+            push_out: false,
+            next_shooter,
+            ball_in_hand: scratched && result.is_none(),
+            result,
+        }
+    }
+}
+
+impl Ruleset for OnePocketRules {
+    fn shooter(&self) -> WhoseMove {
+        self.shooter.clone()
+    }
+
+    fn rack(&self, spec: &TableSpec, rng: &mut dyn RngCore) -> Vec<(u32, Vec3)> {
+        let positions = rack::triangle(spec.foot_spot(), 5);
+        let balls: Vec<u32> = (1..=BALLS).collect();
+        rack::fill(&positions, &[], &balls, rng)
+    }
+
+    fn new_rack(&mut self, breaker: WhoseMove) {
+        *self = OnePocketRules::new(breaker);
+    }
+
+    // a scratch puts the cue ball in the kitchen
+    fn in_hand_behind_head_string(&self) -> bool {
+        true
+    }
+
+    fn declare(&mut self, _declaration: Declaration) -> Result<(), String> {
+        Err("nothing is called in one-pocket".to_string())
+    }
+
+    fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome {
+        OnePocketRules::evaluate(self, log)
+    }
+
+    fn report(&self, state: &mut GameState) {
+        state.scores = [self.score(&WhoseMove::Player1), self.score(&WhoseMove::Player2)];
+        state.consecutive_fouls = self.consecutive_fouls;
+        state.owned_pockets = self.pockets.map(Some);
+        state.balls_made = self.made.clone();
+        state.balls_owed = self.owed;
+    }
+
+    fn restore(&mut self, state: &GameState, _on_table: &[u32]) {
+        self.shooter = state.whose_move.clone();
+        self.made = state.balls_made.clone();
+        self.owed = state.balls_owed;
+        self.consecutive_fouls = state.consecutive_fouls;
+        if let [Some(first), Some(second)] = state.owned_pockets {
+            self.pockets = [first, second];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ShotEvent;
+    use super::*;
+    use super::super::test_support::{contact, rail, pot, round_trip};
+
+    fn shoot(rules: &mut OnePocketRules, events: Vec<ShotEvent>) -> ShotOutcome {
+        rules.evaluate(&ShotLog { events })
+    }
+
+    #[test]
+    fn own_pocket_scores_and_keeps_the_table() {
+        let mut rules = OnePocketRules::new(WhoseMove::Player1);
+        let outcome = shoot(&mut rules, vec![contact(0, 3), pot(3, Pocket::FootLeft)]);
+        assert_eq!(rules.score(&WhoseMove::Player1), 1);
+        assert_eq!(outcome.next_shooter, WhoseMove::Player1);
+        assert!(outcome.respotted.is_empty());
+    }
+
+    #[test]
+    fn opponents_pocket_scores_for_the_opponent() {
+        let mut rules = OnePocketRules::new(WhoseMove::Player1);
+        let outcome = shoot(&mut rules, vec![contact(0, 3), pot(3, Pocket::FootRight)]);
+        assert!(outcome.fouls.is_empty());
+        assert_eq!(rules.score(&WhoseMove::Player2), 1);
+        assert_eq!(outcome.next_shooter, WhoseMove::Player2);
+    }
+
+    #[test]
+    fn other_pockets_are_spotted() {
+        let mut rules = OnePocketRules::new(WhoseMove::Player1);
+        let outcome = shoot(&mut rules, vec![contact(0, 3), pot(3, Pocket::SideLeft)]);
+        assert_eq!(outcome.respotted, vec![3]);
+        assert_eq!(rules.score(&WhoseMove::Player1), 0);
+        assert_eq!(outcome.next_shooter, WhoseMove::Player2);
+    }
+
+    #[test]
+    fn foul_spots_a_ball_or_owes_one() {
+        let mut rules = OnePocketRules::new(WhoseMove::Player1);
+        rules.made[0] = vec![6];
+        let outcome = shoot(&mut rules, vec![contact(0, 3)]);
+        assert_eq!(outcome.fouls, vec![Foul::NoRailAfterContact]);
+        assert_eq!(outcome.respotted, vec![6]);
+        assert!(!outcome.ball_in_hand);
+        assert_eq!(rules.score(&WhoseMove::Player1), 0);
+
+        rules.shooter = WhoseMove::Player1;
+        shoot(&mut rules, vec![contact(0, 3)]);
+        assert_eq!(rules.score(&WhoseMove::Player1), -1);
+
+        // the next ball made pays the debt and comes back up
+        rules.shooter = WhoseMove::Player1;
+        let outcome = shoot(&mut rules, vec![contact(0, 4), pot(4, Pocket::FootLeft)]);
+        assert_eq!(outcome.respotted, vec![4]);
+        assert_eq!(rules.score(&WhoseMove::Player1), 0);
+        assert_eq!(rules.consecutive_fouls[0], 0);
+    }
+
+    #[test]
+    fn scores_and_debts_are_picked_up_from_the_game_state() {
+        let mut rules = OnePocketRules::new(WhoseMove::Player1);
+        rules.made = [vec![2, 9], vec![]];
+        rules.owed = [0, 1];
+        rules.consecutive_fouls = [0, 1];
+        let restored = round_trip(&rules, OnePocketRules::new(WhoseMove::Player2), &[]);
+        assert_eq!(restored.score(&WhoseMove::Player1), 2);
+        assert_eq!(restored.score(&WhoseMove::Player2), -1);
+        assert_eq!(restored, rules);
+    }
+
+    #[test]
+    fn own_pocket_on_a_scratch_does_not_count() {
+        let mut rules = OnePocketRules::new(WhoseMove::Player1);
+        let outcome = shoot(&mut rules, vec![contact(0, 3), pot(3, Pocket::FootLeft), pot(5, Pocket::FootRight), pot(0, Pocket::SideLeft)]);
+        assert_eq!(outcome.fouls, vec![Foul::CueBallPocketed]);
+        assert!(outcome.ball_in_hand);
+        assert_eq!(outcome.respotted, vec![3]);
+        assert_eq!(rules.score(&WhoseMove::Player1), -1);
+        assert_eq!(rules.score(&WhoseMove::Player2), 1);
+        assert_eq!(outcome.next_shooter, WhoseMove::Player2);
+    }
+
+    #[test]
+    fn first_to_eight_wins() {
+        let mut rules = OnePocketRules::new(WhoseMove::Player1);
+        rules.made = [(1..=7).collect(), (8..=14).collect()];
+        let outcome = shoot(&mut rules, vec![contact(0, 15), rail(15), pot(15, Pocket::FootRight)]);
+        assert_eq!(outcome.result, Some(GameResult { winner: WhoseMove::Player2, reason: WinReason::TargetScoreReached }));
+    }
+
+    #[test]
+    fn three_fouls_lose() {
+        let mut rules = OnePocketRules::new(WhoseMove::Player1);
+        rules.consecutive_fouls[0] = 2;
+        let outcome = shoot(&mut rules, vec![]);
+        assert_eq!(outcome.result, Some(GameResult { winner: WhoseMove::Player2, reason: WinReason::ThreeConsecutiveFouls }));
+    }
+}
@@ -0,0 +1,57 @@
+// src/rules/rack.rs
+// Rack shapes. Positions are ball centres at rest on the bed, a hair apart
+// so the rack does not start out squeezed, with the apex towards the head.
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+
+use super::super::STANDARD_BALL_RADIUS;
+
+const SPACING_EPSILON: f32 = 0.0005;
+// shuffled balls are nudged by up to this much so no two breaks are alike
+const MAX_JITTER: f32 = 0.0002;
+
+// distance between neighbouring columns and rows of a rack
+fn spacing() -> (f32, f32) {
+    let spacing_r = STANDARD_BALL_RADIUS + SPACING_EPSILON;
+    (spacing_r, 3.0_f32.sqrt() * spacing_r)
+}
+
+fn row(z: f32, count: usize) -> impl Iterator<Item = Vec3> {
+    let (x_spacing, _) = spacing();
+    (0..count).map(move |j| Vec3::new((2.0 * j as f32 - (count as f32 - 1.0)) * x_spacing, STANDARD_BALL_RADIUS, z))
+}
+
+/// A triangle of `rows` rows with its apex at `apex`, row by row from the
+/// apex, left to right.
+pub fn triangle(apex: Vec3, rows: usize) -> Vec<Vec3> {
+    let (_, z_spacing) = spacing();
+    (0..rows).flat_map(|k| row(apex.z + k as f32 * z_spacing, k + 1)).map(|p| p + Vec3::X * apex.x).collect()
+}
+
+/// The nine-ball diamond, rows of 1, 2, 3, 2 and 1 with its apex at `apex`,
+/// row by row from the apex, left to right.
+pub fn diamond(apex: Vec3) -> Vec<Vec3> {
+    let (_, z_spacing) = spacing();
+    [1, 2, 3, 2, 1]
+        .into_iter()
+        .enumerate()
+        .flat_map(|(k, count)| row(apex.z + k as f32 * z_spacing, count))
+        .map(|p| p + Vec3::X * apex.x)
+        .collect()
+}
+
+/// Put each `fixed` ball at its index into `positions` and shuffle `others`
+/// into the spots that are left, with `rng`.
+pub fn fill(positions: &[Vec3], fixed: &[(u32, usize)], others: &[u32], rng: &mut dyn RngCore) -> Vec<(u32, Vec3)> {
+    let mut shuffled = others.to_vec();
+    shuffled.shuffle(rng);
+
+    let mut rack: Vec<(u32, Vec3)> = fixed.iter().map(|&(ball, at)| (ball, positions[at])).collect();
+    let open = (0..positions.len()).filter(|i| !fixed.iter().any(|&(_, at)| at == *i));
+    for (ball, i) in shuffled.into_iter().zip(open) {
+        let jitter = Vec3::new(rng.random_range(-MAX_JITTER..MAX_JITTER), 0.0, rng.random_range(-MAX_JITTER..MAX_JITTER));
+        rack.push((ball, positions[i] + jitter));
+    }
+    rack
+}
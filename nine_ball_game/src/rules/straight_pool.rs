@@ -0,0 +1,381 @@
+// src/rules/straight_pool.rs
+// Straight pool (14.1 continuous). Any ball may be played, but every shot
+// is called. When the called ball drops in the called pocket, each ball
+// pocketed on the shot scores a point; otherwise anything that dropped is
+// spotted and the inning ends. A foul costs a point (two on the opening
+// break) and a third foul in a row another fifteen, after which all fifteen
+// are racked and the offender breaks again. When only one object ball is
+// left the other fourteen are racked again with the apex empty and the
+// shooter plays on. First to the target score wins.
+use bevy::prelude::*;
+use rand::RngCore;
+
+use super::super::{GameState, Pocket, TableSpec, WhoseMove};
+use super::nine_ball::FOUL_LIMIT;
+use super::{rack, Declaration, Foul, GameResult, Ruleset, ShotEvent, ShotLog, ShotOutcome, WinReason, CUE_BALL};
+
+pub const BALLS: u32 = 15;
+/// Object balls the opening break has to drive to a rail, along with the cue ball.
+pub const BREAK_BALLS_TO_RAIL: u32 = 2;
+pub const FOUL_PENALTY: i32 = 1;
+pub const BREAK_FOUL_PENALTY: i32 = 2;
+/// On top of the point for the foul itself
+pub const THIRD_FOUL_PENALTY: i32 = 15;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StraightPoolRules {
+    pub shooter: WhoseMove,
+    /// Object balls still on the table
+    pub on_table: Vec<u32>,
+    pub scores: [i32; 2],
+    /// Fouls in a row, per player
+    pub consecutive_fouls: [u8; 2],
+    pub breaking: bool,
+    pub target_score: i32,
+    /// Innings played so far, counting the one in progress
+    pub innings: u32,
+    /// Ball and pocket named for the coming shot
+    pub called_shot: Option<(u32, Pocket)>,
+}
+
+// whether the cue ball touched a cushion after hitting a ball
+fn cue_ball_to_rail_after_contact(log: &ShotLog) -> bool {
+    log.events
+        .iter()
+        .skip_while(|event| !matches!(event, ShotEvent::BallContact { .. }))
+        .any(|event| *event == ShotEvent::Cushion { ball: CUE_BALL })
+}
+
+impl StraightPoolRules {
+    pub fn new(breaker: WhoseMove, target_score: i32) -> Self {
+        StraightPoolRules {
+            shooter: breaker,
+            on_table: (1..=BALLS).collect(),
+            scores: [0; 2],
+            consecutive_fouls: [0; 2],
+            breaking: true,
+            target_score,
+            innings: 1,
+            called_shot: None,
+        }
+    }
+
+    /// Name the ball and pocket for the coming shot.
+    pub fn call_shot(&mut self, ball: u32, pocket: Pocket) -> Result<(), String> {
+        if !self.on_table.contains(&ball) {
+            return Err(format!("the {} is not on the table", ball));
+        }
+        self.called_shot = Some((ball, pocket));
+        Ok(())
+    }
+
+    pub fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome {
+        let shooter = self.shooter.clone();
+        let breaking = self.breaking;
+        let called_made = self
+            .called_shot
+            .take()
+            .is_some_and(|(ball, pocket)| log.pocketed().iter().any(|p| p.number == ball && p.pocket == pocket));
+        let mut fouls = Vec::new();
+
+        if log.timed_out() {
+            fouls.push(Foul::ShotClockExpired);
+        } else if log.first_contact().is_none() {
+            fouls.push(Foul::NoBallHit);
+        } else if breaking {
+            // the opening break is a safety unless a called ball drops
+            let balls_to_rail = log.object_balls_to_rail().len() as u32;
+            if !called_made && (balls_to_rail < BREAK_BALLS_TO_RAIL || !cue_ball_to_rail_after_contact(log)) {
+                fouls.push(Foul::IllegalBreak { balls_to_rail });
+            }
+        } else if !log.rail_after_contact() {
+            fouls.push(Foul::NoRailAfterContact);
+        }
+        if log.cue_ball_pocketed() {
+            fouls.push(Foul::CueBallPocketed);
+        }
+        let jumped = log.off_table();
+        for &ball in &jumped {
+            if ball == CUE_BALL {
+                fouls.push(Foul::CueBallOffTable);
+            } else {
+                fouls.push(Foul::BallOffTable { ball });
+            }
+        }
+        let foul = !fouls.is_empty();
+        let scratched = log.cue_ball_pocketed() || jumped.contains(&CUE_BALL);
+
+        let pocketed = log.object_balls_pocketed();
+        let legal_made = !foul && called_made;
+        let mut points = 0;
+        let mut respotted = Vec::new();
+        if legal_made {
+            points = pocketed.len() as i32;
+            self.on_table.retain(|ball| !pocketed.contains(ball));
+        } else {
+            respotted.extend(pocketed.iter().chain(jumped.iter()).copied().filter(|&ball| ball != CUE_BALL));
+        }
+
+        let fouls_in_a_row = &mut self.consecutive_fouls[shooter.index()];
+        let mut rebreak = false;
+        if foul {
+            let break_foul = breaking && fouls.iter().any(|f| matches!(f, Foul::IllegalBreak { .. }));
+            points = if break_foul { -BREAK_FOUL_PENALTY } else { -FOUL_PENALTY };
+            *fouls_in_a_row += 1;
+            if *fouls_in_a_row >= FOUL_LIMIT {
+                points -= THIRD_FOUL_PENALTY;
+                *fouls_in_a_row = 0;
+                rebreak = true;
+            }
+        } else {
+            *fouls_in_a_row = 0;
+        }
+        self.scores[shooter.index()] += points;
+
+        let mut result = None;
+        if legal_made && self.scores[shooter.index()] >= self.target_score {
+            result = Some(GameResult { winner: shooter.clone(), reason: WinReason::TargetScoreReached });
+        }
+
+        // down to the last object ball: rack the other fourteen and play on
+        let mut rerack = Vec::new();
+        if legal_made && result.is_none() && self.on_table.len() <= 1 {
+            rerack = (1..=BALLS).filter(|ball| !self.on_table.contains(ball)).collect();
+            self.on_table = (1..=BALLS).collect();
+        }
+        // a third foul in a row: the offender breaks a full rack from the kitchen
+        if rebreak {
+            rerack = (1..=BALLS).collect();
+            respotted.clear();
+            self.on_table = (1..=BALLS).collect();
+        }
+
+        let next_shooter = if legal_made || rebreak { shooter.clone() } else { shooter.other() };
+        if next_shooter != shooter {
+            self.innings += 1;
+        }
+        self.breaking = rebreak;
+        self.shooter = next_shooter.clone();
+
+        ShotOutcome {
+            shooter,
+            fouls,
+            pocketed: log.pocketed(),
+            respotted,
+            rerack,
+            points,
+            push_out: false,
+            next_shooter,
+            ball_in_hand: (scratched || rebreak) && result.is_none(),
+            result,
+        }
+    }
+}
+
+impl Ruleset for StraightPoolRules {
+    fn shooter(&self) -> WhoseMove {
+        self.shooter.clone()
+    }
+
+    fn rack(&self, spec: &TableSpec, rng: &mut dyn RngCore) -> Vec<(u32, Vec3)> {
+        let positions = rack::triangle(spec.foot_spot(), 5);
+        let balls: Vec<u32> = (1..=BALLS).collect();
+        rack::fill(&positions, &[], &balls, rng)
+    }
+
+    // the apex spot is left empty, unless all fifteen are racked
+    fn rerack(&self, spec: &TableSpec, balls: &[u32], rng: &mut dyn RngCore) -> Vec<(u32, Vec3)> {
+        let mut positions = rack::triangle(spec.foot_spot(), 5);
+        positions.rotate_left(1);
+        positions.truncate(balls.len());
+        rack::fill(&positions, &[], balls, rng)
+    }
+
+    fn new_rack(&mut self, breaker: WhoseMove) {
+        *self = StraightPoolRules::new(breaker, self.target_score);
+    }
+
+    // ball in hand only ever comes from a scratch, and goes in the kitchen
+    fn in_hand_behind_head_string(&self) -> bool {
+        true
+    }
+
+    fn declare(&mut self, declaration: Declaration) -> Result<(), String> {
+        match declaration {
+            Declaration::CallShot { ball, pocket } => self.call_shot(ball, pocket),
+            Declaration::CallPocket(_) => Err("call a ball as well as the pocket".to_string()),
+            Declaration::PushOut | Declaration::Pass => Err("there is no push out in straight pool".to_string()),
+        }
+    }
+
+    fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome {
+        StraightPoolRules::evaluate(self, log)
+    }
+
+    fn report(&self, state: &mut GameState) {
+        state.scores = self.scores;
+        state.innings = self.innings;
+        state.breaking = self.breaking;
+        state.consecutive_fouls = self.consecutive_fouls;
+        state.called_ball = self.called_shot.map(|(ball, _)| ball);
+        state.called_pocket = self.called_shot.map(|(_, pocket)| pocket);
+    }
+
+    fn restore(&mut self, state: &GameState, on_table: &[u32]) {
+        self.shooter = state.whose_move.clone();
+        self.on_table = on_table.to_vec();
+        self.scores = state.scores;
+        self.innings = state.innings;
+        self.breaking = state.breaking;
+        self.consecutive_fouls = state.consecutive_fouls;
+        self.called_shot = state.called_ball.zip(state.called_pocket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_support::{contact, rail, pot, round_trip};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn mid_game() -> StraightPoolRules {
+        StraightPoolRules { breaking: false, ..StraightPoolRules::new(WhoseMove::Player1, 100) }
+    }
+
+    fn shoot(rules: &mut StraightPoolRules, call: Option<(u32, Pocket)>, events: Vec<ShotEvent>) -> ShotOutcome {
+        if let Some((ball, pocket)) = call {
+            rules.call_shot(ball, pocket).unwrap();
+        }
+        rules.evaluate(&ShotLog { events })
+    }
+
+    #[test]
+    fn called_ball_scores_with_everything_else_that_drops() {
+        let mut rules = mid_game();
+        let outcome = shoot(&mut rules, Some((5, Pocket::FootLeft)), vec![contact(0, 5), contact(5, 6), pot(5, Pocket::FootLeft), pot(6, Pocket::SideLeft)]);
+        assert_eq!(outcome.points, 2);
+        assert_eq!(rules.scores, [2, 0]);
+        assert_eq!(outcome.next_shooter, WhoseMove::Player1);
+        assert_eq!(rules.innings, 1);
+    }
+
+    #[test]
+    fn uncalled_balls_are_spotted_and_the_inning_ends() {
+        let mut rules = mid_game();
+        let outcome = shoot(&mut rules, Some((5, Pocket::FootLeft)), vec![contact(0, 5), pot(5, Pocket::FootRight)]);
+        assert!(outcome.fouls.is_empty());
+        assert_eq!(outcome.points, 0);
+        assert_eq!(outcome.respotted, vec![5]);
+        assert!(rules.on_table.contains(&5));
+        assert_eq!(outcome.next_shooter, WhoseMove::Player2);
+        assert_eq!(rules.innings, 2);
+    }
+
+    #[test]
+    fn fouls_cost_a_point_and_the_third_in_a_row_fifteen_more() {
+        let mut rules = mid_game();
+        let miss = vec![contact(0, 5), rail(5)];
+        let foul = vec![contact(0, 5)];
+        for expected in [-1, -2] {
+            let outcome = shoot(&mut rules, None, foul.clone());
+            assert_eq!(outcome.fouls, vec![Foul::NoRailAfterContact]);
+            assert!(!outcome.ball_in_hand, "cue ball stays where it is");
+            assert_eq!(rules.scores[0], expected);
+            shoot(&mut rules, None, miss.clone());
+        }
+        let outcome = shoot(&mut rules, None, foul.clone());
+        assert_eq!(outcome.points, -FOUL_PENALTY - THIRD_FOUL_PENALTY);
+        assert_eq!(rules.scores[0], -18);
+        assert_eq!(rules.consecutive_fouls[0], 0, "the count starts again");
+    }
+
+    #[test]
+    fn a_third_foul_in_a_row_means_breaking_a_new_rack() {
+        let mut rules = StraightPoolRules { consecutive_fouls: [2, 0], on_table: vec![3, 5, 8], ..mid_game() };
+        let outcome = shoot(&mut rules, Some((5, Pocket::FootLeft)), vec![contact(0, 5), pot(8, Pocket::SideLeft), pot(0, Pocket::FootRight)]);
+        assert_eq!(outcome.points, -FOUL_PENALTY - THIRD_FOUL_PENALTY);
+        assert_eq!(outcome.rerack, (1..=BALLS).collect::<Vec<_>>());
+        assert!(outcome.respotted.is_empty(), "the 8 goes in the rack");
+        assert_eq!(outcome.next_shooter, WhoseMove::Player1);
+        assert!(outcome.ball_in_hand);
+        assert!(rules.breaking);
+        assert_eq!(rules.on_table.len(), 15);
+
+        let mut state = GameState::default();
+        rules.report(&mut state);
+        assert!(state.breaking);
+
+        // the break is judged as an opening break
+        let outcome = shoot(&mut rules, None, vec![contact(0, 1), rail(3)]);
+        assert_eq!(outcome.fouls, vec![Foul::IllegalBreak { balls_to_rail: 1 }]);
+        assert!(!rules.breaking);
+    }
+
+    #[test]
+    fn running_out_of_time_is_a_foul() {
+        let mut rules = mid_game();
+        let outcome = shoot(&mut rules, Some((5, Pocket::FootLeft)), vec![ShotEvent::ShotClockExpired]);
+        assert_eq!(outcome.fouls, vec![Foul::ShotClockExpired]);
+        assert_eq!(rules.scores[0], -1);
+        assert_eq!(outcome.next_shooter, WhoseMove::Player2);
+    }
+
+    #[test]
+    fn scratch_gives_ball_in_hand_and_spots_the_balls() {
+        let mut rules = mid_game();
+        let outcome = shoot(&mut rules, Some((5, Pocket::FootLeft)), vec![contact(0, 5), pot(5, Pocket::FootLeft), pot(0, Pocket::SideRight)]);
+        assert_eq!(outcome.fouls, vec![Foul::CueBallPocketed]);
+        assert_eq!(outcome.points, -1);
+        assert_eq!(outcome.respotted, vec![5]);
+        assert!(outcome.ball_in_hand);
+    }
+
+    #[test]
+    fn opening_break_needs_two_balls_and_the_cue_ball_to_a_rail() {
+        let mut rules = StraightPoolRules::new(WhoseMove::Player1, 100);
+        let outcome = shoot(&mut rules, None, vec![contact(0, 1), rail(3), rail(7), rail(0)]);
+        assert!(outcome.fouls.is_empty());
+        assert_eq!(outcome.next_shooter, WhoseMove::Player2);
+
+        let mut rules = StraightPoolRules::new(WhoseMove::Player1, 100);
+        let outcome = shoot(&mut rules, None, vec![contact(0, 1), rail(3), rail(7)]);
+        assert_eq!(outcome.fouls, vec![Foul::IllegalBreak { balls_to_rail: 2 }]);
+        assert_eq!(outcome.points, -BREAK_FOUL_PENALTY);
+
+        let mut rules = StraightPoolRules::new(WhoseMove::Player1, 100);
+        let outcome = shoot(&mut rules, Some((9, Pocket::FootRight)), vec![contact(0, 1), pot(9, Pocket::FootRight)]);
+        assert!(outcome.fouls.is_empty(), "a called ball makes the break legal");
+        assert_eq!(outcome.points, 1);
+    }
+
+    #[test]
+    fn last_ball_brings_a_rerack_of_fourteen() {
+        let mut rules = StraightPoolRules { on_table: vec![4, 11], ..mid_game() };
+        let outcome = shoot(&mut rules, Some((4, Pocket::HeadLeft)), vec![contact(0, 4), pot(4, Pocket::HeadLeft)]);
+        assert_eq!(outcome.rerack.len(), 14);
+        assert!(!outcome.rerack.contains(&11));
+        assert_eq!(rules.on_table.len(), 15);
+        assert_eq!(outcome.next_shooter, WhoseMove::Player1);
+
+        let spec = TableSpec::default();
+        let apex = rack::triangle(spec.foot_spot(), 5)[0];
+        let racked = rules.rerack(&spec, &outcome.rerack, &mut StdRng::seed_from_u64(14));
+        assert_eq!(racked.len(), 14);
+        assert!(racked.iter().all(|(_, p)| p.distance(apex) > 0.01), "apex is left open");
+    }
+
+    #[test]
+    fn scores_and_the_call_are_picked_up_from_the_game_state() {
+        let mut rules = StraightPoolRules { on_table: vec![4, 7, 11], scores: [23, -2], innings: 6, consecutive_fouls: [0, 2], ..mid_game() };
+        rules.call_shot(7, Pocket::SideRight).unwrap();
+        assert_eq!(round_trip(&rules, StraightPoolRules::new(WhoseMove::Player2, 100), &rules.on_table), rules);
+    }
+
+    #[test]
+    fn reaching_the_target_wins() {
+        let mut rules = StraightPoolRules { target_score: 10, scores: [9, 3], ..mid_game() };
+        let outcome = shoot(&mut rules, Some((5, Pocket::FootLeft)), vec![contact(0, 5), pot(5, Pocket::FootLeft)]);
+        assert_eq!(outcome.result, Some(GameResult { winner: WhoseMove::Player1, reason: WinReason::TargetScoreReached }));
+    }
+}
@@ -0,0 +1,302 @@
+// src/rules/ten_ball.rs
+// WPA ten-ball. Played like nine-ball (lowest ball first, push-out after
+// the break, three fouls lose) except that every shot after the break is
+// called: the shooter names a ball and a pocket. Balls that drop without
+// being called stay down, but the turn ends and the opponent may take the
+// table or hand it back. The 10 only wins when it is called; otherwise it
+// is spotted, including on the break.
+use bevy::prelude::*;
+use rand::RngCore;
+
+use super::super::{GameState, Pocket, TableSpec, WhoseMove};
+use super::nine_ball::{rotation_fouls, FOUL_LIMIT};
+use super::{rack, Declaration, GameResult, Ruleset, ShotLog, ShotOutcome, WinReason};
+
+pub const TEN_BALL: u32 = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TenBallRules {
+    pub shooter: WhoseMove,
+    /// Object balls still on the table
+    pub on_table: Vec<u32>,
+    /// Fouls in a row, per player
+    pub consecutive_fouls: [u8; 2],
+    pub breaking: bool,
+    pub push_out_available: bool,
+    pub push_out_declared: bool,
+    /// The shooter may hand the table back, after a push-out or after the
+    /// opponent pocketed a ball they did not call
+    pub may_pass: bool,
+    /// Ball and pocket named for the coming shot
+    pub called_shot: Option<(u32, Pocket)>,
+}
+
+impl TenBallRules {
+    pub fn new(breaker: WhoseMove) -> Self {
+        TenBallRules {
+            shooter: breaker,
+            on_table: (1..=TEN_BALL).collect(),
+            consecutive_fouls: [0; 2],
+            breaking: true,
+            push_out_available: false,
+            push_out_declared: false,
+            may_pass: false,
+            called_shot: None,
+        }
+    }
+
+    pub fn lowest_ball(&self) -> u32 {
+        self.on_table.iter().copied().min().unwrap_or(TEN_BALL)
+    }
+
+    /// Name the ball and pocket for the coming shot.
+    pub fn call_shot(&mut self, ball: u32, pocket: Pocket) -> Result<(), String> {
+        if self.breaking {
+            return Err("the break is not called".to_string());
+        }
+        if self.push_out_declared {
+            return Err("a push out is not called".to_string());
+        }
+        if !self.on_table.contains(&ball) {
+            return Err(format!("the {} is not on the table", ball));
+        }
+        self.called_shot = Some((ball, pocket));
+        Ok(())
+    }
+
+    pub fn declare_push_out(&mut self) -> Result<(), String> {
+        if !self.push_out_available {
+            return Err("a push out is only allowed on the shot after the break".to_string());
+        }
+        self.push_out_declared = true;
+        self.called_shot = None;
+        Ok(())
+    }
+
+    /// Hand the table back to the opponent.
+    pub fn pass(&mut self) -> Result<WhoseMove, String> {
+        if !self.may_pass {
+            return Err("can only pass after a push out or an uncalled ball".to_string());
+        }
+        self.may_pass = false;
+        self.shooter = self.shooter.other();
+        Ok(self.shooter.clone())
+    }
+
+    pub fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome {
+        let shooter = self.shooter.clone();
+        let push_out = self.push_out_declared;
+        let breaking = self.breaking;
+        let called_shot = self.called_shot.take();
+        let fouls = rotation_fouls(log, self.lowest_ball(), breaking, push_out);
+        let jumped = log.off_table();
+
+        let pocketed = log.object_balls_pocketed();
+        self.on_table.retain(|ball| !pocketed.contains(ball) && !jumped.contains(ball));
+        let foul = !fouls.is_empty();
+
+        let called_made = called_shot.is_some_and(|(ball, pocket)| {
+            log.pocketed().iter().any(|p| p.number == ball && p.pocket == pocket)
+        });
+
+        let mut result = None;
+        let mut respotted = Vec::new();
+        if pocketed.contains(&TEN_BALL) || jumped.contains(&TEN_BALL) {
+            if !foul && called_made && called_shot.map(|(ball, _)| ball) == Some(TEN_BALL) {
+                result = Some(GameResult { winner: shooter.clone(), reason: WinReason::TenBallPocketed });
+            } else {
+                respotted.push(TEN_BALL);
+                self.on_table.push(TEN_BALL);
+            }
+        }
+
+        let fouls_in_a_row = &mut self.consecutive_fouls[shooter.index()];
+        if foul {
+            *fouls_in_a_row += 1;
+            if *fouls_in_a_row >= FOUL_LIMIT {
+                result = Some(GameResult { winner: shooter.other(), reason: WinReason::ThreeConsecutiveFouls });
+            }
+        } else {
+            *fouls_in_a_row = 0;
+        }
+
+        let legal = !foul && !push_out;
+        let keeps_table = legal && if breaking { !pocketed.is_empty() } else { called_made };
+        // balls that dropped without being called give the opponent the option
+        let uncalled = legal && !breaking && !called_made && !pocketed.is_empty();
+        let next_shooter = if keeps_table { shooter.clone() } else { shooter.other() };
+
+        self.push_out_available = breaking && !foul && result.is_none();
+        self.may_pass = ((push_out && !foul) || uncalled) && result.is_none();
+        self.breaking = false;
+        self.push_out_declared = false;
+        self.shooter = next_shooter.clone();
+
+        ShotOutcome {
+            shooter,
+            fouls,
+            pocketed: log.pocketed(),
+            respotted,
+            rerack: Vec::new(),
+            points: 0,
+            push_out,
+            next_shooter,
+            ball_in_hand: foul && result.is_none(),
+            result,
+        }
+    }
+}
+
+impl Ruleset for TenBallRules {
+    fn shooter(&self) -> WhoseMove {
+        self.shooter.clone()
+    }
+
+    fn target_ball(&self) -> Option<u32> {
+        Some(self.lowest_ball())
+    }
+
+    // the 1 at the apex on the foot spot, the 10 in the middle of the third
+    // row and the 2 and 3 on the back corners
+    fn rack(&self, spec: &TableSpec, rng: &mut dyn RngCore) -> Vec<(u32, Vec3)> {
+        let positions = rack::triangle(spec.foot_spot(), 4);
+        rack::fill(&positions, &[(1, 0), (TEN_BALL, 4), (2, 6), (3, 9)], &[4, 5, 6, 7, 8, 9], rng)
+    }
+
+    fn new_rack(&mut self, breaker: WhoseMove) {
+        *self = TenBallRules::new(breaker);
+    }
+
+    fn declare(&mut self, declaration: Declaration) -> Result<(), String> {
+        match declaration {
+            Declaration::PushOut => self.declare_push_out(),
+            Declaration::Pass => self.pass().map(|_| ()),
+            Declaration::CallShot { ball, pocket } => self.call_shot(ball, pocket),
+            Declaration::CallPocket(_) => Err("call a ball as well as the pocket".to_string()),
+        }
+    }
+
+    fn evaluate(&mut self, log: &ShotLog) -> ShotOutcome {
+        TenBallRules::evaluate(self, log)
+    }
+
+    fn report(&self, state: &mut GameState) {
+        state.consecutive_fouls = self.consecutive_fouls;
+        state.push_out_available = self.push_out_available;
+        state.push_out_declared = self.push_out_declared;
+        state.may_pass = self.may_pass;
+        state.called_ball = self.called_shot.map(|(ball, _)| ball);
+        state.called_pocket = self.called_shot.map(|(_, pocket)| pocket);
+        state.breaking = self.breaking;
+    }
+
+    fn restore(&mut self, state: &GameState, on_table: &[u32]) {
+        self.shooter = state.whose_move.clone();
+        self.on_table = on_table.to_vec();
+        self.consecutive_fouls = state.consecutive_fouls;
+        self.push_out_available = state.push_out_available;
+        self.push_out_declared = state.push_out_declared;
+        self.may_pass = state.may_pass;
+        self.called_shot = state.called_ball.zip(state.called_pocket);
+        self.breaking = state.breaking;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Foul;
+    use super::*;
+    use super::super::test_support::{contact, rail, pot, round_trip};
+
+    fn mid_game() -> TenBallRules {
+        TenBallRules { breaking: false, ..TenBallRules::new(WhoseMove::Player1) }
+    }
+
+    fn calling(ball: u32, pocket: Pocket) -> TenBallRules {
+        let mut rules = mid_game();
+        rules.call_shot(ball, pocket).unwrap();
+        rules
+    }
+
+    #[test]
+    fn a_called_push_out_is_picked_up_from_the_game_state() {
+        let mut rules = TenBallRules { on_table: vec![2, 6, 10], consecutive_fouls: [0, 1], push_out_available: true, ..mid_game() };
+        rules.declare_push_out().unwrap();
+        assert_eq!(round_trip(&rules, TenBallRules::new(WhoseMove::Player2), &rules.on_table), rules);
+    }
+
+    #[test]
+    fn called_ball_in_called_pocket_keeps_the_table() {
+        let mut rules = calling(1, Pocket::FootLeft);
+        let outcome = rules.evaluate(&ShotLog { events: vec![contact(0, 1), pot(1, Pocket::FootLeft)] });
+        assert_eq!(outcome.next_shooter, WhoseMove::Player1);
+        assert!(!rules.may_pass);
+        assert_eq!(rules.called_shot, None, "a call is for one shot");
+    }
+
+    #[test]
+    fn uncalled_ball_gives_the_opponent_the_option() {
+        for (call, events) in [
+            (Some((1, Pocket::FootLeft)), vec![contact(0, 1), pot(1, Pocket::FootRight)]),
+            (Some((1, Pocket::FootLeft)), vec![contact(0, 1), contact(1, 5), pot(5, Pocket::SideLeft), rail(1)]),
+            (None, vec![contact(0, 1), pot(1, Pocket::FootLeft)]),
+        ] {
+            let mut rules = mid_game();
+            rules.called_shot = call;
+            let outcome = rules.evaluate(&ShotLog { events });
+            assert!(outcome.fouls.is_empty());
+            assert!(!outcome.ball_in_hand);
+            assert_eq!(outcome.next_shooter, WhoseMove::Player2);
+            // the balls stay down
+            assert_eq!(rules.lowest_ball(), if outcome.pocketed[0].number == 1 { 2 } else { 1 });
+            assert!(rules.may_pass);
+            assert_eq!(rules.pass(), Ok(WhoseMove::Player1));
+        }
+    }
+
+    #[test]
+    fn missing_the_call_just_ends_the_turn() {
+        let mut rules = calling(1, Pocket::FootLeft);
+        let outcome = rules.evaluate(&ShotLog { events: vec![contact(0, 1), rail(1)] });
+        assert_eq!(outcome.next_shooter, WhoseMove::Player2);
+        assert!(!rules.may_pass);
+    }
+
+    #[test]
+    fn ten_wins_only_when_called() {
+        let mut rules = calling(TEN_BALL, Pocket::SideRight);
+        let outcome = rules.evaluate(&ShotLog { events: vec![contact(0, 1), contact(1, 10), pot(10, Pocket::SideRight)] });
+        assert_eq!(outcome.result, Some(GameResult { winner: WhoseMove::Player1, reason: WinReason::TenBallPocketed }));
+
+        let mut rules = calling(1, Pocket::FootLeft);
+        let outcome = rules.evaluate(&ShotLog { events: vec![contact(0, 1), contact(1, 10), pot(10, Pocket::SideRight)] });
+        assert_eq!(outcome.result, None);
+        assert_eq!(outcome.respotted, vec![TEN_BALL]);
+
+        let mut rules = TenBallRules::new(WhoseMove::Player1);
+        let outcome = rules.evaluate(&ShotLog { events: vec![contact(0, 1), pot(10, Pocket::FootLeft)] });
+        assert_eq!(outcome.result, None, "the 10 on the break is spotted");
+        assert_eq!(outcome.respotted, vec![TEN_BALL]);
+        assert_eq!(outcome.next_shooter, WhoseMove::Player1);
+    }
+
+    #[test]
+    fn ten_on_a_foul_is_spotted() {
+        let mut rules = calling(TEN_BALL, Pocket::SideRight);
+        let outcome = rules.evaluate(&ShotLog { events: vec![contact(0, 1), contact(1, 10), pot(10, Pocket::SideRight), pot(0, Pocket::HeadLeft)] });
+        assert_eq!(outcome.fouls, vec![Foul::CueBallPocketed]);
+        assert_eq!(outcome.result, None);
+        assert_eq!(outcome.respotted, vec![TEN_BALL]);
+        assert!(outcome.ball_in_hand);
+        assert!(!rules.may_pass);
+    }
+
+    #[test]
+    fn call_must_name_a_ball_on_the_table() {
+        let mut rules = mid_game();
+        rules.on_table.retain(|&ball| ball != 3);
+        assert!(rules.call_shot(3, Pocket::FootLeft).is_err());
+        assert!(rules.call_shot(4, Pocket::FootLeft).is_ok());
+        assert!(TenBallRules::new(WhoseMove::Player1).call_shot(1, Pocket::FootLeft).is_err());
+    }
+}
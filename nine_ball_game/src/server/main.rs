@@ -1,13 +1,13 @@
 use bevy::state::app::StatesPlugin;
-use bevy::{prelude::*, scene::ScenePlugin};
+use bevy::prelude::*;
 use bevy::app::ScheduleRunnerPlugin;
 use bevy_rapier3d::prelude::*;
-use clap::Parser;
-use rand::Rng;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use std::time::Duration;
 use tokio::sync::{mpsc, broadcast};
 use axum::{
-    extract::{ws::{Message, WebSocket, WebSocketUpgrade}},
+    extract::{ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade}},
     response::IntoResponse,
     routing::any,
     Router,
@@ -15,26 +15,88 @@ use axum::{
 use futures_util::{StreamExt, SinkExt};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::collections::{HashMap, VecDeque};
 use bevy::prelude::{Res,State};
-#[path = "../lib.rs"] 
-mod root_logic;
-use root_logic::ClientMessage;
+use nine_ball_game::{ClientMessage, Seat, ServerMessage, TableSpec, PROTOCOL_VERSION};
+use nine_ball_game::physics::cue;
+use nine_ball_game::snapshot::{SentSnapshots, TickState};
+use nine_ball_game::simulation::{ball_at_rest, judge_shot, rack_balls, spawn_cue_ball, spawn_table, AnyBall, CueBall, PoolBalls, RackRng, Referee, ShotRecorder, SimulationPlugin};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use nine_ball_game::trajectory::TrajectoryRecorder;
+use ws_gateway::SessionId;
+mod report;
+mod ws_gateway;
+
+use nine_ball_game::rules::{eight_ball::EightBallRules, match_play::{BreakRule, MatchScore}, nine_ball::NineBallRules, one_pocket::OnePocketRules, straight_pool::StraightPoolRules, ten_ball::TenBallRules, Declaration, ShotEvent, ShotOutcome};
 
 // --- 1. DEFINE RESOURCES ---
 
+// One WebSocket connection: numbered in the order they arrive, with the
+// seat its token gave it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Session {
+    pub id: SessionId,
+    pub seat: Seat,
+}
+
+// Client bytes, tagged with the session they came in on
 #[derive(Resource)]
-pub struct BrowserInbound(pub mpsc::UnboundedReceiver<Vec<u8>>);
+pub struct BrowserInbound(pub mpsc::UnboundedReceiver<(Session, Vec<u8>)>);
+
+// A socket finishing its handshake, or going away
+#[derive(Debug, Clone, Copy)]
+pub enum SeatEvent {
+    Joined(Seat),
+    Left(Seat),
+}
 
 #[derive(Resource)]
-pub struct BrowserOutbound(pub broadcast::Sender<Vec<u8>>);
+pub struct SeatEvents(pub mpsc::UnboundedReceiver<SeatEvent>);
+
+// What Bevy hands the sockets: bincode ServerMessages for every client or
+// for one seat only, and game states each socket encodes for its own client
+#[derive(Debug, Clone)]
+pub enum Outbound {
+    Message(Option<Seat>, Vec<u8>),
+    // time is seconds since the server started
+    State { tick: u32, time: f64, state: Arc<TickState>, keyframe: bool },
+}
 
 #[derive(Resource)]
+pub struct BrowserOutbound(pub broadcast::Sender<Outbound>);
+
+#[derive(Resource, Clone)]
 pub struct GameTokens {
     pub p1: String,
     pub p2: String,
     pub match_id: String,
 }
 
+impl GameTokens {
+    /// No tokens configured: a local game where one browser plays both sides
+    pub fn hot_seat(&self) -> bool {
+        self.p1.is_empty() && self.p2.is_empty()
+    }
+
+    /// The seat a connection's ?token= puts it in. No token watches; a token
+    /// that is neither player's is refused.
+    pub fn seat_for(&self, token: &str) -> Option<Seat> {
+        if self.hot_seat() {
+            return Some(Seat::Player1);
+        }
+        if token.is_empty() {
+            Some(Seat::Spectator)
+        } else if token == self.p1 {
+            Some(Seat::Player1)
+        } else if token == self.p2 {
+            Some(Seat::Player2)
+        } else {
+            None
+        }
+    }
+}
+
 // --- 2. CLI ARGUMENTS ---
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -50,69 +112,182 @@ struct Args {
 
     #[arg(long, default_value = "")]
     match_id: String,
+
+    /// Built-in table ("7ft", "8ft", "9ft") or a path to a .toml/.ron TableSpec
+    #[arg(long, default_value = "9ft")]
+    table: String,
+
+    /// Which game to play
+    #[arg(long, value_enum, default_value_t = GameVariant::nine_ball)]
+    variant: GameVariant,
+
+    /// Racks needed to win the match
+    #[arg(long, default_value_t = 1)]
+    race_to: u32,
+
+    /// Who breaks after the first rack: "alternate" or "winner"
+    #[arg(long, default_value = "alternate")]
+    breaks: BreakRule,
+
+    /// nineballnet endpoint the match result is POSTed to when the match is
+    /// over, signed with the secret in MATCH_RESULT_SECRET, which must then
+    /// be set. Not reported when empty.
+    #[arg(long, default_value = "")]
+    result_url: String,
+
+    /// Points needed to win a game of straight pool
+    #[arg(long, default_value_t = 100)]
+    target_score: i32,
+
+    /// Seconds a player who drops out has to reconnect before forfeiting the match
+    #[arg(long, default_value_t = 60)]
+    reconnect_grace: u64,
+
+    /// Seconds spectators see the game behind the players, so a player
+    /// cannot watch the spectator stream for help
+    #[arg(long, default_value_t = 0)]
+    spectator_delay: u64,
+
+    /// Seconds the shooter has for each shot; 0 turns the shot clock off
+    #[arg(long, default_value_t = 30)]
+    shot_clock: u64,
+
+    /// Seconds an extension adds to the shot clock; each player has one per rack
+    #[arg(long, default_value_t = 30)]
+    extension: u64,
+
+    /// Seconds without a move from either player before the match is
+    /// abandoned; 0 for no limit
+    #[arg(long, default_value_t = 600)]
+    inactivity_limit: u64,
+
+    /// Resolve each shot as fast as the physics can be stepped and send it
+    /// to the clients as one replay, instead of streaming it as it rolls
+    #[arg(long)]
+    instant_shots: bool,
+
+    /// Host many matches in this one process: players connect to
+    /// /match/<match_id> on --port, and matches are created and closed
+    /// through an HTTP API on 127.0.0.1 at this port. Every other option is
+    /// then given per match when it is created.
+    #[arg(long)]
+    control_port: Option<u16>,
+}
+
+fn load_table_spec(table: &str) -> Result<TableSpec, String> {
+    if let Some(spec) = TableSpec::preset(table) {
+        return Ok(spec);
+    }
+
+    let path = std::path::Path::new(table);
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    if !matches!(extension, Some("toml") | Some("ron")) {
+        return Err(format!("unknown table {}: expected 7ft, 8ft, 9ft or a .toml/.ron file", table));
+    }
+    let text = std::fs::read_to_string(path).map_err(|e| format!("could not read table spec {}: {}", table, e))?;
+    let spec: TableSpec = if extension == Some("toml") {
+        toml::from_str(&text).map_err(|e| e.to_string())?
+    } else {
+        ron::from_str(&text).map_err(|e| e.to_string())?
+    };
+    spec.validate()?;
+    Ok(spec)
 }
 
 // --- 3. MAIN ENTRY POINT ---
 fn main() {
     let args = Args::parse();
+    if let Some(control_port) = args.control_port {
+        run_match_host(args.port, control_port);
+        return;
+    }
     println!("Server starting on port {} | P1: {} | P2: {} | match_id: {}", args.port, args.p1_token, args.p2_token, args.match_id);
 
+    let port = args.port;
+    let instant_shots = args.instant_shots;
+    let (mut app, network) = match build_match(args) {
+        Ok(built) => built,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    // -- B. Start WebSocket Server --
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            start_network_listener(port, network).await;
+        });
+    });
+
+    // only one app per process may set up logging
+    app.add_plugins(bevy::log::LogPlugin::default());
+    if instant_shots {
+        app.add_systems(Last, pace_frames.after(record_replay));
+    }
+    app.run();
+}
+
+// One match: its Bevy app, and the channels its sockets reach it through
+fn build_match(args: Args) -> Result<(App, NetworkState), String> {
+    let table_spec = load_table_spec(&args.table).map_err(|e| format!("Invalid --table: {}", e))?;
+    // nineballnet refuses results signed with an empty key, every time
+    let result_secret = std::env::var(report::SECRET_ENV).unwrap_or_default();
+    if !args.result_url.is_empty() && result_secret.is_empty() {
+        return Err(format!("--result-url is set but {} is not, so results could not be signed", report::SECRET_ENV));
+    }
+    println!("Table: {:?}", table_spec);
+    println!("Variant: {:?} | race to {} | breaks: {:?}", args.variant, args.race_to, args.breaks);
+
+    let game_tokens = GameTokens {
+        p1: args.p1_token,
+        p2: args.p2_token,
+        match_id: args.match_id
+    };
+
     // -- A. Setup Channels --
     
     // 1. INBOUND (Clients -> Bevy): Standard MPSC (Many inputs, one consumer)
     let (tx_to_bevy, rx_to_bevy) = mpsc::unbounded_channel();
+    // Seats coming and going, for presence tracking
+    let (tx_seat_events, rx_seat_events) = mpsc::unbounded_channel();
     
     // 2. OUTBOUND (Bevy -> Clients): BROADCAST (One producer, many listeners)
     // Capacity 100 prevents laggy clients from crashing the server
-    let (tx_from_bevy, _) = broadcast::channel::<Vec<u8>>(100);
-
-    // -- B. Start WebSocket Server --
-    let port = args.port;
-    let tx_to_bevy_clone = tx_to_bevy.clone();
-    let tx_from_bevy_clone = tx_from_bevy.clone();
+    let (tx_from_bevy, _) = broadcast::channel::<Outbound>(100);
 
-    std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async {
-            start_network_listener(port, tx_to_bevy_clone, tx_from_bevy_clone).await;
-        });
-    });
+    let network = NetworkState {
+        to_bevy: tx_to_bevy,
+        seat_events: tx_seat_events,
+        from_bevy_broadcast: tx_from_bevy.clone(),
+        tokens: Arc::new(game_tokens.clone()),
+        table: Arc::new(table_spec.clone()),
+        spectator_delay: Duration::from_secs(args.spectator_delay),
+    };
 
     // -- C. Setup Bevy App --
     let mut app = App::new();
 
-    app.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0))));
-
-    app.add_plugins((
-        AssetPlugin::default(),
-        HierarchyPlugin::default(),
-        TransformPlugin::default(),
-        ScenePlugin::default(),
-        bevy::log::LogPlugin::default(),
-        StatesPlugin
-    ));
-
-    app.init_asset::<Mesh>();
-    app.init_asset::<Scene>();
-    app.init_asset::<StandardMaterial>();
-
-    app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default()).insert_resource(RapierConfiguration {
-           gravity: Vec3::new(0.0, -9.81, 0.0),
-           timestep_mode: TimestepMode::Fixed { dt: 1.0 / 60.0, substeps: 1 },
-           physics_pipeline_active: true,
-           query_pipeline_active: true,
-           scaled_shape_subdivision: 2, 
-           force_update_from_transform_changes: false, 
-       });
+    // with instant shots, pace_frames keeps to 60 Hz except while a shot is resolved
+    let frame_wait = if args.instant_shots { Duration::ZERO } else { FRAME };
+    app.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(frame_wait)));
+
+    app.add_plugins((SimulationPlugin, StatesPlugin));
 
     // Insert Resources
     app.insert_resource(BrowserInbound(rx_to_bevy));
+    app.insert_resource(SeatEvents(rx_seat_events));
+    app.insert_resource(Presence::new(Duration::from_secs(args.reconnect_grace)));
+    app.insert_resource(ShotClock::new(Duration::from_secs(args.shot_clock), Duration::from_secs(args.extension)));
+    app.insert_resource(Inactivity { last_move: std::time::Instant::now(), limit: Duration::from_secs(args.inactivity_limit) });
     app.insert_resource(BrowserOutbound(tx_from_bevy)); // Bevy gets the Sender
-    app.insert_resource(GameTokens {
-        p1: args.p1_token,
-        p2: args.p2_token,
-        match_id: args.match_id
-    });
+    app.insert_resource(SnapshotTicks::new());
+    app.insert_resource(ShotReplay::new(args.instant_shots));
+    app.insert_resource(game_tokens);
+    app.insert_resource(table_spec);
+    // every match racks from its own generator
+    app.insert_resource(RackRng(StdRng::from_os_rng()));
 
     // Add your game logic
     // app.add_plugins(server::NineBallServerPlugin); 
@@ -121,35 +296,298 @@ fn main() {
        .insert_state(WhoseMove::Player1)
        .insert_state(GamePhase::PreShot);
     app.add_systems(Update, broadcast_state_to_clients)
-    .add_systems(Update, handle_incoming_network_messages);
+    .add_systems(Update, handle_incoming_network_messages)
+    .add_systems(Update, track_presence)
+    .add_systems(Update, (run_shot_clock, abandon_inactive_match))
+    .add_systems(Update, start_replay.after(handle_incoming_network_messages))
+    .add_systems(Last, record_replay)
+    .add_systems(OnEnter(GamePhase::PostShot), send_replay.after(carry_out_outcome));
 
     app.insert_resource(GameState::default());
     app.add_systems(Update, update_gamestate);
-    app.add_plugins(NineBallRuleset);
-    
-    app.run();
+    app.insert_resource(MatchScore::new(args.race_to, args.breaks, WhoseMove::Player1));
+    app.insert_resource(ResultReporter {
+        url: args.result_url,
+        secret: result_secret,
+        sending: None,
+    });
+    match args.variant {
+        GameVariant::nine_ball => app.add_plugins(NineBallRuleset),
+        GameVariant::eight_ball => app.add_plugins(EightBallRuleset),
+        GameVariant::ten_ball => app.add_plugins(TenBallRuleset),
+        GameVariant::straight_pool => app.add_plugins(StraightPoolRuleset { target_score: args.target_score }),
+        GameVariant::one_pocket => app.add_plugins(OnePocketRuleset),
+    };
+
+    Ok((app, network))
+}
+
+// --control-port: many matches in one process. Every match is its own Bevy
+// app, so their worlds and Rapier pipelines never meet; this thread steps
+// them all at 60 Hz. Sockets reach them through ws_gateway.
+struct MatchHost {
+    gateway: ws_gateway::Gateway,
+    matches: HashMap<String, App>,
+}
+
+// Options that belong to the whole hosting process, not to one match
+const PROCESS_ARGS: [&str; 2] = ["port", "control_port"];
+
+impl MatchHost {
+    fn create(&mut self, match_id: String, args: Vec<String>) -> Result<(), String> {
+        if self.matches.contains_key(&match_id) {
+            return Err(format!("match {} already exists", match_id));
+        }
+        let given = Args::command().try_get_matches_from(std::iter::once("server".to_string()).chain(args)).map_err(|e| e.to_string())?;
+        if let Some(arg) = PROCESS_ARGS.iter().find(|arg| given.value_source(arg) == Some(ValueSource::CommandLine)) {
+            return Err(format!("--{} is set for the whole server, not per match", arg.replace('_', "-")));
+        }
+        let mut args = Args::from_arg_matches(&given).map_err(|e| e.to_string())?;
+        args.match_id = match_id.clone();
+        let (mut app, network) = build_match(args)?;
+        // the runner never runs here, so finish the plugins by hand
+        app.finish();
+        app.cleanup();
+        self.gateway.add_match(match_id.clone(), network);
+        self.matches.insert(match_id.clone(), app);
+        println!("Match {} created, {} hosted", match_id, self.matches.len());
+        Ok(())
+    }
+
+    // Whatever the control API has asked for since the last frame
+    fn run_commands(&mut self, commands: &std::sync::mpsc::Receiver<ws_gateway::ControlCommand>) {
+        while let Ok(command) = commands.try_recv() {
+            match command {
+                ws_gateway::ControlCommand::Create { match_id, args, reply } => {
+                    let _ = reply.send(self.create(match_id, args));
+                }
+                ws_gateway::ControlCommand::Destroy { match_id, reply } => {
+                    let _ = reply.send(self.destroy(&match_id, Duration::ZERO));
+                }
+            }
+        }
+    }
+
+    fn destroy(&mut self, match_id: &str, linger: Duration) -> bool {
+        self.gateway.remove_match(match_id, linger);
+        let removed = self.matches.remove(match_id).is_some();
+        if removed {
+            println!("Match {} closed, {} hosted", match_id, self.matches.len());
+        }
+        removed
+    }
+
+    // One frame for every match, then whatever is left of it for matches
+    // resolving an instant shot
+    fn tick(&mut self, deadline: std::time::Instant) {
+        let mut finished = Vec::new();
+        for (match_id, app) in self.matches.iter_mut() {
+            app.update();
+            if app.should_exit().is_some() {
+                finished.push(match_id.clone());
+            }
+        }
+        while std::time::Instant::now() < deadline {
+            let mut resolving = self.matches.values_mut().filter(|app| app.world().resource::<ShotReplay>().resolving()).peekable();
+            if resolving.peek().is_none() {
+                break;
+            }
+            resolving.for_each(|app| app.update());
+        }
+        // give the last messages time to reach the players
+        for match_id in finished {
+            self.destroy(&match_id, MATCH_OVER_LINGER);
+        }
+    }
+}
+
+// How long the sockets of a finished match stay open
+const MATCH_OVER_LINGER: Duration = Duration::from_secs(5);
+
+fn run_match_host(port: u16, control_port: u16) {
+    let (commands, command_rx) = std::sync::mpsc::channel();
+    let (gateway_tx, gateway_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let gateway = ws_gateway::Gateway::new(rt.handle().clone());
+        let _ = gateway_tx.send(gateway.clone());
+        rt.block_on(ws_gateway::serve(port, control_port, gateway, commands));
+    });
+    let Ok(gateway) = gateway_rx.recv() else {
+        return;
+    };
+    println!("Hosting matches on port {}, control API on 127.0.0.1:{}", port, control_port);
+
+    let mut host = MatchHost { gateway, matches: HashMap::new() };
+    loop {
+        let deadline = std::time::Instant::now() + FRAME;
+        host.run_commands(&command_rx);
+        host.tick(deadline);
+        std::thread::sleep(deadline.saturating_duration_since(std::time::Instant::now()));
+    }
 }
 
 // Add this component/system to send updates
+// A full state goes out at least this often, even with nothing moving
+const KEYFRAME_INTERVAL: Duration = Duration::from_secs(2);
+
+// The state last handed to the sockets, so frames where nothing changed
+// send nothing
+#[derive(Resource)]
+struct SnapshotTicks {
+    started: std::time::Instant,
+    tick: u32,
+    last: Option<Arc<TickState>>,
+    last_keyframe: std::time::Instant,
+    // send the state next frame even if it has not changed
+    resend: bool,
+}
+
+impl SnapshotTicks {
+    fn new() -> Self {
+        SnapshotTicks { started: std::time::Instant::now(), tick: 0, last: None, last_keyframe: std::time::Instant::now(), resend: false }
+    }
+
+    fn publish(&mut self, network_out: &BrowserOutbound, state: TickState, keyframe: bool) {
+        let state = Arc::new(state);
+        self.tick += 1;
+        self.last = Some(state.clone());
+        self.resend = false;
+        if keyframe {
+            self.last_keyframe = std::time::Instant::now();
+        }
+        let time = self.started.elapsed().as_secs_f64();
+        let _ = network_out.0.send(Outbound::State { tick: self.tick, time, state, keyframe });
+    }
+}
+
 fn broadcast_state_to_clients(
     game_state: Res<GameState>,
+    mut ticks: ResMut<SnapshotTicks>,
+    replay: Res<ShotReplay>,
+    network_out: Res<BrowserOutbound>,
+) {
+    // a shot being resolved goes out whole, as a replay
+    if replay.resolving() {
+        return;
+    }
+    let state = TickState::new(&game_state);
+    let keyframe = ticks.last_keyframe.elapsed() >= KEYFRAME_INTERVAL;
+    if keyframe || ticks.resend || ticks.last.as_deref() != Some(&state) {
+        ticks.publish(&network_out, state, keyframe);
+    }
+}
+
+// One physics step, and one frame when the server keeps to real time
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// --instant-shots: from the strike until the balls stop, frames run back to
+// back and each physics step is recorded; the clients then get the whole
+// shot in one ShotReplay and play it back at real speed
+#[derive(Resource)]
+struct ShotReplay {
+    enabled: bool,
+    // when the shot being resolved was struck, and the steps so far
+    recording: Option<(std::time::Instant, TrajectoryRecorder)>,
+    // no shot is taken until the clients have shown the last one
+    showing_until: Option<std::time::Instant>,
+}
+
+impl ShotReplay {
+    fn new(enabled: bool) -> Self {
+        ShotReplay { enabled, recording: None, showing_until: None }
+    }
+
+    fn resolving(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    fn showing(&self) -> bool {
+        self.showing_until.is_some_and(|until| std::time::Instant::now() < until)
+    }
+}
+
+fn start_replay(mut shots: EventReader<ShotMade>, mut replay: ResMut<ShotReplay>) {
+    if shots.read().count() > 0 && replay.enabled {
+        replay.recording = Some((std::time::Instant::now(), TrajectoryRecorder::new(FRAME.as_secs_f32())));
+    }
+}
+
+// Once a frame, the balls as update_gamestate last read them
+fn record_replay(gamestate: Res<GameState>, mut replay: ResMut<ShotReplay>) {
+    if let Some((_, recorder)) = replay.recording.as_mut() {
+        recorder.record(&gamestate.balls);
+    }
+}
+
+// Once the outcome is carried out, the shot goes out in one message
+fn send_replay(
+    gamestate: Res<GameState>,
+    ticks: Res<SnapshotTicks>,
+    mut replay: ResMut<ShotReplay>,
     network_out: Res<BrowserOutbound>,
 ) {
-    // 1. Serialize the current GameState
-    // We use a "Match" wrapper or just the raw state, depending on your client expectation.
-    // Based on your client code: bincode::deserialize::<GameState>(&data)
-    match bincode::serialize(&*game_state) {
+    let Some((struck, recorder)) = replay.recording.take() else {
+        return;
+    };
+    let Some(outcome) = gamestate.last_outcome.clone() else {
+        return;
+    };
+    let trajectory = recorder.finish();
+    println!("Shot resolved in {:?}, {} frames over {:.1}s", struck.elapsed(), trajectory.frames.len(), trajectory.duration());
+    replay.showing_until = Some(struck + Duration::from_secs_f32(trajectory.duration()));
+    let start_time = struck.duration_since(ticks.started).as_secs_f64();
+    send_to_clients(&network_out, &ServerMessage::ShotReplay { start_time, trajectory, outcome });
+}
+
+// Keeps the server to 60 Hz when the runner does not, except while a shot
+// is being resolved
+fn pace_frames(replay: Res<ShotReplay>, mut frame_started: Local<Option<std::time::Instant>>) {
+    if replay.enabled && !replay.resolving() {
+        if let Some(started) = *frame_started {
+            std::thread::sleep(FRAME.saturating_sub(started.elapsed()));
+        }
+    }
+    *frame_started = Some(std::time::Instant::now());
+}
+
+fn send_to_clients(network_out: &BrowserOutbound, message: &ServerMessage) {
+    send_to(network_out, None, message);
+}
+
+// Only the sockets in `seat` pass this on
+fn send_to_seat(network_out: &BrowserOutbound, seat: Seat, message: &ServerMessage) {
+    send_to(network_out, Some(seat), message);
+}
+
+fn send_to(network_out: &BrowserOutbound, seat: Option<Seat>, message: &ServerMessage) {
+    match bincode::serialize(message) {
         Ok(data) => {
-            // 2. Send to the Tokio listener via the channel
+            // Send to the Tokio listener via the channel
             // We ignore errors because if no clients are connected, send fails (which is fine)
-            let _ = network_out.0.send(data);
+            let _ = network_out.0.send(Outbound::Message(seat, data));
         },
         Err(e) => {
-            eprintln!("Failed to serialize GameState: {}", e);
+            eprintln!("Failed to serialize ServerMessage: {}", e);
         }
     }
 }
 
+// Game events for the clients, sent as they happen alongside the snapshots
+#[derive(Event, Debug, Clone)]
+pub struct Announcement(pub ServerMessage);
+
+fn send_announcements(mut announcements: EventReader<Announcement>, network_out: Res<BrowserOutbound>) {
+    for announcement in announcements.read() {
+        send_to_clients(&network_out, &announcement.0);
+    }
+}
+
+// Covers every change of shooter: after a shot, a pass or a new rack
+fn announce_turn_change(whose_move: Res<State<WhoseMove>>, mut announcements: EventWriter<Announcement>) {
+    announcements.send(Announcement(ServerMessage::TurnChanged { shooter: whose_move.get().clone() }));
+}
+
 
 use bevy_rapier3d::prelude::Velocity; // Ensure you have this import
 
@@ -160,7 +598,9 @@ fn update_gamestate(
     pool_ball_query: Query<(&Transform, &Velocity, &PoolBalls)>, 
     cue_ball_query: Query<(&Transform, &Velocity), With<CueBall>>,
     gamephase_res: Res<State<GamePhase>>,
-    whose_move_res: Res<State<WhoseMove>>
+    whose_move_res: Res<State<WhoseMove>>,
+    referee: Res<Referee>,
+    match_score: Res<MatchScore>,
 ) {
     let mut ball_vec = vec![];
 
@@ -190,7 +630,15 @@ fn update_gamestate(
     gamestate.phase = gamephase_res.get().clone();
     gamestate.should_show_shot_controls = true;
     gamestate.balls = ball_vec;
+    gamestate.racks_won = match_score.racks_won;
+    gamestate.race_to = match_score.race_to;
+    gamestate.target_ball = referee.0.target_ball();
+    referee.0.report(&mut gamestate);
 }
+// Sent back to anyone who plays out of turn; the rest of the queue is
+// still handled
+const NOT_YOUR_TURN: &str = "not your turn, or not the time for that";
+
 fn handle_incoming_network_messages(
     // The channel we created in main()
     mut inbound: ResMut<BrowserInbound>, 
@@ -202,98 +650,399 @@ fn handle_incoming_network_messages(
     mut commands: Commands,
     mut cue_ball_query: Query<Entity, With<CueBall>>,
     game_tokens: Res<GameTokens>,
-    whose_move: Res<State<WhoseMove>>
+    whose_move: Res<State<WhoseMove>>,
+    mut next_shooter: ResMut<NextState<WhoseMove>>,
+    mut shot_recorder: ResMut<ShotRecorder>,
+    mut referee: ResMut<Referee>,
+    (mut shot_clock, mut inactivity, replay): (ResMut<ShotClock>, ResMut<Inactivity>, Res<ShotReplay>),
+    table_spec: Res<TableSpec>,
+    object_ball_query: Query<&Transform, With<PoolBalls>>,
+    network_out: Res<BrowserOutbound>,
 ) {
+    // a second shot before the phase moves on would overwrite the first
+    let mut shot_taken = false;
+
     // Loop until the channel is empty for this frame
-    while let Ok(bytes) = inbound.0.try_recv() {
+    while let Ok((session, bytes)) = inbound.0.try_recv() {
+        let seat = session.seat;
         match bincode::deserialize::<ClientMessage>(&bytes) {
             Ok(message) => {
-                println!("Server received: {:?}", message);
+                println!("Server received from session {} ({:?}): {:?}", session.id, seat, message);
 
                 match message {
-                    ClientMessage::Shot { power, direction, angvel, token } => {
+                    ClientMessage::Shot { power, direction, tip } => {
                         // Apply the shot logic directly to the state
                         // OR trigger a physics event
                         // validate player and game state
 
-                           if !match **whose_move {
-                            WhoseMove::Player1 => game_tokens.p1 == token && **whose_move == WhoseMove::Player1 && **current_state == GamePhase::PreShot,
-                            WhoseMove::Player2 => game_tokens.p2 == token && **whose_move == WhoseMove::Player2 && **current_state == GamePhase::PreShot,
-                        } {
-                             println!("Shot Rejected: Auth token does not match or not players turn");
-                            return;
+                        if !is_shooter(&game_tokens, &whose_move, seat) || **current_state != GamePhase::PreShot {
+                            println!("Shot Rejected from session {} ({:?}): not the shooter or not players turn", session.id, seat);
+                            send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: NOT_YOUR_TURN.to_string() });
+                            continue;
+                        };
+                        if shot_taken {
+                            println!("Shot Rejected from session {} ({:?}): a shot is already under way", session.id, seat);
+                            send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: "a shot is already under way".to_string() });
+                            continue;
+                        }
+                        if replay.showing() {
+                            send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: "the last shot is still being shown".to_string() });
+                            continue;
+                        }
+
+                        let (power, direction, tip) = match cue::sanitize_shot(power, direction, tip) {
+                            Ok(shot) => shot,
+                            Err(reason) => {
+                                println!("Shot Rejected from session {} ({:?}): {}", session.id, seat, reason);
+                                send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: reason });
+                                continue;
+                            }
                         };
 
-                        println!("Processing shot: Power {}", power);
+                        println!("Processing shot: Power {} Tip {:?}", power, tip);
                         if let Ok(cue_ball) = cue_ball_query.get_single_mut() {
-                            commands.entity(cue_ball).insert(Velocity {linvel: direction * power, angvel: Vec3::ZERO});
+                            let strike = cue::strike(direction, cue::cue_speed_for(power), tip, CUE_BALL_RADIUS);
+                            commands.entity(cue_ball).insert(Velocity {linvel: strike.linvel, angvel: strike.angvel});
+                            shot_recorder.log.clear();
+                            shot_recorder.recording = true;
+                            shot_taken = true;
+                            inactivity.last_move = std::time::Instant::now();
                             //issue shot made event
                             shot_events.send(ShotMade);
                         }
                     },
-                    ClientMessage::BallPlacement { position, token } => {
+                    ClientMessage::BallPlacement { position } => {
 
                         // validate player and game state
 
+                        if !is_shooter(&game_tokens, &whose_move, seat) || **current_state != GamePhase::BallInHand {
+                            println!("Ball Placement Rejected: {:?} is not the shooter or not players turn", seat);
+                            send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: NOT_YOUR_TURN.to_string() });
+                            continue;
+                        };
 
+                        if replay.showing() {
+                            send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: "the last shot is still being shown".to_string() });
+                            continue;
+                        }
 
-                        if !match **whose_move {
-                            WhoseMove::Player1 => game_tokens.p1 == token && **whose_move == WhoseMove::Player1 && **current_state == GamePhase::BallInHand,
-                            WhoseMove::Player2 => game_tokens.p2 == token && **whose_move == WhoseMove::Player2 && **current_state == GamePhase::BallInHand,
-                        } {
-                             println!("Ball Placement Rejected: Auth token does not match or not players turn");
-                            return;
+                        let occupied: Vec<Vec3> = object_ball_query.iter().map(|transform| transform.translation).collect();
+                        let kitchen = referee.0.in_hand_behind_head_string();
+                        let position = match table_spec.place_cue_ball(position, CUE_BALL_RADIUS, &occupied, kitchen) {
+                            Ok(position) => position,
+                            Err(reason) => {
+                                println!("Ball Placement Rejected: {}", reason);
+                                send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: reason });
+                                continue;
+                            }
                         };
 
                         println!("Moving cue ball to: {}", position);
 
                            if let Ok(cue_ball) = cue_ball_query.get_single_mut() {
-                            commands.entity(cue_ball).insert(TransformBundle::from(Transform::from_translation(position + Vec3::Y * 2.0))).insert(Velocity {linvel: Vec3::ZERO, angvel: Vec3::ZERO });
+                            commands.entity(cue_ball).insert(TransformBundle::from(Transform::from_translation(position))).insert(Velocity {linvel: Vec3::ZERO, angvel: Vec3::ZERO });
                         } else {
                             //respawn cue ball
-                             commands
-        .spawn(RigidBody::Dynamic)
-        .insert(Collider::ball(CUE_BALL_RADIUS))
-        .insert(BALL_RESTITUTION)
-        .insert(TransformBundle::from(Transform::from_translation(position + Vec3::Y *2.0 )))
-        .insert(ColliderMassProperties::Mass(BALL_MASS))
-        .insert(BALL_DAMPING)
-        .insert(Friction::coefficient(BALL_FRICTION_COEFF))
-        .insert(CueBall).insert(Ccd::enabled()).insert(ActiveEvents::COLLISION_EVENTS).insert(Velocity {linvel: Vec3::ZERO, angvel: Vec3::ZERO });
-
+                            spawn_cue_ball(&mut commands, position);
                         }
 
+                        inactivity.last_move = std::time::Instant::now();
                         //set new gamephase
                         set_state.set(GamePhase::PreShot);
                     }
+                    ClientMessage::PushOut => {
+                        if !is_shooter(&game_tokens, &whose_move, seat) || **current_state != GamePhase::PreShot {
+                            println!("Push out Rejected: {:?} is not the shooter or not players turn", seat);
+                            send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: NOT_YOUR_TURN.to_string() });
+                            continue;
+                        }
+                        match referee.0.declare(Declaration::PushOut) {
+                            Ok(()) => {
+                                println!("{:?} calls a push out", **whose_move);
+                                inactivity.last_move = std::time::Instant::now();
+                            }
+                            Err(e) => {
+                                println!("Push out Rejected: {}", e);
+                                send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: e });
+                            }
+                        }
+                    }
+                    ClientMessage::Pass => {
+                        if !is_shooter(&game_tokens, &whose_move, seat) || **current_state != GamePhase::PreShot {
+                            println!("Pass Rejected: {:?} is not the shooter or not players turn", seat);
+                            send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: NOT_YOUR_TURN.to_string() });
+                            continue;
+                        }
+                        match referee.0.declare(Declaration::Pass) {
+                            Ok(()) => {
+                                println!("{:?} passes, {:?} to shoot", **whose_move, referee.0.shooter());
+                                next_shooter.set(referee.0.shooter());
+                                inactivity.last_move = std::time::Instant::now();
+                            }
+                            Err(e) => {
+                                println!("Pass Rejected: {}", e);
+                                send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: e });
+                            }
+                        }
+                    }
+                    ClientMessage::CallPocket { pocket } => {
+                        if !is_shooter(&game_tokens, &whose_move, seat) || !matches!(**current_state, GamePhase::PreShot | GamePhase::BallInHand) {
+                            println!("Call Rejected: {:?} is not the shooter or not players turn", seat);
+                            send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: NOT_YOUR_TURN.to_string() });
+                            continue;
+                        }
+                        match referee.0.declare(Declaration::CallPocket(pocket)) {
+                            Ok(()) => {
+                                println!("{:?} calls the 8 in {:?}", **whose_move, pocket);
+                                inactivity.last_move = std::time::Instant::now();
+                            }
+                            Err(e) => {
+                                println!("Call Rejected: {}", e);
+                                send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: e });
+                            }
+                        }
+                    }
+                    ClientMessage::CallShot { ball, pocket } => {
+                        if !is_shooter(&game_tokens, &whose_move, seat) || !matches!(**current_state, GamePhase::PreShot | GamePhase::BallInHand) {
+                            println!("Call Rejected: {:?} is not the shooter or not players turn", seat);
+                            send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: NOT_YOUR_TURN.to_string() });
+                            continue;
+                        }
+                        match referee.0.declare(Declaration::CallShot { ball, pocket }) {
+                            Ok(()) => {
+                                println!("{:?} calls the {} in {:?}", **whose_move, ball, pocket);
+                                inactivity.last_move = std::time::Instant::now();
+                            }
+                            Err(e) => {
+                                println!("Call Rejected: {}", e);
+                                send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: e });
+                            }
+                        }
+                    }
+                    // the sockets keep acknowledgements to themselves
+                    ClientMessage::Ack { .. } => {}
+                    ClientMessage::Extension => {
+                        if !is_shooter(&game_tokens, &whose_move, seat) {
+                            println!("Extension Rejected: {:?} is not the shooter or not players turn", seat);
+                            send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: NOT_YOUR_TURN.to_string() });
+                            continue;
+                        }
+                        match shot_clock.extend(&whose_move) {
+                            Ok(()) => println!("{:?} takes an extension", **whose_move),
+                            Err(e) => {
+                                println!("Extension Rejected: {}", e);
+                                send_to_seat(&network_out, seat, &ServerMessage::Rejected { message: e });
+                            }
+                        }
+                    }
                     _ => {}
                 }
             },
-            Err(e) => eprintln!("Failed to deserialize client message: {}", e),
+            Err(e) => eprintln!("Failed to deserialize message from session {} ({:?}): {}", session.id, seat, e),
+        }
+    }
+}
+
+
+fn is_shooter(game_tokens: &GameTokens, whose_move: &WhoseMove, seat: Seat) -> bool {
+    // a hot-seat connection plays whichever side is to move
+    seat.player() == Some(whose_move.clone()) || (game_tokens.hot_seat() && seat != Seat::Spectator)
+}
+
+// Who is connected to each seat, and since when a player has been gone
+#[derive(Resource)]
+struct Presence {
+    connections: HashMap<Seat, u32>,
+    away_since: [Option<std::time::Instant>; 2],
+    grace: Duration,
+}
+
+impl Presence {
+    fn new(grace: Duration) -> Self {
+        Presence { connections: HashMap::new(), away_since: [None; 2], grace }
+    }
+}
+
+// Marks players away when their last socket closes and back when one
+// reconnects, and forfeits the match for a player gone longer than the grace
+// period. A player who has not connected yet is not away.
+fn track_presence(
+    mut seat_events: ResMut<SeatEvents>,
+    mut presence: ResMut<Presence>,
+    game_tokens: Res<GameTokens>,
+    mut gamestate: ResMut<GameState>,
+    mut match_score: ResMut<MatchScore>,
+    current_state: Res<State<GamePhase>>,
+    mut next_phase: ResMut<NextState<GamePhase>>,
+    mut winner: ResMut<NextState<Winner>>,
+    mut game_ended_event_writer: EventWriter<GameEndedEvent>,
+    mut announcements: EventWriter<Announcement>,
+    mut ticks: ResMut<SnapshotTicks>,
+) {
+    while let Ok(event) = seat_events.0.try_recv() {
+        match event {
+            SeatEvent::Joined(seat) => {
+                *presence.connections.entry(seat).or_default() += 1;
+                if let Some(player) = seat.player() {
+                    if presence.away_since[player.index()].take().is_some() {
+                        println!("{:?} is back", player);
+                    }
+                }
+                // bring the newcomer up to date even if nothing is moving
+                ticks.resend = true;
+            }
+            SeatEvent::Left(seat) => {
+                let connections = presence.connections.entry(seat).or_default();
+                *connections = connections.saturating_sub(1);
+                if *connections == 0 {
+                    if let Some(player) = seat.player() {
+                        println!("{:?} is away, {}s to reconnect", player, presence.grace.as_secs());
+                        presence.away_since[player.index()] = Some(std::time::Instant::now());
+                    }
+                }
+            }
+        }
+    }
+    gamestate.away = presence.away_since.map(|since| since.is_some());
+    gamestate.spectators = presence.connections.get(&Seat::Spectator).copied().unwrap_or_default();
+
+    // in hot seat every connection is Player1, and there is no one to forfeit to
+    if game_tokens.hot_seat() || **current_state == GamePhase::GameEnded {
+        return;
+    }
+    let gone = [WhoseMove::Player1, WhoseMove::Player2]
+        .into_iter()
+        .filter_map(|player| presence.away_since[player.index()].map(|since| (since, player)))
+        .filter(|(since, _)| since.elapsed() >= presence.grace)
+        .min_by_key(|(since, _)| *since);
+    if let Some((_, loser)) = gone {
+        let match_winner = match_score.forfeit(&loser);
+        println!("{:?} did not come back, {:?} wins the match by forfeit", loser, match_winner);
+        announcements.send(Announcement(ServerMessage::MatchOver { winner: match_winner.clone(), racks_won: match_score.racks_won }));
+        winner.set(Winner(match_winner));
+        next_phase.set(GamePhase::GameEnded);
+        game_ended_event_writer.send(GameEndedEvent);
+    }
+}
+
+// Time left for the shot being lined up, and the extensions still to use
+#[derive(Resource)]
+struct ShotClock {
+    // no clock when zero
+    limit: Duration,
+    extension: Duration,
+    // None while the balls are moving, or with no clock
+    remaining: Option<Duration>,
+    extensions_left: [bool; 2],
+    // racks played, to give the extensions back on a new rack
+    rack: u32,
+}
+
+impl ShotClock {
+    fn new(limit: Duration, extension: Duration) -> Self {
+        ShotClock { limit, extension, remaining: None, extensions_left: [true; 2], rack: 0 }
+    }
+
+    fn extend(&mut self, player: &WhoseMove) -> Result<(), String> {
+        let Some(remaining) = self.remaining else {
+            return Err("the shot clock is not running".to_string());
+        };
+        if !self.extensions_left[player.index()] {
+            return Err("the extension for this rack is used up".to_string());
+        }
+        self.extensions_left[player.index()] = false;
+        self.remaining = Some(remaining + self.extension);
+        Ok(())
+    }
+}
+
+// Counts down while the shooter lines up, from PreShot or BallInHand, and
+// starts again for every shot. Running out of time is judged like a shot
+// that never happened: the ruleset calls the foul, and judge_shot carries
+// it out. The clock waits while a player is away.
+fn run_shot_clock(
+    time: Res<Time>,
+    mut clock: ResMut<ShotClock>,
+    match_score: Res<MatchScore>,
+    current_state: Res<State<GamePhase>>,
+    whose_move: Res<State<WhoseMove>>,
+    mut gamestate: ResMut<GameState>,
+    mut shot_recorder: ResMut<ShotRecorder>,
+    mut next_phase: ResMut<NextState<GamePhase>>,
+    replay: Res<ShotReplay>,
+) {
+    let rack = match_score.racks_won[0] + match_score.racks_won[1];
+    if rack != clock.rack {
+        clock.rack = rack;
+        clock.extensions_left = [true; 2];
+    }
+
+    let lining_up = matches!(**current_state, GamePhase::PreShot | GamePhase::BallInHand);
+    if clock.limit.is_zero() || !lining_up {
+        clock.remaining = None;
+    } else if clock.remaining.is_none() || whose_move.is_changed() {
+        // a new shot, or the table handed over on a pass
+        clock.remaining = Some(clock.limit);
+    } else if !gamestate.away.contains(&true) && !replay.showing() {
+        let remaining = clock.remaining.unwrap_or_default().saturating_sub(time.delta());
+        clock.remaining = Some(remaining);
+        if remaining.is_zero() {
+            println!("{:?} ran out of time", **whose_move);
+            shot_recorder.log.clear();
+            shot_recorder.log.push(ShotEvent::ShotClockExpired);
+            next_phase.set(GamePhase::PostShot);
         }
     }
+    gamestate.shot_clock = clock.remaining.map(|remaining| remaining.as_secs_f32());
+    gamestate.extensions_left = clock.extensions_left;
+}
+
+// When either player last made a move the server took: a shot, a placement
+// or a declaration. Refused messages do not count.
+#[derive(Resource)]
+struct Inactivity {
+    last_move: std::time::Instant,
+    // no limit when zero
+    limit: Duration,
 }
 
+// Ends a match nobody is playing, with no winner
+fn abandon_inactive_match(
+    inactivity: Res<Inactivity>,
+    mut match_score: ResMut<MatchScore>,
+    current_state: Res<State<GamePhase>>,
+    mut next_phase: ResMut<NextState<GamePhase>>,
+    mut game_ended_event_writer: EventWriter<GameEndedEvent>,
+    mut announcements: EventWriter<Announcement>,
+) {
+    if inactivity.limit.is_zero() || **current_state == GamePhase::GameEnded || inactivity.last_move.elapsed() < inactivity.limit {
+        return;
+    }
+    println!("No moves for {}s, abandoning the match", inactivity.limit.as_secs());
+    match_score.abandoned = true;
+    announcements.send(Announcement(ServerMessage::MatchAbandoned { racks_won: match_score.racks_won }));
+    next_phase.set(GamePhase::GameEnded);
+    game_ended_event_writer.send(GameEndedEvent);
+}
 
 // --- 4. NETWORK LOGIC ---
 #[derive(Clone)]
 struct NetworkState {
     // Channel to send data TO Bevy (Client Input)
-    to_bevy: mpsc::UnboundedSender<Vec<u8>>,
+    to_bevy: mpsc::UnboundedSender<(Session, Vec<u8>)>,
+    // Tells Bevy when a seat joins or leaves
+    seat_events: mpsc::UnboundedSender<SeatEvent>,
     // Channel to subscribe to data FROM Bevy (Game State Updates)
-    from_bevy_broadcast: broadcast::Sender<Vec<u8>>,
+    from_bevy_broadcast: broadcast::Sender<Outbound>,
+    // Decides each connection's seat from its ?token=
+    tokens: Arc<GameTokens>,
+    // Sent to each client in its Welcome, before anything else
+    table: Arc<TableSpec>,
+    // How far behind the players spectators are fed
+    spectator_delay: Duration,
 }
 
-async fn start_network_listener(
-    port: u16,
-    tx_to_bevy: mpsc::UnboundedSender<Vec<u8>>,
-    tx_from_bevy: broadcast::Sender<Vec<u8>>,
-) {
-    let state = NetworkState {
-        to_bevy: tx_to_bevy,
-        from_bevy_broadcast: tx_from_bevy,
-    };
-
+async fn start_network_listener(port: u16, state: NetworkState) {
     let app = Router::new()
         .route("/", any(ws_handler))
         .with_state(state);
@@ -307,28 +1056,113 @@ async fn start_network_listener(
 
 async fn ws_handler(
     ws: WebSocketUpgrade,
+    axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
     axum::extract::State(state): axum::extract::State<NetworkState>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+    let token = params.get("token").cloned().unwrap_or_default();
+    let seat = state.tokens.seat_for(&token);
+    ws.on_upgrade(move |socket| handle_socket(socket, state, seat, ws_gateway::next_session_id()))
+}
+
+// Each connection may send RATE_LIMIT_BURST messages at once, then
+// RATE_LIMIT_PER_SEC; anything faster is dropped before it reaches Bevy
+const RATE_LIMIT_BURST: f32 = 20.0;
+const RATE_LIMIT_PER_SEC: f32 = 10.0;
+
+struct RateLimit {
+    allowance: f32,
+    last: tokio::time::Instant,
+}
+
+impl RateLimit {
+    fn new() -> Self {
+        RateLimit { allowance: RATE_LIMIT_BURST, last: tokio::time::Instant::now() }
+    }
+
+    fn allow(&mut self) -> bool {
+        let now = tokio::time::Instant::now();
+        self.allowance = (self.allowance + (now - self.last).as_secs_f32() * RATE_LIMIT_PER_SEC).min(RATE_LIMIT_BURST);
+        self.last = now;
+        if self.allowance < 1.0 {
+            return false;
+        }
+        self.allowance -= 1.0;
+        true
+    }
 }
 
-async fn handle_socket(socket: WebSocket, state: NetworkState) {
+async fn handle_socket(socket: WebSocket, state: NetworkState, seat: Option<Seat>, session_id: SessionId) {
     let (mut sender, mut receiver) = socket.split();
 
+    // Upgrade first so the browser sees why it was turned away
+    let Some(seat) = seat else {
+        println!("Refusing connection: unknown token");
+        let error = bincode::serialize(&ServerMessage::Error { message: "unknown token".to_string() }).unwrap();
+        let _ = sender.send(Message::Binary(error)).await;
+        let _ = sender.send(Message::Close(Some(CloseFrame { code: close_code::POLICY, reason: "unknown token".into() }))).await;
+        return;
+    };
+    let session = Session { id: session_id, seat };
+    println!("Client connected as {:?}, session {}", seat, session.id);
+    let mut rate_limit = RateLimit::new();
+    // what this client has been sent and has acknowledged, to delta against
+    let mut snapshots = SentSnapshots::default();
+
     // Subscribe to the broadcast channel specifically for THIS connection
     let mut my_rx = state.from_bevy_broadcast.subscribe();
 
+    // The client needs the table, and to know who it is, before it can draw anything
+    let welcome = ServerMessage::Welcome { protocol_version: PROTOCOL_VERSION, table: (*state.table).clone(), seat };
+    if sender.send(Message::Binary(bincode::serialize(&welcome).unwrap())).await.is_err() {
+        return;
+    }
+    // Nothing reaches Bevy until the client has said Hello with our version
+    let mut greeted = false;
+    // Spectators get everything late, held here until it is due
+    let delay = if seat == Seat::Spectator { state.spectator_delay } else { Duration::ZERO };
+    let mut delayed: VecDeque<(tokio::time::Instant, Outbound)> = VecDeque::new();
+
     loop {
         tokio::select! {
             // 1. INCOMING: Client (WASM) -> Bevy
             // FIX: Match Binary, not Text!
-            Some(msg) = receiver.next() => {
+            msg = receiver.next() => {
+                // the socket is gone; a dropped connection ends here rather than with a Close
+                let Some(msg) = msg else { break };
                 match msg {
+                    Ok(Message::Binary(data)) if greeted => {
+                        if let Ok(ClientMessage::Ack { tick }) = bincode::deserialize::<ClientMessage>(&data) {
+                            // acknowledgements are about this socket's snapshots, Bevy never sees them
+                            snapshots.ack(tick);
+                        } else if seat == Seat::Spectator {
+                            // spectators only watch
+                        } else if !rate_limit.allow() {
+                            println!("Dropping message from session {} ({:?}): over the rate limit", session.id, seat);
+                        } else {
+                            // Forward raw bytes to Bevy, attributed to this socket's session
+                            let _ = state.to_bevy.send((session, data));
+                        }
+                    }
                     Ok(Message::Binary(data)) => {
-                        // Forward raw bytes to Bevy
-                        let _ = state.to_bevy.send(data);
+                        let refusal = match bincode::deserialize::<ClientMessage>(&data) {
+                            Ok(ClientMessage::Hello { protocol_version }) if protocol_version == PROTOCOL_VERSION => None,
+                            Ok(ClientMessage::Hello { protocol_version }) => {
+                                Some(format!("client speaks protocol {}, server speaks {}; reload the page", protocol_version, PROTOCOL_VERSION))
+                            }
+                            _ => Some("expected Hello as the first message".to_string()),
+                        };
+                        let Some(message) = refusal else {
+                            greeted = true;
+                            let _ = state.seat_events.send(SeatEvent::Joined(seat));
+                            continue;
+                        };
+                        println!("Refusing connection: {}", message);
+                        let error = bincode::serialize(&ServerMessage::Error { message: message.clone() }).unwrap();
+                        let _ = sender.send(Message::Binary(error)).await;
+                        let _ = sender.send(Message::Close(Some(CloseFrame { code: close_code::PROTOCOL, reason: message.into() }))).await;
+                        break;
                     }
-                    Ok(Message::Close(_)) => break,
+                    Ok(Message::Close(_)) | Err(_) => break,
                     // Ignore Text/Ping/Pong
                     _ => {}
                 }
@@ -336,302 +1170,89 @@ async fn handle_socket(socket: WebSocket, state: NetworkState) {
 
             // 2. OUTGOING: Bevy -> Client (WASM)
             // FIX: Send Binary, not Text!
-            Ok(msg) = my_rx.recv() => {
-                // 'msg' is already Vec<u8> (Bincode bytes)
-                if sender.send(Message::Binary(msg)).await.is_err() {
+            outbound = my_rx.recv() => {
+                let outbound = match outbound {
+                    Ok(outbound) => outbound,
+                    // the next snapshot is still built on what the client acknowledged
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        println!("Session {} ({:?}) fell behind, {} messages skipped", session.id, seat, skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if matches!(&outbound, Outbound::Message(Some(to), _) if *to != seat) {
+                    // meant for another seat
+                } else if !delay.is_zero() {
+                    delayed.push_back((tokio::time::Instant::now() + delay, outbound));
+                } else if sender.send(Message::Binary(encode_outbound(outbound, &mut snapshots))).await.is_err() {
+                    break;
+                }
+            }
+
+            // 3. DELAYED: Bevy -> Spectator, once the delay has passed
+            _ = tokio::time::sleep_until(delayed.front().map(|(due, _)| *due).unwrap_or_else(tokio::time::Instant::now)), if !delayed.is_empty() => {
+                let (_, outbound) = delayed.pop_front().unwrap();
+                if sender.send(Message::Binary(encode_outbound(outbound, &mut snapshots))).await.is_err() {
                     break;
                 }
             }
         }
     }
-}
-
-use rand::seq::SliceRandom; // Ensure this is imported
-use nine_ball_game::{GameState, WhoseMove};
-use nine_ball_game::{TABLE_WIDTH, TABLE_LENGTH, FRICTION_COEFF, TABLE_FRICTION_COEFF, BALL_FRICTION_COEFF, CUE_BALL_RADIUS, STANDARD_BALL_RADIUS};
-// ... Player struct definition
-fn setup_physics_for_nine_ball(mut commands: Commands  ) {
 
-    
-/* Create the ground. */
-    commands
-    .spawn(RigidBody::Fixed)
-        .insert(Collider::cuboid(TABLE_WIDTH, 0.0, TABLE_LENGTH))
-      //  .insert(Friction{coefficient: FRICTION_COEFF, combine_rule: CoefficientCombineRule::Average})
-      .insert(Friction::coefficient(TABLE_FRICTION_COEFF))
-        .insert(TransformBundle::from(Transform::from_xyz(0.0, 0.0, 0.0)));
-
-
-      commands
-    .spawn(RigidBody::Fixed)
-        .insert(Collider::cuboid(TABLE_WIDTH, 0.0, TABLE_LENGTH))
-      //  .insert(Friction{coefficient: FRICTION_COEFF, combine_rule: CoefficientCombineRule::Average})
-      .insert(Friction::coefficient(TABLE_FRICTION_COEFF))
-        .insert(TransformBundle::from(Transform::from_xyz(0.0, 5.0, 0.0)));
-
-    //create the walls
-    commands
-    .spawn(RigidBody::Fixed)
-    .insert(Collider::cuboid(WALL_DIMENSIONS.half_size.x, WALL_DIMENSIONS.half_size.y, WALL_DIMENSIONS.half_size.z))
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(TABLE_WIDTH, 0.0, TABLE_WIDTH))))
-    .insert(Restitution {coefficient: 1.0, combine_rule: CoefficientCombineRule::Max});
-
-    commands
-    .spawn(RigidBody::Fixed)
-    .insert(Collider::cuboid(WALL_DIMENSIONS.half_size.x, WALL_DIMENSIONS.half_size.y, WALL_DIMENSIONS.half_size.z))
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(TABLE_WIDTH, 0.0, -TABLE_WIDTH))))
-.insert(Friction::coefficient(FRICTION_COEFF))
-.insert(Restitution {coefficient: 1.0, combine_rule: CoefficientCombineRule::Max});
-commands
-    .spawn(RigidBody::Fixed)
-    .insert(Collider::cuboid(WALL_DIMENSIONS.half_size.x, WALL_DIMENSIONS.half_size.y, WALL_DIMENSIONS.half_size.z))
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(-TABLE_WIDTH, 0.0, TABLE_WIDTH))))
-    .insert(Restitution {coefficient: 1.0, combine_rule: CoefficientCombineRule::Max});
-
-    commands
-    .spawn(RigidBody::Fixed)
-    .insert(Collider::cuboid(WALL_DIMENSIONS.half_size.x, WALL_DIMENSIONS.half_size.y, WALL_DIMENSIONS.half_size.z))
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(-TABLE_WIDTH, 0.0, -TABLE_WIDTH))))
-    .insert(Restitution {coefficient: 1.0, combine_rule: CoefficientCombineRule::Max});
-    commands
-    .spawn(RigidBody::Fixed)
-    .insert(Collider::cuboid(WALL_DIMENSIONS.half_size.z, WALL_DIMENSIONS.half_size.y, WALL_DIMENSIONS.half_size.x))
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(0.0, 0.0, -TABLE_LENGTH))))
-    .insert(Restitution {coefficient: 1.0, combine_rule: CoefficientCombineRule::Max});
-    commands
-    .spawn(RigidBody::Fixed)
-    .insert(Collider::cuboid(WALL_DIMENSIONS.half_size.z, WALL_DIMENSIONS.half_size.y, WALL_DIMENSIONS.half_size.x))
-    .insert(TransformBundle::from_transform(Transform::from_translation(Vec3::new(0.0, 0.0, TABLE_LENGTH))))
-    .insert(Restitution {coefficient: 1.0, combine_rule: CoefficientCombineRule::Max});
-
-    //make aimer
-
-
-    
-    /* Create the cue ball. */
-    commands
-        .spawn(RigidBody::Dynamic)
-        .insert(Collider::ball(CUE_BALL_RADIUS))
-        .insert(BALL_RESTITUTION)
-        //.insert(ColliderMassProperties::Mass(0.40))
-        .insert(TransformBundle::from(Transform::from_xyz(0.0, CUE_BALL_RADIUS, -TABLE_WIDTH )))
-        .insert(ColliderMassProperties::Mass(BALL_MASS))
-        .insert(BALL_DAMPING)
-        .insert(Friction::coefficient(BALL_FRICTION_COEFF))
-        .insert(DEFAULT_VELOCITY)
-        .insert(CueBall).insert(Ccd::enabled()).insert(ActiveEvents::COLLISION_EVENTS);
-
-
-
-
-
-// ... inside your startup system ...
-
-// 1. GEOMETRY (Your existing geometry code is fine)
-let r = STANDARD_BALL_RADIUS;
-const SPACING_EPSILON: f32 = 0.0005; 
-const MAX_JITTER: f32 = 0.0002; 
-
-let spacing_r = r + SPACING_EPSILON;
-let z_spacing = 3.0_f32.sqrt() * spacing_r; 
-let x_spacing = spacing_r; 
-let center_z = TABLE_WIDTH; 
-
-// Define positions relative to the 9-Ball (Center)
-let rack_positions = vec![
-    // Row 1 (Apex - Standard place for 1-ball)
-    Vec3::new(0.0, r, center_z - 2.0 * z_spacing),      // Index 0
-
-    // Row 2
-    Vec3::new(-x_spacing, r, center_z - z_spacing),     // Index 1
-    Vec3::new( x_spacing, r, center_z - z_spacing),     // Index 2
-
-    // Row 3 (Wing Row - Center is 9-ball spot)
-    Vec3::new(-2.0 * x_spacing, r, center_z),           // Index 3
-    Vec3::new( 0.0,             r, center_z),           // Index 4 (CENTER)
-    Vec3::new( 2.0 * x_spacing, r, center_z),           // Index 5
-
-    // Row 4
-    Vec3::new(-x_spacing, r, center_z + z_spacing),     // Index 6
-    Vec3::new( x_spacing, r, center_z + z_spacing),     // Index 7
-
-    // Row 5 (Bottom Tip)
-    Vec3::new(0.0, r, center_z + 2.0 * z_spacing),      // Index 8
-];
-
-// 2. LOGIC FIX: AVOID DOUBLE ASSIGNMENT
-let mut spawn_list: Vec<(u32, Vec3)> = Vec::new();
-
-// A. FIXED BALLS
-// Standard 9-Ball: 1 is at Apex (Index 0), 9 is in Center (Index 4)
-spawn_list.push((1, rack_positions[0])); 
-spawn_list.push((9, rack_positions[4])); 
-
-// B. SHUFFLE THE REST
-// Balls 2-8 need to be shuffled into the REMAINING positions.
-let mut other_balls = vec![2, 3, 4, 5, 6, 7, 8];
-let mut rng = rand::thread_rng();
-other_balls.shuffle(&mut rng);
-
-// Identify which indices are still empty (We used 0 and 4)
-let available_indices = vec![1, 2, 3, 5, 6, 7, 8];
-
-// Map the shuffled balls to the available indices
-for (i, &ball_num) in other_balls.iter().enumerate() {
-    let target_index = available_indices[i];
-    spawn_list.push((ball_num, rack_positions[target_index]));
-}
-
-// 3. SPAWN LOOP
-for (ball_number, mut pos) in spawn_list {
-    
-    // Apply your Jitter (Excluding the fixed 9-ball if desired)
-    if ball_number != 9 {
-         let x_jitter: f32 = rng.gen_range(-MAX_JITTER..MAX_JITTER);
-         let z_jitter: f32 = rng.gen_range(-MAX_JITTER..MAX_JITTER);
-         pos.x += x_jitter;
-         pos.z += z_jitter;
-    }
-
-    commands
-        .spawn(RigidBody::Dynamic)
-        .insert(Collider::ball(STANDARD_BALL_RADIUS))
-        .insert(BALL_RESTITUTION)
-        .insert(PoolBalls(ball_number))
-        .insert(ColliderMassProperties::Mass(BALL_MASS))
-        .insert(BALL_DAMPING)
-        .insert(DEFAULT_VELOCITY)
-        .insert(Friction::coefficient(BALL_FRICTION_COEFF))
-        .insert(TransformBundle::from(Transform::from_translation(pos)))
-        .insert(Ccd::enabled())
-        .insert(ActiveEvents::COLLISION_EVENTS);
-}
+    if greeted {
+        let _ = state.seat_events.send(SeatEvent::Left(seat));
+    }
 }
 
-
-// CHANGE: usize -> u32
-#[derive(Component, PartialEq, PartialOrd, Eq, Ord, Debug, Clone, Copy, Hash)]
-struct PoolBalls(u32); 
-
-#[derive(Component)]
-struct FloatingNumber(u32);
-
-// CHANGE: usize -> u32
-#[derive(Resource, Debug, Clone, PartialEq, Eq, Hash)]
-struct PoolBallsOnTable(u32);
-
-#[derive(Component)]
-struct MainWindow;
-
-#[derive(Component)]
-struct SecondWindow;
-
-#[derive(Component)]
-struct CueBall;
-
-
-#[derive(Component)]
-struct Aimer;
-
-#[derive(Component)]
-struct SpinSelector;
-
-#[derive(Component)]
-struct MyGameCamera;
-
-#[derive(Component)]
-struct CurrentCuePosition;
-
-#[derive(Component)]
-struct ShotPower(f32,bool);
-
-const BALL_MASS: f32 = 0.17;
-const BALL_RESTITUTION: Restitution = Restitution::coefficient(1.00);
-const DEFAULT_VELOCITY: Velocity =  Velocity {
-    linvel: Vec3::ZERO,
-    angvel: Vec3::ZERO
-
-};
-const BALL_DAMPING: Damping = Damping {
-linear_damping: 0.2533301,
-angular_damping: 0.253301
-};
-const WALL_DIMENSIONS: Cuboid = Cuboid{half_size: Vec3::new(0.02, 120.55, 0.6096)};
-const WALL_MESH_DIMENSIONS: Cuboid = Cuboid{half_size: Vec3::new(0.02, 5.55, 0.6096)};
-const BACK_WALL_MESH_DIMENSIONS: Cuboid = Cuboid{half_size: Vec3::new(0.6096, 120.55, 0.002)};
-const TARGET_BALL_TORUS_DIMENSIONS: Torus = Torus{ minor_radius: 0.002 , major_radius: 0.06 };
-const CAMERA_HEIGHT: Vec3 = Vec3 {x: 0.0, y: 1.97, z: 0.0};
-
-#[derive(Component, PartialEq, PartialOrd, Eq, Ord, Debug, Clone, Copy, Hash)]
-struct TargetBallTorus;
-
-
-
-fn query_target_ball_torus_in_nine_ball(mut commands: Commands, lowest_numbered_ball: Res<State<CorrectObjectBall>>, torus_query: Query< (Entity, &Transform), With<TargetBallTorus>>, pool_ball_query: Query<(&PoolBalls, &Transform)>) {
-    let lowest_num = lowest_numbered_ball.0.0;
-    let toruses= torus_query.iter();
-    for (ball, transform) in pool_ball_query.iter() {
-        if ball.0 == lowest_num {
-            for (torus, torus_transform) in toruses{
-                commands.entity(torus).insert(TransformBundle::from(Transform {translation: transform.translation,rotation: torus_transform.rotation, ..default() }));
-            }
-            break
+// States are encoded as late as possible, against whatever the client has
+// acknowledged by the time they go out
+fn encode_outbound(outbound: Outbound, snapshots: &mut SentSnapshots) -> Vec<u8> {
+    match outbound {
+        Outbound::Message(_, data) => data,
+        Outbound::State { tick, time, state, keyframe } => {
+            bincode::serialize(&ServerMessage::Snapshot(snapshots.encode(tick, time, state, keyframe))).unwrap()
         }
     }
 }
 
-#[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
-struct CorrectObjectBall(PoolBalls);
+use nine_ball_game::{GameState, WhoseMove};
+use nine_ball_game::CUE_BALL_RADIUS;
+// ... Player struct definition
+fn setup_table(mut commands: Commands, table_spec: Res<TableSpec>, referee: Res<Referee>, mut rack_rng: ResMut<RackRng>) {
+    spawn_table(&mut commands, &table_spec);
+    rack_balls(&mut commands, &table_spec, referee.0.as_ref(), &mut rack_rng.0);
+}
 
 
 pub struct GameMode;
 
+// Table, physics and shot handling shared by every game. Each ruleset
+// plugin adds this and inserts its Referee.
 impl Plugin for GameMode {
     fn build(&self, app: &mut App) {
         app
-        
+        .add_systems(Startup, setup_table)
         .insert_state(GamePhase::PreShot)
         .insert_state(WhoseMove::Player1)
-        .insert_state(Scratch(false))
-    .insert_state(FirstContactHasBeenMade::NotYet)
-        .add_systems(Update, despawn_pocketed_balls.run_if(in_state(GamePhase::InMotion)))
+        .insert_state(Winner(WhoseMove::Player1))
+        .init_resource::<MatchStats>()
         .add_event::<GameEndedEvent>()
+        .add_event::<Announcement>()
+        .add_systems(Update, (announce_turn_change.run_if(state_changed::<WhoseMove>), send_announcements).chain())
         .add_event::<HumanPlayerMoveStart>()
         .add_event::<ComputerPlayerMoveStart>()
         .add_event::<ShotMade>()
            .add_event::<ShotCompletedPhysics>()
            .add_systems(Update, game_ended_event_reader)
-           .add_systems(Update, send_game_ended_on_exit );
-
-    }
-    
-}
-
-fn should_check_object_ball(first_contact: Res<State<FirstContactHasBeenMade>>, phase: Res<State<GamePhase>>) -> bool {
-    return  first_contact.get() == &FirstContactHasBeenMade::NotYet && phase.get() == &GamePhase::InMotion
-}
-
-pub struct NineBallRuleset;
-
-impl Plugin for NineBallRuleset {
-    fn build(&self, app: &mut App) {
-        app
-        .add_systems(Startup, setup_physics_for_nine_ball)
-        .add_plugins(GameMode)
-        .insert_state(CorrectObjectBall(PoolBalls(1)))
-        .insert_resource(PoolBallsOnTable(9))
-        .insert_state(Winner(WhoseMove::Player1))
+           .add_systems(Update, send_game_ended_on_exit )
         .add_systems(PostUpdate, state_setter_in_nine_ball_game)
-        .add_systems(Update, query_target_ball_torus_in_nine_ball)
           .add_systems(
             Update, // Needs to run after physics updates
             check_if_balls_still_rolling.run_if(in_state(GamePhase::InMotion)).after(PhysicsSet::StepSimulation))        
           .add_systems(
             OnEnter(GamePhase::PostShot),
-            evaluate_shot_rules,
+            judge_shot.pipe(carry_out_outcome),
         )
-        .add_systems(Last, (check_for_win_in_nine_ball, set_correct_object_ball_after_shot_in_nine_ball, tabulate_for_nine_ball).run_if(in_state(GamePhase::PostShot)).after(PhysicsSet::StepSimulation))//run if shot has been made and balls have stopped moving
-        .add_systems(PostUpdate, check_for_correct_object_ball.run_if(in_state(FirstContactHasBeenMade::NotYet)).run_if(in_state(GamePhase::InMotion)).run_if(should_check_object_ball).after(PhysicsSet::StepSimulation))
        .add_systems(
   PostUpdate,
   clear_collision_events
@@ -642,161 +1263,77 @@ impl Plugin for NineBallRuleset {
     }
 }
 
-fn clear_collision_events(mut collision_events: ResMut<Events<CollisionEvent>>,) {
+pub struct NineBallRuleset;
 
-collision_events.clear();
+impl Plugin for NineBallRuleset {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(Referee(Box::new(NineBallRules::new(WhoseMove::Player1))))
+        .add_plugins(GameMode);
+    }
 }
 
-       
-
-
-
-
-const  EPS: f32 = 1e-2;
-
-// run when state is InMotion
-
-// run when first contact has not been made but ball is in motion
-fn check_for_correct_object_ball(
-    // We now read CollisionEvents
-    mut collision_events: EventReader<CollisionEvent>,
-    previous_contact: Res<State<FirstContactHasBeenMade>>,
-    mut next_first_contact_state: ResMut<NextState<FirstContactHasBeenMade>>,
-    mut next_scratch_state: ResMut<NextState<Scratch>>,
-    // We need the current state of lowest_numbered_ball
-    current_correct_object_ball: Res<State<CorrectObjectBall>>,
-    // Queries to identify the types of entities involved in the collision
-    cue_ball_query: Query<Entity, With<CueBall>>,
-    pool_ball_query: Query<(Entity, &PoolBalls)>,
-) {
-    // Get the cue ball entity once
-    let Some(cue_ball_entity) = cue_ball_query.iter().next() else {
-        // Handle case where cue ball doesn't exist, though it usually should
-        return;
-    };
-
-    let correct_object_ball_number = current_correct_object_ball.0;
-
-    for event in collision_events.read() {
-        if let CollisionEvent::Started(e1, e2, _flags) = event {
-            let (collider1, collider2) = (e1, e2);
-
-            // Check if one of the entities is the cue ball
-            let (other_ball_entity, is_cue_ball_involved) = if collider1 == &cue_ball_entity {
-                (collider2, true)
-            } else if collider2 == &cue_ball_entity {
-                (collider1, true)
-            } else {
-                continue // Neither is the cue ball, skip
-            };
-
-            if is_cue_ball_involved {
-                // Check if the other entity is a pool ball
-                if let Ok((_other_ball_entity_from_query, other_pool_ball_component)) = pool_ball_query.get(*other_ball_entity) {
-                    
-                    let collided_ball_number = other_pool_ball_component.0;
-                    if previous_contact.get() == &FirstContactHasBeenMade::NotYet { // Check the next state to avoid double setting
-                        next_first_contact_state.set(FirstContactHasBeenMade::Yes);
-                        println!("FIRST CONTACT HAS BEEN MADE with ball {}", collided_ball_number);
-                        
-                        
-                        // Now check if it's the CORRECT object ball
-                    if collided_ball_number != correct_object_ball_number.0 {
-                        println!("lowest numbered ball: {} first contact (incorrect) {:?}", correct_object_ball_number.0, collided_ball_number);
-                        println!("scratch set by incorrect first contact");
-                        next_scratch_state.set(Scratch(true)); // Set scratch state
-                    } else {
-                        println!("lowest numbered ball: {} first contact (correct) {}", correct_object_ball_number.0, collided_ball_number);
-                        // Optional: If hitting the correct ball first means *no* scratch, ensure scratch isn't set.
-                        // Or if you only set `scratch` if it's incorrect, this branch is fine.
-                    }
-                }
+pub struct TenBallRuleset;
 
-                    // BREAK HERE if you only care about the *very first* collision event of the frame
-                    // However, typically you process all events in the reader.
-                    // If your game logic only cares about the first *conceptual* contact in a turn,
-                    // you might use a flag to prevent further processing after the first relevant one is found.
-                    // For instance, if you have a `BallInPlay` state and you only care about the
-                    // first contact *while* `BallInPlay` is active.
-                }
-            }
-        }
+impl Plugin for TenBallRuleset {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(Referee(Box::new(TenBallRules::new(WhoseMove::Player1))))
+        .add_plugins(GameMode);
     }
-    collision_events.clear();
 }
 
-fn set_correct_object_ball_after_shot_in_nine_ball(ball_query: Query<&PoolBalls>, mut correct_ball_setter: ResMut<NextState<CorrectObjectBall>>) {
-    let mut lowest = 10;
+pub struct StraightPoolRuleset {
+    pub target_score: i32,
+}
 
-    for i in ball_query.iter() {
-        if i.0 < lowest {
-            lowest = i.0;
-        }
+impl Plugin for StraightPoolRuleset {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(Referee(Box::new(StraightPoolRules::new(WhoseMove::Player1, self.target_score))))
+        .add_plugins(GameMode);
     }
-
-    correct_ball_setter.set(CorrectObjectBall(PoolBalls(lowest)));
-    println!("{:?}", lowest);
-
 }
 
-/* fn ball_in_hand(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, mut materials: ResMut<Assets<StandardMaterial>>, mut set_state: ResMut<NextState<Phase>>, cue_ball_query: Query<(Entity, &Transform), With<CueBall>>) {
-    if let Ok((cue_ball_entity, cue_ball_transform))= cue_ball_query.get_single() {
-     
-         
-    } else {
+pub struct OnePocketRuleset;
 
-        commands
-        .spawn(RigidBody::Dynamic)
-        .insert(MaterialMeshBundle {mesh: meshes.add(Sphere::new(STANDARD_BALL_RADIUS)), material: materials.add(StandardMaterial::from_color(Color::WHITE)), ..default()})
-        .insert(Collider::ball(CUE_BALL_RADIUS))
-        .insert(BALL_RESTITUTION)
-        //.insert(ColliderMassProperties::Mass(0.40))
-        .insert(TransformBundle::from(Transform::from_xyz(0.0, STANDARD_BALL_RADIUS, 0.0)))
-        .insert(ColliderMassProperties::Mass(BALL_MASS))
-        .insert(BALL_DAMPING)
-        .insert(CueBall);
+impl Plugin for OnePocketRuleset {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(Referee(Box::new(OnePocketRules::new(WhoseMove::Player1))))
+        .add_plugins(GameMode);
     }
-      /* Create the cue ball. */
-
-    
+}
 
-} */
+pub struct EightBallRuleset;
 
-fn computer_ball_in_hand(mut commands: Commands, cue_ball_query: Query<Entity, With<CueBall>>,  mut set_state: ResMut<NextState<GamePhase>>) {
-    
-    if let Ok(cue_ball_entity) = cue_ball_query.get_single() {
-        commands.entity(cue_ball_entity).despawn();
-    }
-
-    commands
-        .spawn(RigidBody::Dynamic)
-        .insert(Collider::ball(CUE_BALL_RADIUS))
-        .insert(BALL_RESTITUTION)
-        //.insert(ColliderMassProperties::Mass(0.40))
-        .insert(TransformBundle::from(Transform::from_xyz(0.0, STANDARD_BALL_RADIUS, 0.0)))
-        .insert(ColliderMassProperties::Mass(BALL_MASS))
-        .insert(BALL_DAMPING)
-        .insert(CueBall)
-        .insert(Velocity { linvel: Vec3::ZERO, angvel: Vec3::ZERO })
-        .insert(Transform::from_translation(Vec3::new(0.0, CUE_BALL_RADIUS, 0.0)));
-    set_state.set(GamePhase::PreShot);
+impl Plugin for EightBallRuleset {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(Referee(Box::new(EightBallRules::new(WhoseMove::Player1))))
+        .add_plugins(GameMode);
+    }
 }
 
+fn clear_collision_events(mut collision_events: ResMut<Events<CollisionEvent>>,) {
 
+collision_events.clear();
+}
 
-fn despawn_pocketed_balls(mut commands: Commands, cue_ball_query: Query<(Entity, &Transform), With<CueBall>>, pool_ball_query: Query<(Entity, &Transform),With<PoolBalls>> ) {
-    if let Ok((cue_ball, cue_transform) )= cue_ball_query.get_single() {
-        if cue_transform.translation.y < -10.0 {
-            commands.entity(cue_ball).despawn();
-        }
-    }
+       
+// Running totals for the result report
+#[derive(Resource, Debug)]
+struct MatchStats {
+    started: std::time::Instant,
+    shots: u32,
+    fouls: Vec<report::CalledFoul>,
+}
 
-    for (i, target_ball_transform) in  pool_ball_query.iter() {
-        if target_ball_transform.translation.y < -10.0 {
-            commands.entity(i).despawn();
-        }
+impl Default for MatchStats {
+    fn default() -> Self {
+        MatchStats { started: std::time::Instant::now(), shots: 0, fouls: Vec::new() }
     }
-} 
+}
 
 fn state_setter_in_nine_ball_game( mut shot_made_reader: EventReader<ShotMade>,mut shot_finished_reader: EventReader<ShotCompletedPhysics>, mut next_phase: ResMut<NextState<GamePhase>>, ) {
     
@@ -824,22 +1361,13 @@ pub struct ShotCompletedPhysics; // Event to signal physics has settled
 fn check_if_balls_still_rolling(
     current_phase: Res<State<GamePhase>>, // Read current Phase
     mut next_phase: ResMut<NextState<GamePhase>>,
-    velocity_query: Query<&Velocity, With<PoolBalls>>,
-    cue_ball_velocity_query:  Query<&Velocity, With<CueBall>>, // Query for all balls that should stop
+    velocity_query: Query<&Velocity, AnyBall>, // Query for all balls that should stop
     mut event_writer: EventWriter<ShotCompletedPhysics>, // Event to signal physics has settled
 ) {
-    // Only proceed if the phase is InMotion
-for cue_vel in cue_ball_velocity_query.iter() {
-    if cue_vel.linvel.length_squared() > EPS * EPS || cue_vel.angvel.length_squared() > EPS * EPS {
-        return
-}
-}
     // Check if any ball is still moving
-    for vel in velocity_query.iter() {
-        if vel.linvel.length_squared() > EPS * EPS || vel.angvel.length_squared() > EPS * EPS {
-            // A ball is still moving, so we don't proceed.
-            return;
-        }
+    if !velocity_query.iter().all(ball_at_rest) {
+        // A ball is still moving, so we don't proceed.
+        return;
     }
 
     // If we reach this point, all balls have stopped.
@@ -847,65 +1375,72 @@ for cue_vel in cue_ball_velocity_query.iter() {
     event_writer.send(ShotCompletedPhysics);
 }
 
-// --- System 3: Evaluating Shot Rules ---
-// This system runs once when entering the `ShotEvaluation` phase.
-fn evaluate_shot_rules(
-    first_contact_made: Res<State<FirstContactHasBeenMade>>, // Read resource
-    mut scratch: ResMut<NextState<Scratch>>, // Read/Write resource
-    // Add other resources/queries needed for other rules (e.g., pocketed balls, correct object ball hit)
+// --- System 3: Judging the Shot ---
+// Runs once the balls have stopped, piped from the simulation's judge_shot:
+// the rules have read the shot log and decided fouls, who shoots next,
+// whether they get ball in hand and whether the game is over, and the balls
+// they return are back on the table. This system carries out the rest.
+fn carry_out_outcome(
+    In(outcome): In<ShotOutcome>,
+    mut commands: Commands,
+    table_spec: Res<TableSpec>,
+    mut referee: ResMut<Referee>,
+    mut rack_rng: ResMut<RackRng>,
+    mut match_score: ResMut<MatchScore>,
+    mut match_stats: ResMut<MatchStats>,
+    mut gamestate: ResMut<GameState>,
+    ball_query: Query<Entity, AnyBall>,
+    mut next_shooter: ResMut<NextState<WhoseMove>>,
+    mut next_phase: ResMut<NextState<GamePhase>>,
+    mut winner: ResMut<NextState<Winner>>,
+    mut game_ended_event_writer: EventWriter<GameEndedEvent>,
+    mut announcements: EventWriter<Announcement>,
+    replay: Res<ShotReplay>,
 ) {
-    println!("Evaluating shot rules...");
-
-    // Rule: Scratch if no valid first contact was made
-    if first_contact_made.get() == &FirstContactHasBeenMade::NotYet {
-        println!("SCRATCH: No first contact was made!");
-        scratch.set( Scratch(true)); // Set resource directly
-    } else {
-        println!("First contact was made.");
-        // Add logic for other scratch conditions or valid shot checks
+    println!("Shot outcome: {:?}", outcome);
+    match_stats.shots += 1;
+    for foul in &outcome.fouls {
+        println!("FOUL by {:?}: {}", outcome.shooter, foul);
+        match_stats.fouls.push(report::CalledFoul { shooter: outcome.shooter.clone(), foul: foul.clone() });
+        announcements.send(Announcement(ServerMessage::FoulCalled { shooter: outcome.shooter.clone(), reason: foul.clone() }));
     }
-
-    // After evaluating all rules, transition to PostShot (or whatever is next)
-    println!("Shot evaluation complete. Transitioning to Phase::PostShot.");
-}
-
-
-
-fn tabulate_for_nine_ball( mut first_contact: ResMut<NextState<FirstContactHasBeenMade>>, check_first_contact: Res<State<FirstContactHasBeenMade>> ,is_scratch: Res<State<Scratch>> ,mut scratch_setter: ResMut<NextState<Scratch>>, mut next_shooter: ResMut<NextState<WhoseMove>>, current_shooter: Res<State<WhoseMove>>, mut next_phase: ResMut<NextState<GamePhase>>,mut balls_on_table: ResMut<PoolBallsOnTable>, ball_query: Query<Entity, With<PoolBalls>>, cue_ball_query: Query<Entity, With<CueBall>>) {
-
-    let mut change_shooter = true;
-    let mut scratch = false;
-   
-    let ball_count = ball_query.iter().count() as u32;
-    if ball_count < balls_on_table.0 {
-        
-        change_shooter = false;
+    // a replayed shot carries its own outcome
+    if !replay.resolving() {
+        announcements.send(Announcement(ServerMessage::ShotResult(outcome.clone())));
     }
 
-    balls_on_table.0 = ball_count;
-
-    if let Ok(cue) =  cue_ball_query.get_single() {
-        
-    } else {
-        scratch = true;
-        change_shooter = true;
-    }
-    println!("{:?} {:?}", is_scratch.get().0, change_shooter);
-    if is_scratch.get().0 || scratch  || *check_first_contact.get() == FirstContactHasBeenMade::NotYet{
-        next_phase.set(GamePhase::BallInHand);
+    if let Some(result) = &outcome.result {
+        println!("{:?} wins the rack: {:?}", result.winner, result.reason);
+        if let Some(match_winner) = match_score.record_rack(&result.winner) {
+            println!("{:?} wins the match {}-{}", match_winner, match_score.racks_won[0], match_score.racks_won[1]);
+            next_shooter.set(outcome.next_shooter.clone());
+            announcements.send(Announcement(ServerMessage::MatchOver { winner: match_winner.clone(), racks_won: match_score.racks_won }));
+            winner.set(Winner(match_winner));
+            next_phase.set(GamePhase::GameEnded);
+            game_ended_event_writer.send(GameEndedEvent);
+        } else {
+            // clear the table and rack the next one
+            for entity in ball_query.iter() {
+                commands.entity(entity).despawn();
+            }
+            referee.0.new_rack(match_score.breaker.clone());
+            rack_balls(&mut commands, &table_spec, referee.0.as_ref(), &mut rack_rng.0);
+            println!("Rack {} to {:?} to break", match_score.racks_won[0] + match_score.racks_won[1] + 1, match_score.breaker);
+            next_shooter.set(match_score.breaker.clone());
+            next_phase.set(GamePhase::PreShot);
+        }
     } else {
-        println!("next phase set");
-        next_phase.set(GamePhase::PreShot);
+        next_shooter.set(outcome.next_shooter.clone());
+        if outcome.ball_in_hand {
+            announcements.send(Announcement(ServerMessage::BallInHandGranted { player: outcome.next_shooter.clone() }));
+            next_phase.set(GamePhase::BallInHand);
+        } else {
+            next_phase.set(GamePhase::PreShot);
+        }
     }
 
-    if change_shooter || is_scratch.get().0 || *check_first_contact.get() == FirstContactHasBeenMade::NotYet{
-        match current_shooter.get() {
-            WhoseMove::Player1 => next_shooter.set(WhoseMove::Player2),
-            WhoseMove::Player2 => next_shooter.set(WhoseMove::Player1),
-        };  
-    };
-    first_contact.set(FirstContactHasBeenMade::NotYet);
-    scratch_setter.set(Scratch(false));
+    // set here rather than in update_gamestate so the final packet sent on exit carries it
+    gamestate.last_outcome = Some(outcome);
 }
 
 
@@ -914,6 +1449,7 @@ fn send_game_ended_on_exit(
     // Use your actual Network Resource/Channel here
     network_out: Res<BrowserOutbound>,
     current_state: Res<GameState>, 
+    mut ticks: ResMut<SnapshotTicks>,
 ) {
     // Check if an exit event has been emitted this frame
     if !exit_events.is_empty() {
@@ -932,58 +1468,72 @@ fn send_game_ended_on_exit(
             let mut final_packet = current_state.clone();
             final_packet.phase = GamePhase::GameEnded;
 
-              match bincode::serialize(&final_packet) {
-        Ok(data) => {
-            // 2. Send to the Tokio listener via the channel
-            // We ignore errors because if no clients are connected, send fails (which is fine)
-            let _ = network_out.0.send(data);
-        },
-        Err(e) => {
-            eprintln!("Failed to serialize GameState: {}", e);
-        }
-    }
+            // 2. Send to the Tokio listener as a keyframe, so every client
+            // gets all of it whatever it last acknowledged
+            ticks.publish(&network_out, TickState::new(&final_packet), true);
         }
     }
     
 }
 
-//run before tabulate
-fn check_for_win_in_nine_ball(mut game_ended_event_writer: EventWriter<GameEndedEvent>, mut next_game_phase: ResMut<NextState<GamePhase>>,pool_ball_query: Query<&PoolBalls>, is_scratch: Res<State<Scratch>>, whose_turn: Res<State<WhoseMove>>, mut winner: ResMut<NextState<Winner>>) {
-    for i in pool_ball_query.iter() {
-        if i.0 == 9{
-            return
-        }
-    }
-    if is_scratch.get().0 {
-        match whose_turn.get() {
-            WhoseMove::Player1 => winner.set(Winner(WhoseMove::Player2)),
-            WhoseMove::Player2 => winner.set(Winner(WhoseMove::Player1)),
-        };
-    } else {
-        winner.set(Winner(whose_turn.get().clone()));
-    };
-
-    next_game_phase.set(GamePhase::GameEnded);
-
-    game_ended_event_writer.send(GameEndedEvent);
-}
-
 #[derive(Event)]
 pub struct GameEndedEvent;
 
-fn game_ended_event_reader(mut game_ended_event_reader: EventReader<GameEndedEvent>,mut exit: EventWriter<AppExit>) {
+// Where the result goes once the match is over, and the report on its way
+#[derive(Resource)]
+struct ResultReporter {
+    url: String,
+    secret: String,
+    sending: Option<std::thread::JoinHandle<()>>,
+}
+
+// Exits once the match is over, after nineballnet has been sent the result
+fn game_ended_event_reader(
+    mut game_ended_event_reader: EventReader<GameEndedEvent>,
+    mut exit: EventWriter<AppExit>,
+    mut reporter: ResMut<ResultReporter>,
+    game_tokens: Res<GameTokens>,
+    match_score: Res<MatchScore>,
+    match_stats: Res<MatchStats>,
+) {
+    if game_ended_event_reader.read().count() > 0 {
+        let winner = match_score.winner();
+        if winner.is_none() && !match_score.abandoned {
+            return;
+        }
+        if reporter.url.is_empty() {
+            exit.send(AppExit::Success);
+            return;
+        }
+        let result = report::MatchResult {
+            match_id: game_tokens.match_id.clone(),
+            winner_token: match winner {
+                Some(WhoseMove::Player1) => game_tokens.p1.clone(),
+                Some(WhoseMove::Player2) => game_tokens.p2.clone(),
+                None => String::new(),
+            },
+            // with no winner, Player1's racks and then Player2's
+            winner_racks: match_score.racks_won[winner.as_ref().map_or(0, |w| w.index())],
+            loser_racks: match_score.racks_won[winner.as_ref().map_or(1, |w| w.other().index())],
+            abandoned: match_score.abandoned,
+            shots: match_stats.shots,
+            duration_secs: match_stats.started.elapsed().as_secs(),
+            fouls: match_stats.fouls.clone(),
+        };
+        let (url, secret) = (reporter.url.clone(), reporter.secret.clone());
+        reporter.sending = Some(std::thread::spawn(move || match report::post_result(&url, &secret, &result) {
+            Ok(()) => println!("Reported result of match {}", result.match_id),
+            Err(e) => eprintln!("Could not report result of match {}: {}", result.match_id, e),
+        }));
+    }
 
-    for i in game_ended_event_reader.read() {
+    if reporter.sending.as_ref().is_some_and(|sending| sending.is_finished()) {
+        reporter.sending = None;
         exit.send(AppExit::Success);
-
     }
 }
 
 
-//
-fn setup_nine_ball_game() {
-
-}
 
 #[derive(Event)]
 pub struct HumanPlayerMoveStart;
@@ -998,19 +1548,11 @@ pub struct ComputerPlayerMoveStart;
 pub struct ShotMade;
 
 
-
-#[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
-enum MyAppState {
-    LoadingScreen,
-    MainMenu,
-    InGame(GameVariant),
-    PostGame
-}
-
-#[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(States, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum GameVariant {
     nine_ball,
     eight_ball,
+    ten_ball,
     straight_pool,
     one_pocket,
 }
@@ -1023,15 +1565,356 @@ use nine_ball_game::{BallData,GamePhase};
 
 
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
-enum FirstContactHasBeenMade {
-    Yes,
-    NotYet
-}
+struct Winner(WhoseMove);
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nine_ball_game::{CueTip, MAX_SHOT_POWER};
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn fuzz_app(phase: GamePhase) -> (App, mpsc::UnboundedSender<(Session, Vec<u8>)>, Entity) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (out, _) = broadcast::channel(1024);
+        let mut app = App::new();
+        app.add_plugins(StatesPlugin)
+            .insert_state(WhoseMove::Player1)
+            .insert_state(phase)
+            .add_event::<ShotMade>()
+            .insert_resource(BrowserInbound(rx))
+            .insert_resource(BrowserOutbound(out))
+            .insert_resource(GameTokens { p1: "p1".into(), p2: "p2".into(), match_id: "m".into() })
+            .insert_resource(ShotRecorder::default())
+            .insert_resource(Referee(Box::new(NineBallRules::new(WhoseMove::Player1))))
+            .insert_resource(ShotClock::new(Duration::from_secs(30), Duration::from_secs(30)))
+            .insert_resource(Inactivity { last_move: std::time::Instant::now(), limit: Duration::ZERO })
+            .insert_resource(TableSpec::default())
+            .insert_resource(ShotReplay::new(false))
+            .add_systems(Update, handle_incoming_network_messages);
+        let cue_ball = app.world_mut().spawn((CueBall, TransformBundle::default())).id();
+        (app, tx, cue_ball)
+    }
 
-#[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
-struct Scratch(bool);
+    fn random_f32(rng: &mut StdRng) -> f32 {
+        match rng.random_range(0..6) {
+            0 => f32::NAN,
+            1 => f32::INFINITY,
+            2 => f32::NEG_INFINITY,
+            3 => f32::MAX * if rng.random() { 1.0 } else { -1.0 },
+            4 => 0.0,
+            _ => rng.random_range(-100.0..100.0),
+        }
+    }
 
-#[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
-struct Winner(WhoseMove);
+    fn random_vec3(rng: &mut StdRng) -> Vec3 {
+        Vec3::new(random_f32(rng), random_f32(rng), random_f32(rng))
+    }
+
+    fn random_message(rng: &mut StdRng) -> Vec<u8> {
+        let message = match rng.random_range(0..4) {
+            0 => {
+                let mut bytes = vec![0u8; rng.random_range(0..64)];
+                rng.fill(&mut bytes[..]);
+                return bytes;
+            }
+            1 => ClientMessage::Shot {
+                power: random_f32(rng),
+                direction: random_vec3(rng),
+                tip: CueTip { offset: Vec2::new(random_f32(rng), random_f32(rng)), elevation: random_f32(rng) },
+            },
+            2 => ClientMessage::BallPlacement { position: random_vec3(rng) },
+            _ => ClientMessage::Extension,
+        };
+        bincode::serialize(&message).unwrap()
+    }
 
+    #[test]
+    fn fuzzed_messages_never_give_the_cue_ball_a_wild_velocity() {
+        let mut rng = StdRng::seed_from_u64(20);
+        for _ in 0..50 {
+            let (mut app, tx, cue_ball) = fuzz_app(GamePhase::PreShot);
+            let mut outbound = app.world().resource::<BrowserOutbound>().0.subscribe();
+            // the seat behind each message that calls for an answer, in order
+            let mut senders = Vec::new();
+            for _ in 0..20 {
+                let seat = [Seat::Player1, Seat::Player2, Seat::Spectator][rng.random_range(0..3)];
+                let message = random_message(&mut rng);
+                let answered = bincode::deserialize::<ClientMessage>(&message)
+                    .is_ok_and(|message| !matches!(message, ClientMessage::Join { .. } | ClientMessage::Hello { .. } | ClientMessage::Ack { .. }));
+                if answered {
+                    senders.push(seat);
+                }
+                tx.send((Session { id: 1, seat }, message)).unwrap();
+            }
+            app.update();
+
+            // a rejection does not stop the rest of the batch being handled
+            assert!(app.world().resource::<BrowserInbound>().0.is_empty());
+            // everything but the one shot taken is turned away, back to the seat it came from
+            let rejected = rejected_seats(&mut outbound);
+            if app.world().resource::<ShotRecorder>().recording {
+                let one_shot_taken = (0..senders.len())
+                    .any(|taken| senders[taken] == Seat::Player1 && [&senders[..taken], &senders[taken + 1..]].concat() == rejected);
+                assert!(one_shot_taken, "sent by {:?}, rejected to {:?}", senders, rejected);
+            } else {
+                assert_eq!(rejected, senders);
+            }
+            if let Some(velocity) = app.world().get::<Velocity>(cue_ball) {
+                assert!(velocity.linvel.is_finite() && velocity.angvel.is_finite());
+                assert!(velocity.linvel.length() <= MAX_SHOT_POWER * 1.001);
+            }
+            if let Some(transform) = app.world().get::<Transform>(cue_ball) {
+                assert!(transform.translation.is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn fuzzed_placements_leave_the_cue_ball_on_the_table() {
+        let mut rng = StdRng::seed_from_u64(20);
+        let spec = TableSpec::default();
+        for _ in 0..50 {
+            let (mut app, tx, cue_ball) = fuzz_app(GamePhase::BallInHand);
+            for _ in 0..20 {
+                let seat = [Seat::Player1, Seat::Player2, Seat::Spectator][rng.random_range(0..3)];
+                // near the table often enough that some placements are legal
+                let near_table = Vec3::new(rng.random_range(-1.0..1.0), rng.random_range(-0.1..0.1), rng.random_range(-1.5..1.5));
+                let message = match rng.random_range(0..3) {
+                    0 => bincode::serialize(&ClientMessage::BallPlacement { position: near_table }).unwrap(),
+                    1 => bincode::serialize(&ClientMessage::BallPlacement { position: random_vec3(&mut rng) }).unwrap(),
+                    _ => random_message(&mut rng),
+                };
+                tx.send((Session { id: 1, seat }, message)).unwrap();
+            }
+            app.update();
+
+            assert!(app.world().resource::<BrowserInbound>().0.is_empty());
+            let position = app.world().get::<Transform>(cue_ball).unwrap().translation;
+            assert!(position.is_finite());
+            assert!(position.x.abs() <= spec.half_width() && position.z.abs() <= spec.half_length());
+        }
+    }
+
+    #[test]
+    fn a_shot_with_no_aim_is_refused_and_leaves_the_cue_ball_still() {
+        let (mut app, tx, cue_ball) = fuzz_app(GamePhase::PreShot);
+        let shot = ClientMessage::Shot { power: f32::NAN, direction: Vec3::Y, tip: CueTip::default() };
+        tx.send((Session { id: 7, seat: Seat::Player1 }, bincode::serialize(&shot).unwrap())).unwrap();
+        app.update();
+
+        assert!(app.world().get::<Velocity>(cue_ball).is_none());
+        assert!(!app.world().resource::<ShotRecorder>().recording);
+    }
+
+    #[test]
+    fn an_oversized_shot_is_clamped_to_the_maximum_power() {
+        let (mut app, tx, cue_ball) = fuzz_app(GamePhase::PreShot);
+        let shot = ClientMessage::Shot { power: 1.0e9, direction: Vec3::new(0.0, 5.0, 1.0), tip: CueTip::default() };
+        tx.send((Session { id: 7, seat: Seat::Player1 }, bincode::serialize(&shot).unwrap())).unwrap();
+        app.update();
+
+        let velocity = app.world().get::<Velocity>(cue_ball).unwrap();
+        assert!(velocity.linvel.y.abs() < 1e-4);
+        assert!((velocity.linvel.length() - MAX_SHOT_POWER).abs() < 0.01 * MAX_SHOT_POWER);
+    }
+
+    #[test]
+    fn a_hello_from_any_version_reads_as_a_hello() {
+        // variant 0 then the version, however the rest of ClientMessage has moved
+        let old_client: Vec<u8> = [0u32, 3].iter().flat_map(|word| word.to_le_bytes()).collect();
+        assert!(matches!(bincode::deserialize::<ClientMessage>(&old_client), Ok(ClientMessage::Hello { protocol_version: 3 })));
+        let hello = bincode::serialize(&ClientMessage::Hello { protocol_version: PROTOCOL_VERSION }).unwrap();
+        assert_eq!(hello[..4], 0u32.to_le_bytes());
+    }
+
+    // The seat each Rejected so far went to, in the order they were sent
+    fn rejected_seats(outbound: &mut broadcast::Receiver<Outbound>) -> Vec<Seat> {
+        let mut seats = Vec::new();
+        while let Ok(sent) = outbound.try_recv() {
+            if let Outbound::Message(Some(seat), data) = sent {
+                if matches!(bincode::deserialize::<ServerMessage>(&data), Ok(ServerMessage::Rejected { .. })) {
+                    seats.push(seat);
+                }
+            }
+        }
+        seats
+    }
+
+    #[test]
+    fn only_moves_the_server_takes_hold_off_the_inactivity_limit() {
+        let (mut app, tx, _) = fuzz_app(GamePhase::PreShot);
+        let idle_since = std::time::Instant::now() - Duration::from_secs(60);
+        app.world_mut().resource_mut::<Inactivity>().last_move = idle_since;
+        let mut outbound = app.world().resource::<BrowserOutbound>().0.subscribe();
+        // out of turn, and the shooter with no push out to pass on and no clock to extend
+        for seat in [Seat::Player2, Seat::Player1] {
+            for message in [ClientMessage::Pass, ClientMessage::Extension] {
+                tx.send((Session { id: 1, seat }, bincode::serialize(&message).unwrap())).unwrap();
+            }
+        }
+        app.update();
+
+        assert_eq!(app.world().resource::<Inactivity>().last_move, idle_since);
+        assert_eq!(rejected_seats(&mut outbound), vec![Seat::Player2, Seat::Player2, Seat::Player1, Seat::Player1]);
+
+        let shot = ClientMessage::Shot { power: 5.0, direction: Vec3::Z, tip: CueTip::default() };
+        tx.send((Session { id: 1, seat: Seat::Player1 }, bincode::serialize(&shot).unwrap())).unwrap();
+        app.update();
+        assert!(app.world().resource::<Inactivity>().last_move > idle_since);
+    }
+
+    // Takes one POST, answers 200 and hands back its body
+    fn accept_one_report(listener: std::net::TcpListener) -> std::thread::JoinHandle<String> {
+        use std::io::{BufRead, BufReader, Read, Write};
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            reader.get_mut().write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            String::from_utf8(body).unwrap()
+        })
+    }
+
+    #[test]
+    fn an_idle_match_is_abandoned_and_reported_with_no_winner() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/matches/result", listener.local_addr().unwrap());
+        let report = accept_one_report(listener);
+
+        let limit = Duration::from_secs(60);
+        let mut score = MatchScore::new(3, BreakRule::Alternate, WhoseMove::Player1);
+        score.racks_won = [2, 1];
+        let mut app = App::new();
+        app.add_plugins(StatesPlugin)
+            .insert_state(GamePhase::PreShot)
+            .add_event::<GameEndedEvent>()
+            .add_event::<Announcement>()
+            .add_event::<AppExit>()
+            .insert_resource(score)
+            .insert_resource(MatchStats::default())
+            .insert_resource(GameTokens { p1: "p1".into(), p2: "p2".into(), match_id: "m".into() })
+            .insert_resource(ResultReporter { url, secret: "secret".into(), sending: None })
+            // nobody has moved for longer than the limit
+            .insert_resource(Inactivity { last_move: std::time::Instant::now() - limit * 2, limit })
+            .add_systems(Update, (abandon_inactive_match, game_ended_event_reader).chain());
+        app.update();
+
+        let announcements = app.world().resource::<Events<Announcement>>();
+        assert!(announcements
+            .get_reader()
+            .read(announcements)
+            .any(|Announcement(message)| matches!(message, ServerMessage::MatchAbandoned { racks_won: [2, 1] })));
+        assert!(app.world().resource::<MatchScore>().abandoned);
+
+        let report: serde_json::Value = serde_json::from_str(&report.join().unwrap()).unwrap();
+        assert_eq!(report["abandoned"], true);
+        assert_eq!(report["winner_token"], "");
+        assert_eq!(report["match_id"], "m");
+    }
+
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    // Makes a request to the control API from another thread, answering
+    // its commands on this one as run_match_host would
+    fn control_call(
+        host: &mut MatchHost,
+        commands: &std::sync::mpsc::Receiver<ws_gateway::ControlCommand>,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> (u16, String) {
+        let call = std::thread::spawn(move || {
+            let response = request.send().unwrap();
+            (response.status().as_u16(), response.text().unwrap())
+        });
+        while !call.is_finished() {
+            host.run_commands(commands);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        call.join().unwrap()
+    }
+
+    #[test]
+    fn matches_are_created_and_closed_through_the_control_api() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let gateway = ws_gateway::Gateway::new(runtime.handle().clone());
+        let (command_tx, commands) = std::sync::mpsc::channel();
+        let control_port = free_port();
+        runtime.spawn(ws_gateway::serve(free_port(), control_port, gateway.clone(), command_tx));
+        while std::net::TcpStream::connect(("127.0.0.1", control_port)).is_err() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let mut host = MatchHost { gateway, matches: HashMap::new() };
+        let client = reqwest::blocking::Client::new();
+        let url = format!("http://127.0.0.1:{}/matches", control_port);
+        let create = |match_id: &str, args: &[&str]| {
+            client
+                .post(&url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(serde_json::json!({ "match_id": match_id, "args": args }).to_string())
+        };
+
+        assert_eq!(control_call(&mut host, &commands, create("a", &["--p1-token", "a1", "--race-to", "3"])).0, 201);
+        assert!(host.matches.contains_key("a"));
+        let (status, listed) = control_call(&mut host, &commands, client.get(&url));
+        assert_eq!(status, 200);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&listed).unwrap()[0]["match_id"], "a");
+
+        for (args, because) in [(&["--port", "9000"][..], "--port is set for the whole server"), (&["--control-port=9001"], "--control-port"), (&["--race-to", "lots"], "race-to"), (&["--result-url", "http://127.0.0.1:1/result"], report::SECRET_ENV)] {
+            let (status, reason) = control_call(&mut host, &commands, create("b", args));
+            assert_eq!(status, 400, "{:?}", args);
+            assert!(reason.contains(because), "{:?}: {}", args, reason);
+        }
+        assert!(!host.matches.contains_key("b"));
+        assert_eq!(control_call(&mut host, &commands, create("a", &[])).0, 400, "already hosted");
+
+        let match_url = format!("{}/a", url);
+        assert_eq!(control_call(&mut host, &commands, client.delete(&match_url)).0, 204);
+        assert!(host.matches.is_empty());
+        assert_eq!(control_call(&mut host, &commands, client.delete(&match_url)).0, 404);
+    }
+
+    #[test]
+    fn hosted_matches_play_on_without_touching_each_other() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut host = MatchHost { gateway: ws_gateway::Gateway::new(runtime.handle().clone()), matches: HashMap::new() };
+        let tokens = |p1: &str, p2: &str| vec!["--p1-token".to_string(), p1.to_string(), "--p2-token".to_string(), p2.to_string()];
+        host.create("a".into(), tokens("a1", "a2")).unwrap();
+        host.create("b".into(), tokens("b1", "b2")).unwrap();
+        assert!(host.create("a".into(), Vec::new()).is_err());
+        for _ in 0..5 {
+            host.tick(std::time::Instant::now());
+        }
+
+        let shot = ClientMessage::Shot { power: 5.0, direction: Vec3::X, tip: CueTip::default() };
+        let network = host.gateway.network("a").unwrap();
+        network.to_bevy.send((Session { id: 1, seat: Seat::Player1 }, bincode::serialize(&shot).unwrap())).unwrap();
+        for _ in 0..3 {
+            host.tick(std::time::Instant::now());
+        }
+
+        let phase = |host: &MatchHost, match_id: &str| host.matches[match_id].world().resource::<State<GamePhase>>().get().clone();
+        assert_eq!(phase(&host, "a"), GamePhase::InMotion);
+        assert_eq!(phase(&host, "b"), GamePhase::PreShot);
+
+        assert!(host.destroy("a", Duration::ZERO));
+        assert!(!host.destroy("a", Duration::ZERO));
+        assert!(host.gateway.network("a").is_none());
+        assert_eq!(host.matches.len(), 1);
+    }
+}
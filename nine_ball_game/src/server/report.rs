@@ -0,0 +1,79 @@
+// src/server/report.rs
+// Tells nineballnet how a match ended. The result goes out as a JSON POST,
+// signed with HMAC-SHA256 under a secret nineballnet shares with the game
+// servers, so a player cannot report a win of their own.
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use nine_ball_game::{Foul, WhoseMove};
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+use sha2::Sha256;
+
+/// Lowercase hex HMAC-SHA256 of the request body
+pub const SIGNATURE_HEADER: &str = "X-Match-Signature";
+/// Environment variable holding the shared secret
+pub const SECRET_ENV: &str = "MATCH_RESULT_SECRET";
+
+const ATTEMPTS: u32 = 5;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CalledFoul {
+    pub shooter: WhoseMove,
+    pub foul: Foul,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MatchResult {
+    pub match_id: String,
+    /// Empty when the match was abandoned
+    pub winner_token: String,
+    pub winner_racks: u32,
+    pub loser_racks: u32,
+    /// Nobody played for the inactivity limit
+    pub abandoned: bool,
+    pub shots: u32,
+    pub duration_secs: u64,
+    pub fouls: Vec<CalledFoul>,
+}
+
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes a key of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// POST the result to `url`, backing off and retrying when nineballnet
+/// cannot be reached or has a server error. Blocks, so keep it off the
+/// Bevy thread.
+pub fn post_result(url: &str, secret: &str, result: &MatchResult) -> Result<(), String> {
+    let body = serde_json::to_vec(result).map_err(|e| e.to_string())?;
+    let signature = sign(secret.as_bytes(), &body);
+    let client = reqwest::blocking::Client::builder().timeout(REQUEST_TIMEOUT).build().map_err(|e| e.to_string())?;
+
+    let mut delay = FIRST_RETRY_DELAY;
+    for attempt in 1..=ATTEMPTS {
+        let response = client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone())
+            .send();
+        match response {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            // sending the same result again will not change the answer
+            Ok(response) if response.status().is_client_error() => {
+                return Err(format!("nineballnet rejected the result: {}", response.status()));
+            }
+            Ok(response) => eprintln!("Result report attempt {} failed: {}", attempt, response.status()),
+            Err(e) => eprintln!("Result report attempt {} failed: {}", attempt, e),
+        }
+        if attempt < ATTEMPTS {
+            std::thread::sleep(delay);
+            delay *= 2;
+        }
+    }
+    Err(format!("gave up after {} attempts", ATTEMPTS))
+}
@@ -1,155 +1,181 @@
 // src/server/ws_gateway.rs
+// Multi-match mode (--control-port). One listener takes the sockets for
+// every match in the process: each gets a SessionId and is routed by the
+// match id in its path to that match's channels. A control API, bound to
+// localhost only, creates and closes matches.
 use std::collections::HashMap;
-use std::sync::Arc;
-use futures::{SinkExt, StreamExt};
-use tokio::sync::{mpsc as tokio_mpsc, Mutex as TokioMutex};
-use tokio_tungstenite::tungstenite::Message as WsMessage;
-use tokio_tungstenite::accept_async;
-use tokio::net::TcpListener;
-use tokio::task;
-use bevy::prelude::*;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{ws::WebSocketUpgrade, Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{any, delete, get},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use super::{handle_socket, NetworkState};
 
 /// Gateway-assigned id for each browser connection
 pub type SessionId = u64;
 
-/// Inbound bytes from browsers into Bevy
-pub type BrowserInboundSender = tokio_mpsc::UnboundedSender<(SessionId, Vec<u8>)>;
-pub type BrowserInboundReceiver = tokio_mpsc::UnboundedReceiver<(SessionId, Vec<u8>)>;
-
-/// Outbound bytes from Bevy to browsers
-pub type BrowserOutboundSender = tokio_mpsc::UnboundedSender<(SessionId, Vec<u8>)>;
-pub type BrowserOutboundReceiver = tokio_mpsc::UnboundedReceiver<(SessionId, Vec<u8>)>;
-
-/// Per-socket channel type used by the gateway write task
-pub type ToSocketSender = tokio_mpsc::UnboundedSender<WsMessage>;
-
-/// Internal shared map type (not a Bevy resource directly)
-type InnerConnectionMap = Arc<TokioMutex<HashMap<SessionId, ToSocketSender>>>;
-
-/// Bevy resource wrapper for the connection map
-#[derive(Resource, Clone)]
-pub struct ConnectionMap(pub InnerConnectionMap);
-
-/// Bevy resource wrapper for the inbound channel
-#[derive(Resource)]
-pub struct BrowserInbound(pub BrowserInboundReceiver);
-
-/// Bevy resource wrapper for the outbound channel sender
-#[derive(Resource)]
-pub struct BrowserOutbound(pub BrowserOutboundSender);
-
-
-// ... (Type aliases and Resource wrappers remain the same)
-
-/// Starts the async WebSocket gateway in a new dedicated Tokio thread.
-pub fn start_ws_gateway(addr: String) -> (BrowserInbound, BrowserOutbound, ConnectionMap) {
-    let (in_tx, in_rx) = tokio_mpsc::unbounded_channel();
-    let (out_tx, out_rx) = tokio_mpsc::unbounded_channel();
-    let inner_map: InnerConnectionMap = Arc::new(TokioMutex::new(HashMap::new()));
-    
-    let connection_map_for_bevy = inner_map.clone(); // Clone for the return value
-
-    // Spawn dedicated thread for Tokio runtime to handle async networking
-    std::thread::spawn(move || {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .expect("Failed to build Tokio runtime");
-
-        rt.block_on(async move {
-            let listener = TcpListener::bind(&addr).await.expect("WS gateway: Failed to bind");
-            println!("WS gateway listening on ws://{}", addr);
-
-            let map_clone_writer = inner_map.clone(); // Clone for Task 1
-            
-            // Task 1: Handle messages from Bevy (out_rx) and send to specific browsers
-            task::spawn(async move {
-                let mut out_rx = out_rx;
-                while let Some((session_id, data)) = out_rx.recv().await {
-                    let map = map_clone_writer.lock().await;
-                    if let Some(tx) = map.get(&session_id) {
-                        if let Err(e) = tx.send(WsMessage::Binary(data)) {
-                            eprintln!("WS gateway: failed to send to socket channel: {}", e);
-                        }
-                    }
-                }
-            });
-
-            let mut session_counter: SessionId = 0;
-            // Task 2: Accept incoming connections (uses the original `inner_map`)
-            loop {
-                match listener.accept().await {
-                    Ok((stream, addr)) => {
-                        match accept_async(stream).await {
-                            Ok(ws_stream) => {
-                                session_counter += 1;
-                                let session_id = session_counter;
-                                let (mut ws_sink, mut ws_source) = ws_stream.split();
-                                
-                                let (to_socket_tx, mut to_socket_rx) = tokio_mpsc::unbounded_channel();
-                                
-                                // FIX: Clone the inner_map for this loop iteration before use/move
-                                let current_map_ref = inner_map.clone(); 
-                                
-                                {
-                                    let mut map = current_map_ref.lock().await;
-                                    map.insert(session_id, to_socket_tx);
-                                }
-
-                                let in_tx_clone = in_tx.clone();
-                                let connection_map_clone_read = current_map_ref.clone(); // Clone for read cleanup
-
-                                // Read task: reads from the socket and sends to Bevy
-                                let read_task = task::spawn(async move {
-                                    while let Some(Ok(ws_msg)) = ws_source.next().await {
-                                        match ws_msg {
-                                            WsMessage::Binary(data) => {
-                                                let _ = in_tx_clone.send((session_id, data));
-                                            }
-                                            WsMessage::Text(text) => {
-                                                let _ = in_tx_clone.send((session_id, text.into_bytes()));
-                                            }
-                                            WsMessage::Close(_) => break,
-                                            _ => {}
-                                        }
-                                    }
-                                    // cleanup
-                                    let mut map = connection_map_clone_read.lock().await;
-                                    map.remove(&session_id);
-                                    println!("WS gateway: session {} disconnected (read loop)", session_id);
-                                });
-
-                                let connection_map_clone_write = current_map_ref.clone(); // Clone for write cleanup
-
-                                // Write task: receives from the to_socket channel and writes to the socket
-                                let write_task = task::spawn(async move {
-                                    while let Some(ws_msg) = to_socket_rx.recv().await {
-                                        if let Err(e) = ws_sink.send(ws_msg).await {
-                                            eprintln!("WS gateway: ws send error: {}", e);
-                                            break;
-                                        }
-                                    }
-                                    // cleanup
-                                    let mut map = connection_map_clone_write.lock().await;
-                                    map.remove(&session_id);
-                                    println!("WS gateway: session {} disconnected (write loop)", session_id);
-                                });
-
-                                let _ = (read_task, write_task);
-                                println!("WS gateway: accepted browser session {} from {}", session_id, addr);
-                            }
-                            Err(e) => {
-                                eprintln!("WS gateway: handshake failed for {}: {}", addr, e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("WS gateway: accept error: {}", e);
-                    }
-                }
-            }
+// Sessions are numbered from 1 in the order they connect, across all matches
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+pub fn next_session_id() -> SessionId {
+    NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// The match a session is in, and how to hang it up
+type OpenSession = (String, oneshot::Sender<()>);
+
+/// Every open session, across all matches
+#[derive(Clone, Default)]
+pub struct ConnectionMap(Arc<Mutex<HashMap<SessionId, OpenSession>>>);
+
+/// Where sessions are routed: the channels of every hosted match
+#[derive(Clone)]
+pub struct Gateway {
+    matches: Arc<Mutex<HashMap<String, NetworkState>>>,
+    connections: ConnectionMap,
+    runtime: tokio::runtime::Handle,
+}
+
+impl Gateway {
+    pub fn new(runtime: tokio::runtime::Handle) -> Self {
+        Gateway { matches: Arc::default(), connections: ConnectionMap::default(), runtime }
+    }
+
+    pub fn add_match(&self, match_id: String, network: NetworkState) {
+        self.matches.lock().unwrap().insert(match_id, network);
+    }
+
+    pub fn network(&self, match_id: &str) -> Option<NetworkState> {
+        self.matches.lock().unwrap().get(match_id).cloned()
+    }
+
+    /// Stops routing to a match and hangs up its sessions after `linger`.
+    pub fn remove_match(&self, match_id: &str, linger: Duration) {
+        self.matches.lock().unwrap().remove(match_id);
+        let connections = self.connections.clone();
+        let match_id = match_id.to_string();
+        self.runtime.spawn(async move {
+            tokio::time::sleep(linger).await;
+            connections.0.lock().unwrap().retain(|_, (session_match, _)| *session_match != match_id);
         });
-    });
-
-    (BrowserInbound(in_rx), BrowserOutbound(out_tx), ConnectionMap(connection_map_for_bevy))
-}
\ No newline at end of file
+    }
+
+    fn sessions_in(&self, match_id: &str) -> usize {
+        self.connections.0.lock().unwrap().values().filter(|(session_match, _)| session_match == match_id).count()
+    }
+}
+
+/// What the control API asks of the thread hosting the matches
+pub enum ControlCommand {
+    // args are the server's usual command line, minus --match-id
+    Create { match_id: String, args: Vec<String>, reply: oneshot::Sender<Result<(), String>> },
+    Destroy { match_id: String, reply: oneshot::Sender<bool> },
+}
+
+#[derive(Clone)]
+struct ControlState {
+    gateway: Gateway,
+    commands: std::sync::mpsc::Sender<ControlCommand>,
+}
+
+#[derive(Deserialize)]
+struct CreateMatch {
+    match_id: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct HostedMatch {
+    match_id: String,
+    sessions: usize,
+}
+
+pub async fn serve(port: u16, control_port: u16, gateway: Gateway, commands: std::sync::mpsc::Sender<ControlCommand>) {
+    let sockets = Router::new()
+        .route("/match/:match_id", any(match_socket))
+        .with_state(gateway.clone());
+    let control = Router::new()
+        .route("/matches", get(list_matches).post(create_match))
+        .route("/matches/:match_id", delete(destroy_match))
+        .with_state(ControlState { gateway, commands });
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let control_addr = SocketAddr::from(([127, 0, 0, 1], control_port));
+    println!("WebSocket gateway bound to {}, control API to {}", addr, control_addr);
+
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    let control_listener = tokio::net::TcpListener::bind(control_addr).await.unwrap();
+    let (served, controlled) = tokio::join!(axum::serve(listener, sockets), axum::serve(control_listener, control));
+    served.unwrap();
+    controlled.unwrap();
+}
+
+async fn match_socket(
+    ws: WebSocketUpgrade,
+    Path(match_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(gateway): State<Gateway>,
+) -> impl IntoResponse {
+    let Some(network) = gateway.network(&match_id) else {
+        return (StatusCode::NOT_FOUND, "no such match").into_response();
+    };
+    let token = params.get("token").cloned().unwrap_or_default();
+    let seat = network.tokens.seat_for(&token);
+    ws.on_upgrade(move |socket| async move {
+        let session_id = next_session_id();
+        let (hang_up, hung_up) = oneshot::channel();
+        gateway.connections.0.lock().unwrap().insert(session_id, (match_id.clone(), hang_up));
+        tokio::select! {
+            _ = handle_socket(socket, network, seat, session_id) => {}
+            _ = hung_up => println!("Session {} hung up, match {} is closed", session_id, match_id),
+        }
+        gateway.connections.0.lock().unwrap().remove(&session_id);
+    })
+    .into_response()
+}
+
+async fn list_matches(State(control): State<ControlState>) -> impl IntoResponse {
+    let mut match_ids: Vec<String> = control.gateway.matches.lock().unwrap().keys().cloned().collect();
+    match_ids.sort();
+    let matches: Vec<HostedMatch> = match_ids
+        .into_iter()
+        .map(|match_id| HostedMatch { sessions: control.gateway.sessions_in(&match_id), match_id })
+        .collect();
+    Json(matches)
+}
+
+async fn create_match(State(control): State<ControlState>, Json(request): Json<CreateMatch>) -> impl IntoResponse {
+    let (reply, response) = oneshot::channel();
+    let command = ControlCommand::Create { match_id: request.match_id, args: request.args, reply };
+    if control.commands.send(command).is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "host has stopped".to_string());
+    }
+    match response.await {
+        Ok(Ok(())) => (StatusCode::CREATED, String::new()),
+        Ok(Err(e)) => (StatusCode::BAD_REQUEST, e),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "host has stopped".to_string()),
+    }
+}
+
+async fn destroy_match(State(control): State<ControlState>, Path(match_id): Path<String>) -> StatusCode {
+    let (reply, response) = oneshot::channel();
+    if control.commands.send(ControlCommand::Destroy { match_id, reply }).is_err() {
+        return StatusCode::SERVICE_UNAVAILABLE;
+    }
+    match response.await {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
@@ -0,0 +1,843 @@
+// src/simulation.rs
+// The table as Rapier simulates it, with no windows or sockets: the bodies
+// that make up the table and the balls, the systems that correct Rapier with
+// the models in physics, and the shot log kept while the balls move. The
+// server runs it inside its match app; `Table` runs it on its own, a shot
+// at a time, for AI players, tests and offline analysis.
+use std::collections::HashMap;
+
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use bevy::scene::SceneSpawner;
+use bevy_rapier3d::prelude::*;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::physics::{cue, throw};
+use super::rules::{Ruleset, ShotEvent, ShotLog, ShotOutcome, CUE_BALL};
+use super::table::TableBox;
+use super::{
+    BallData, CueTip, GameState, Pocket, TableSpec, BALL_FRICTION_COEFF, BALL_MASS, BALL_RESTITUTION, BED_HALF_THICKNESS,
+    CUE_BALL_RADIUS, DEFAULT_VELOCITY, FRICTION_COEFF, GRAVITY, STANDARD_BALL_RADIUS,
+};
+
+// Seconds of play per physics step
+pub const PHYSICS_STEP: f32 = 1.0 / 60.0;
+// A ball moving and spinning slower than this is at rest
+pub const REST_SPEED: f32 = 1e-2;
+// Longest a shot runs on a `Table` before it is judged as it lies
+pub const MAX_SHOT_STEPS: u32 = 120 * 60;
+// How far above resting height a ball can be and still be on the cloth
+const CLOTH_CONTACT_TOLERANCE: f32 = 1e-3;
+
+#[derive(Component, PartialEq, PartialOrd, Eq, Ord, Debug, Clone, Copy, Hash)]
+pub struct PoolBalls(pub u32);
+
+#[derive(Component)]
+pub struct CueBall;
+
+// Query filter for every ball, cue ball included
+pub type AnyBall = Or<(With<PoolBalls>, With<CueBall>)>;
+
+// A rail or jaw facing, with the box it was built from
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Cushion(pub TableBox);
+
+impl Cushion {
+    // horizontal direction from a ball centre to the nearest point of the cushion
+    fn direction_from(&self, point: Vec3) -> Vec3 {
+        let local = self.0.rotation.inverse() * (point - self.0.center);
+        let nearest = self.0.center + self.0.rotation * local.clamp(-self.0.half_size, self.0.half_size);
+        let to_cushion = nearest - point;
+        Vec3::new(to_cushion.x, 0.0, to_cushion.z).normalize_or_zero()
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PocketSensor(pub Pocket);
+
+// What happened during the shot in progress, for the rules to judge
+#[derive(Resource, Debug, Default)]
+pub struct ShotRecorder {
+    pub log: ShotLog,
+    pub recording: bool,
+}
+
+// The rules of the game on the table
+#[derive(Resource, Debug)]
+pub struct Referee(pub Box<dyn Ruleset>);
+
+// Shuffles the racks, and the balls racked again part way through a game.
+// Whoever runs the simulation supplies it: the server from the OS, a `Table`
+// from its seed.
+#[derive(Resource, Debug)]
+pub struct RackRng(pub StdRng);
+
+// Ball velocities going into this frame's physics step, so the corrections
+// that run after the step can work from what the ball was doing on impact
+#[derive(Resource, Debug, Default)]
+struct PreStepVelocities(HashMap<Entity, Velocity>);
+
+// Pairs of balls that have started touching but whose impact Rapier may not
+// have resolved yet
+#[derive(Resource, Debug, Default)]
+struct PendingBallHits(Vec<(Entity, Entity)>);
+
+/// Rapier, stepping `PHYSICS_STEP` a frame, and the ball physics it does not
+/// model itself. Add it to an app that has `MinimalPlugins` or more.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((HierarchyPlugin, TransformPlugin));
+
+        // Rapier's systems for colliders built from meshes and scenes read
+        // these; nothing here is built that way, so they stay empty
+        app.init_resource::<Assets<Mesh>>().init_resource::<SceneSpawner>();
+
+        app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default()).insert_resource(RapierConfiguration {
+            gravity: Vec3::new(0.0, -GRAVITY, 0.0),
+            timestep_mode: TimestepMode::Fixed { dt: PHYSICS_STEP, substeps: 1 },
+            physics_pipeline_active: true,
+            query_pipeline_active: true,
+            scaled_shape_subdivision: 2,
+            force_update_from_transform_changes: false,
+        });
+
+        app.init_resource::<ShotRecorder>()
+            .init_resource::<PreStepVelocities>()
+            .init_resource::<PendingBallHits>()
+            .add_systems(Update, (capture_pocketed_balls, despawn_off_table_balls).run_if(is_recording_shot))
+            .add_systems(PostUpdate, record_shot_events.run_if(is_recording_shot).after(PhysicsSet::Writeback))
+            .add_systems(PostUpdate, (apply_cloth_friction, record_pre_step_velocities).chain().before(PhysicsSet::SyncBackend))
+            .add_systems(PostUpdate, (track_ball_contacts, apply_ball_throw, apply_cushion_rebound).chain().after(PhysicsSet::Writeback));
+    }
+}
+
+/// The slate, the cushions with openings for the six pockets, the jaws and a
+/// capture sensor over each pocket.
+pub fn spawn_table(commands: &mut Commands, spec: &TableSpec) {
+    // the slate runs out to the back of the rails so balls can roll into the pockets
+    let bed = spec.bed_half_size();
+    commands
+        .spawn(RigidBody::Fixed)
+        .insert(Collider::cuboid(bed.x, BED_HALF_THICKNESS, bed.z))
+        // frictionless for Rapier: apply_cloth_friction models the ball on the cloth
+        .insert(Friction { coefficient: 0.0, combine_rule: CoefficientCombineRule::Min })
+        .insert(TransformBundle::from(Transform::from_xyz(0.0, -BED_HALF_THICKNESS, 0.0)));
+
+    commands
+        .spawn(RigidBody::Fixed)
+        .insert(Collider::cuboid(spec.half_width(), 0.0, spec.half_length()))
+        .insert(Friction::coefficient(spec.cloth_friction))
+        .insert(TransformBundle::from(Transform::from_xyz(0.0, 5.0, 0.0)));
+
+    for rail in spec.rail_boxes() {
+        spawn_cushion(commands, spec, rail);
+    }
+
+    for pocket in Pocket::ALL {
+        for jaw in pocket.jaw_boxes(spec) {
+            spawn_cushion(commands, spec, jaw);
+        }
+
+        // the sensor touches a ball exactly when the ball's centre is over the hole
+        commands
+            .spawn(Collider::cylinder(STANDARD_BALL_RADIUS, pocket.hole_radius(spec) - STANDARD_BALL_RADIUS))
+            .insert(Sensor)
+            .insert(PocketSensor(pocket))
+            .insert(TransformBundle::from(Transform::from_translation(pocket.hole_center(spec) + Vec3::Y * STANDARD_BALL_RADIUS)));
+    }
+}
+
+fn spawn_cushion(commands: &mut Commands, spec: &TableSpec, cushion: TableBox) {
+    commands
+        .spawn(RigidBody::Fixed)
+        .insert(Collider::cuboid(cushion.half_size.x, cushion.half_size.y, cushion.half_size.z))
+        .insert(TransformBundle::from_transform(cushion.transform()))
+        .insert(Cushion(cushion))
+        .insert(Friction::coefficient(FRICTION_COEFF))
+        .insert(Restitution { coefficient: spec.cushion_restitution, combine_rule: CoefficientCombineRule::Max });
+}
+
+/// The cue ball on the head spot and the object balls racked for the game
+/// `rules` referee, shuffled with `rng`.
+pub fn rack_balls(commands: &mut Commands, spec: &TableSpec, rules: &dyn Ruleset, rng: &mut dyn RngCore) {
+    spawn_cue_ball(commands, spec.head_spot() + Vec3::Y * CUE_BALL_RADIUS);
+    for (number, position) in rules.rack(spec, rng) {
+        spawn_pool_ball(commands, number, position);
+    }
+}
+
+pub fn spawn_cue_ball(commands: &mut Commands, position: Vec3) -> Entity {
+    commands
+        .spawn(RigidBody::Dynamic)
+        .insert(Collider::ball(CUE_BALL_RADIUS))
+        .insert(BALL_RESTITUTION)
+        .insert(TransformBundle::from(Transform::from_translation(position)))
+        .insert(ColliderMassProperties::Mass(BALL_MASS))
+        .insert(Friction::coefficient(BALL_FRICTION_COEFF))
+        .insert(DEFAULT_VELOCITY)
+        .insert(CueBall)
+        .insert(Ccd::enabled())
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .id()
+}
+
+pub fn spawn_pool_ball(commands: &mut Commands, number: u32, position: Vec3) -> Entity {
+    commands
+        .spawn(RigidBody::Dynamic)
+        .insert(Collider::ball(STANDARD_BALL_RADIUS))
+        .insert(BALL_RESTITUTION)
+        .insert(PoolBalls(number))
+        .insert(ColliderMassProperties::Mass(BALL_MASS))
+        .insert(DEFAULT_VELOCITY)
+        .insert(Friction::coefficient(BALL_FRICTION_COEFF))
+        .insert(TransformBundle::from(Transform::from_translation(position)))
+        .insert(Ccd::enabled())
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .id()
+}
+
+/// Puts back on the table the balls a shot's outcome returns to it: balls
+/// to rack again are taken up from wherever they lie and go in the rack,
+/// after anything in the way has been moved up to the head spot, and
+/// spotted balls go on the foot spot. A cue ball in the way of the rack is
+/// picked up instead and the outcome changed to give ball in hand.
+pub fn replace_balls(
+    commands: &mut Commands,
+    spec: &TableSpec,
+    rules: &dyn Ruleset,
+    outcome: &mut ShotOutcome,
+    balls: &mut Query<(Entity, &mut Transform, Option<&PoolBalls>), AnyBall>,
+    rng: &mut dyn RngCore,
+) {
+    let reracked = |number: Option<&PoolBalls>| number.is_some_and(|ball| outcome.rerack.contains(&ball.0));
+    let mut occupied: Vec<Vec3> = balls.iter().filter(|(_, _, number)| !reracked(*number)).map(|(_, t, _)| t.translation).collect();
+    if !outcome.rerack.is_empty() {
+        let rack = rules.rerack(spec, &outcome.rerack, rng);
+        let in_the_way = |p: Vec3| rack.iter().any(|&(_, at)| at.distance(p) < 2.0 * STANDARD_BALL_RADIUS);
+        let mut clear: Vec<Vec3> = occupied.iter().copied().filter(|&p| !in_the_way(p)).collect();
+        clear.extend(rack.iter().map(|&(_, at)| at));
+        let mut cue_ball_in_the_way = false;
+        for (entity, mut transform, number) in balls.iter_mut() {
+            if reracked(number) {
+                commands.entity(entity).despawn();
+            } else if !in_the_way(transform.translation) {
+                continue;
+            } else if number.is_none() {
+                commands.entity(entity).despawn();
+                cue_ball_in_the_way = true;
+            } else {
+                let position = spec.spot_position(spec.head_spot(), STANDARD_BALL_RADIUS, &clear);
+                transform.translation = position;
+                clear.push(position);
+            }
+        }
+        for &(number, position) in &rack {
+            spawn_pool_ball(commands, number, position);
+        }
+        occupied = clear;
+        outcome.ball_in_hand |= cue_ball_in_the_way;
+    }
+    for &number in &outcome.respotted {
+        let position = spec.spot_position(spec.foot_spot(), STANDARD_BALL_RADIUS, &occupied);
+        spawn_pool_ball(commands, number, position);
+        occupied.push(position);
+    }
+}
+
+pub fn ball_at_rest(velocity: &Velocity) -> bool {
+    velocity.linvel.length_squared() <= REST_SPEED * REST_SPEED && velocity.angvel.length_squared() <= REST_SPEED * REST_SPEED
+}
+
+pub fn is_recording_shot(shot_recorder: Res<ShotRecorder>) -> bool {
+    shot_recorder.recording
+}
+
+// Ball-ball and ball-cushion contacts. Pockets and balls leaving the table
+// are logged where they are handled, in capture_pocketed_balls and
+// despawn_off_table_balls.
+fn record_shot_events(
+    mut collision_events: EventReader<CollisionEvent>,
+    pool_ball_query: Query<&PoolBalls>,
+    cue_ball_query: Query<(), With<CueBall>>,
+    cushion_query: Query<(), With<Cushion>>,
+    mut shot_recorder: ResMut<ShotRecorder>,
+) {
+    let ball_number = |entity: Entity| {
+        if let Ok(pool_ball) = pool_ball_query.get(entity) {
+            Some(pool_ball.0)
+        } else if cue_ball_query.contains(entity) {
+            Some(CUE_BALL)
+        } else {
+            None
+        }
+    };
+
+    for event in collision_events.read() {
+        let CollisionEvent::Started(e1, e2, _flags) = event else {
+            continue;
+        };
+        let event = match (ball_number(*e1), ball_number(*e2)) {
+            (Some(first), Some(second)) => ShotEvent::BallContact { first, second },
+            (Some(ball), None) if cushion_query.contains(*e2) => ShotEvent::Cushion { ball },
+            (None, Some(ball)) if cushion_query.contains(*e1) => ShotEvent::Cushion { ball },
+            _ => continue,
+        };
+        shot_recorder.log.push(event);
+    }
+}
+
+fn capture_pocketed_balls(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    pocket_query: Query<&PocketSensor>,
+    pool_ball_query: Query<&PoolBalls>,
+    cue_ball_query: Query<(), With<CueBall>>,
+    mut shot_recorder: ResMut<ShotRecorder>,
+) {
+    for event in collision_events.read() {
+        let CollisionEvent::Started(e1, e2, _flags) = event else {
+            continue;
+        };
+
+        let (pocket, ball) = if let Ok(sensor) = pocket_query.get(*e1) {
+            (sensor.0, *e2)
+        } else if let Ok(sensor) = pocket_query.get(*e2) {
+            (sensor.0, *e1)
+        } else {
+            continue;
+        };
+
+        let number = if let Ok(pool_ball) = pool_ball_query.get(ball) {
+            pool_ball.0
+        } else if cue_ball_query.contains(ball) {
+            CUE_BALL
+        } else {
+            continue;
+        };
+
+        // a ball can only drop once; ignore a second sensor touching it this frame
+        if shot_recorder.log.pocketed().iter().any(|p| p.number == number) {
+            continue;
+        }
+
+        shot_recorder.log.push(ShotEvent::Pocketed { ball: number, pocket });
+        commands.entity(ball).despawn();
+    }
+}
+
+// Balls that leave the table over a rail fall past the slate and are removed
+fn despawn_off_table_balls(
+    mut commands: Commands,
+    ball_query: Query<(Entity, &Transform, Option<&PoolBalls>), AnyBall>,
+    mut shot_recorder: ResMut<ShotRecorder>,
+) {
+    for (ball, transform, pool_ball) in ball_query.iter() {
+        if transform.translation.y < -10.0 {
+            shot_recorder.log.push(ShotEvent::OffTable { ball: pool_ball.map_or(CUE_BALL, |p| p.0) });
+            commands.entity(ball).despawn();
+        }
+    }
+}
+
+// Ball/cloth friction, see physics::cloth. Rapier sees a frictionless slate,
+// so this is what turns skids into natural roll and brings the balls to rest.
+fn apply_cloth_friction(
+    table_spec: Res<TableSpec>,
+    rapier_config: Res<RapierConfiguration>,
+    time: Res<Time>,
+    mut ball_query: Query<(&Transform, &Collider, &mut Velocity), AnyBall>,
+) {
+    let dt = match rapier_config.timestep_mode {
+        TimestepMode::Fixed { dt, .. } => dt,
+        _ => time.delta_seconds(),
+    };
+    let cloth = table_spec.cloth();
+
+    for (transform, collider, mut velocity) in ball_query.iter_mut() {
+        let Some(ball) = collider.as_ball() else {
+            continue;
+        };
+        let radius = ball.radius();
+        // jumped balls get no cloth until they land
+        if transform.translation.y > radius + CLOTH_CONTACT_TOLERANCE {
+            continue;
+        }
+
+        let (linvel, angvel) = cloth.step(velocity.linvel, velocity.angvel, radius, dt);
+        // only touch Velocity when it changes so resting balls stay asleep
+        if linvel != velocity.linvel || angvel != velocity.angvel {
+            velocity.linvel = linvel;
+            velocity.angvel = angvel;
+        }
+    }
+}
+
+fn record_pre_step_velocities(mut pre_step: ResMut<PreStepVelocities>, ball_query: Query<(Entity, &Velocity), AnyBall>) {
+    pre_step.0.clear();
+    pre_step.0.extend(ball_query.iter().map(|(entity, velocity)| (entity, *velocity)));
+}
+
+// Rapier bounces balls off the cushions like flat walls. On the step where a
+// ball's approach to a cushion is turned around, replace Rapier's answer with
+// the cushion model, worked out from the velocity the ball came in with.
+fn apply_cushion_rebound(
+    table_spec: Res<TableSpec>,
+    rapier_context: Res<RapierContext>,
+    pre_step: Res<PreStepVelocities>,
+    cushion_query: Query<&Cushion>,
+    mut ball_query: Query<(Entity, &Transform, &Collider, &mut Velocity), AnyBall>,
+) {
+    let model = table_spec.cushion();
+
+    for (ball, transform, collider, mut velocity) in ball_query.iter_mut() {
+        let (Some(shape), Some(before)) = (collider.as_ball(), pre_step.0.get(&ball)) else {
+            continue;
+        };
+
+        for contact in rapier_context.contact_pairs_with(ball) {
+            if !contact.has_any_active_contact() {
+                continue;
+            }
+            let other = if contact.collider1() == ball { contact.collider2() } else { contact.collider1() };
+            let Ok(cushion) = cushion_query.get(other) else {
+                continue;
+            };
+
+            let into_cushion = cushion.direction_from(transform.translation);
+            if velocity.linvel.dot(into_cushion) > 0.0 {
+                // Rapier has not turned the ball around yet
+                continue;
+            }
+            if let Some((linvel, angvel)) = model.rebound(before.linvel, before.angvel, into_cushion, shape.radius(), BALL_MASS) {
+                velocity.linvel = linvel;
+                velocity.angvel = angvel;
+                break;
+            }
+        }
+    }
+}
+
+fn track_ball_contacts(
+    mut collision_events: EventReader<CollisionEvent>,
+    ball_query: Query<(), AnyBall>,
+    mut pending: ResMut<PendingBallHits>,
+) {
+    for event in collision_events.read() {
+        match event {
+            CollisionEvent::Started(e1, e2, _flags) => {
+                if ball_query.contains(*e1) && ball_query.contains(*e2) && !pending.0.contains(&(*e1, *e2)) {
+                    pending.0.push((*e1, *e2));
+                }
+            }
+            CollisionEvent::Stopped(e1, e2, _flags) => {
+                pending.0.retain(|pair| *pair != (*e1, *e2) && *pair != (*e2, *e1));
+            }
+        }
+    }
+}
+
+// Rapier's contact friction does not throw balls the way real balls do.
+// Once it has bounced a pair apart, redo the collision with the throw model,
+// starting from the velocities the balls had going into the step.
+fn apply_ball_throw(
+    pre_step: Res<PreStepVelocities>,
+    mut pending: ResMut<PendingBallHits>,
+    mut ball_query: Query<(&Transform, &Collider, &mut Velocity), AnyBall>,
+) {
+    let mut resolved = Vec::new();
+    pending.0.retain(|&(a, b)| {
+        let (Ok([(ta, ca, va), (tb, _, vb)]), Some(pre_a), Some(pre_b)) = (ball_query.get_many([a, b]), pre_step.0.get(&a), pre_step.0.get(&b)) else {
+            return false;
+        };
+        let line_of_centres = tb.translation - ta.translation;
+        if (pre_a.linvel - pre_b.linvel).dot(line_of_centres) <= 0.0 {
+            // resting against each other, or already moving apart
+            return false;
+        }
+        if (va.linvel - vb.linvel).dot(line_of_centres) > 0.0 {
+            // still closing, Rapier resolves the impact on a later step
+            return true;
+        }
+        if let Some(ball) = ca.as_ball() {
+            if let Some(after) = throw::collide((pre_a.linvel, pre_a.angvel), (pre_b.linvel, pre_b.angvel), line_of_centres, ball.radius(), BALL_MASS) {
+                resolved.push((a, b, after));
+            }
+        }
+        false
+    });
+
+    // a ball in more than one collision this step (a cluster, the break) is
+    // left to Rapier's solver, which handles simultaneous contacts
+    let in_one_hit = |entity: Entity| resolved.iter().filter(|(a, b, _)| *a == entity || *b == entity).count() == 1;
+    for &(a, b, (after_a, after_b)) in resolved.iter() {
+        if !(in_one_hit(a) && in_one_hit(b)) {
+            continue;
+        }
+        if let Ok([(_, _, mut va), (_, _, mut vb)]) = ball_query.get_many_mut([a, b]) {
+            (va.linvel, va.angvel) = after_a;
+            (vb.linvel, vb.angvel) = after_b;
+        }
+    }
+}
+
+/// A shot as a table will play it. `Shot::new` turns away what the server
+/// would, and trims the rest the way the server does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shot {
+    power: f32,
+    direction: Vec3,
+    tip: CueTip,
+}
+
+impl Shot {
+    pub fn new(power: f32, direction: Vec3, tip: CueTip) -> Result<Self, String> {
+        let (power, direction, tip) = cue::sanitize_shot(power, direction, tip)?;
+        Ok(Shot { power, direction, tip })
+    }
+}
+
+/// A table between shots, as `Table::snapshot` takes it and
+/// `Table::from_snapshot` sets it out again: the balls and where the game
+/// stands.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TableSnapshot {
+    // in ball number order, cue ball first
+    pub balls: Vec<BallData>,
+    // each ball's spin, in the same order
+    pub spins: Vec<Vec3>,
+    /// What the rules report about the game, and whose shot it is; its
+    /// balls are left empty. None for a table laid out by hand.
+    pub game: Option<GameState>,
+}
+
+/// A table in a world of its own, played a shot at a time with the same
+/// Rapier setup and rules as the server. Racks are shuffled from the seed
+/// the table is given, so the same seed, table and shots always give the
+/// same result.
+pub struct Table {
+    app: App,
+}
+
+impl Table {
+    /// A table racked for the game `rules` referee, cue ball on the head
+    /// spot, with every rack shuffled from `seed`.
+    pub fn new(spec: TableSpec, rules: Box<dyn Ruleset>, seed: u64) -> Self {
+        Table::build(spec, rules, seed, rack_balls)
+    }
+
+    /// A table with the balls where `snapshot` has them, refereed by `rules`
+    /// picked up where the snapshot's game stands, or as they stand if it
+    /// has none. Balls racked again later are shuffled from `seed`.
+    pub fn from_snapshot(spec: TableSpec, mut rules: Box<dyn Ruleset>, snapshot: &TableSnapshot, seed: u64) -> Self {
+        if let Some(game) = &snapshot.game {
+            let on_table: Vec<u32> = snapshot.balls.iter().filter(|ball| !ball.is_cue).map(|ball| ball.number).collect();
+            rules.restore(game, &on_table);
+        }
+        Table::build(spec, rules, seed, |commands, _, _, _| {
+            for (i, ball) in snapshot.balls.iter().enumerate() {
+                let entity = if ball.is_cue { spawn_cue_ball(commands, ball.position) } else { spawn_pool_ball(commands, ball.number, ball.position) };
+                let angvel = snapshot.spins.get(i).copied().unwrap_or(Vec3::ZERO);
+                commands
+                    .entity(entity)
+                    .insert(TransformBundle::from(Transform::from_translation(ball.position).with_rotation(ball.rotation)))
+                    .insert(Velocity { linvel: ball.velocity, angvel });
+            }
+        })
+    }
+
+    fn build(
+        spec: TableSpec,
+        rules: Box<dyn Ruleset>,
+        seed: u64,
+        spawn_balls: impl FnOnce(&mut Commands, &TableSpec, &dyn Ruleset, &mut dyn RngCore),
+    ) -> Self {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, SimulationPlugin));
+        // the runner never runs, so finish the plugins by hand
+        app.finish();
+        app.cleanup();
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let world = app.world_mut();
+        let mut commands = world.commands();
+        spawn_table(&mut commands, &spec);
+        spawn_balls(&mut commands, &spec, rules.as_ref(), &mut rng);
+        world.flush();
+        world.insert_resource(spec);
+        world.insert_resource(Referee(rules));
+        world.insert_resource(RackRng(rng));
+        Table { app }
+    }
+
+    pub fn rules(&self) -> &dyn Ruleset {
+        self.app.world().resource::<Referee>().0.as_ref()
+    }
+
+    /// The rules, to pass on the shooter's declarations.
+    pub fn rules_mut(&mut self) -> &mut dyn Ruleset {
+        self.app.world_mut().resource_mut::<Referee>().into_inner().0.as_mut()
+    }
+
+    /// Ball in hand: puts the cue ball at `position`, or the nearest legal
+    /// place to it, and says where that was.
+    pub fn place_cue_ball(&mut self, position: Vec3) -> Result<Vec3, String> {
+        let world = self.app.world_mut();
+        let occupied: Vec<Vec3> = world.query_filtered::<&Transform, With<PoolBalls>>().iter(world).map(|t| t.translation).collect();
+        let kitchen = world.resource::<Referee>().0.in_hand_behind_head_string();
+        let position = world.resource::<TableSpec>().place_cue_ball(position, CUE_BALL_RADIUS, &occupied, kitchen)?;
+
+        let cue_ball = world.query_filtered::<Entity, With<CueBall>>().get_single(world);
+        match cue_ball {
+            Ok(cue_ball) => {
+                world.entity_mut(cue_ball).insert(TransformBundle::from(Transform::from_translation(position))).insert(Velocity::zero());
+            }
+            Err(_) => {
+                spawn_cue_ball(&mut world.commands(), position);
+                world.flush();
+            }
+        }
+        Ok(position)
+    }
+
+    /// Strikes the cue ball, runs the balls until they stop (or for
+    /// `MAX_SHOT_STEPS`) and has the rules judge the shot. Balls the rules
+    /// put back are back on the table when this returns; once the outcome
+    /// has a result the game is over and the balls are left as they lie.
+    /// With the cue ball off the table the shot hits nothing.
+    pub fn apply_shot(&mut self, shot: Shot) -> ShotOutcome {
+        let world = self.app.world_mut();
+        if let Ok(cue_ball) = world.query_filtered::<Entity, With<CueBall>>().get_single(world) {
+            let strike = cue::strike(shot.direction, cue::cue_speed_for(shot.power), shot.tip, CUE_BALL_RADIUS);
+            world.entity_mut(cue_ball).insert(Velocity { linvel: strike.linvel, angvel: strike.angvel });
+        }
+        let mut shot_recorder = world.resource_mut::<ShotRecorder>();
+        shot_recorder.log.clear();
+        shot_recorder.recording = true;
+
+        for _ in 0..MAX_SHOT_STEPS {
+            self.app.update();
+            let world = self.app.world_mut();
+            if world.query_filtered::<&Velocity, AnyBall>().iter(world).all(ball_at_rest) {
+                break;
+            }
+        }
+
+        self.app.world_mut().run_system_once(judge_shot)
+    }
+
+    pub fn snapshot(&self) -> TableSnapshot {
+        let mut balls: Vec<(BallData, Vec3)> = self
+            .app
+            .world()
+            .iter_entities()
+            .filter_map(|entity| {
+                let number = match (entity.get::<PoolBalls>(), entity.contains::<CueBall>()) {
+                    (Some(pool_ball), _) => pool_ball.0,
+                    (None, true) => CUE_BALL,
+                    (None, false) => return None,
+                };
+                let transform = entity.get::<Transform>()?;
+                let velocity = entity.get::<Velocity>().copied().unwrap_or_default();
+                let ball = BallData {
+                    number,
+                    position: transform.translation,
+                    velocity: velocity.linvel,
+                    rotation: transform.rotation,
+                    is_cue: number == CUE_BALL,
+                };
+                Some((ball, velocity.angvel))
+            })
+            .collect();
+        balls.sort_by_key(|(ball, _)| ball.number);
+        let (balls, spins) = balls.into_iter().unzip();
+
+        let mut game = GameState { whose_move: self.rules().shooter(), ..Default::default() };
+        self.rules().report(&mut game);
+        TableSnapshot { balls, spins, game: Some(game) }
+    }
+}
+
+/// The rules' verdict on the shot just played, once the balls have stopped,
+/// with the balls it returns to the table put back. The server pipes the
+/// outcome on to carry out the rest of it.
+pub fn judge_shot(
+    mut commands: Commands,
+    table_spec: Res<TableSpec>,
+    mut referee: ResMut<Referee>,
+    mut shot_recorder: ResMut<ShotRecorder>,
+    mut rack_rng: ResMut<RackRng>,
+    mut ball_query: Query<(Entity, &mut Transform, Option<&PoolBalls>), AnyBall>,
+) -> ShotOutcome {
+    shot_recorder.recording = false;
+    let mut outcome = referee.0.evaluate(&shot_recorder.log);
+    if outcome.result.is_none() {
+        replace_balls(&mut commands, &table_spec, referee.0.as_ref(), &mut outcome, &mut ball_query, &mut rack_rng.0);
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::rules::nine_ball::NineBallRules;
+    use super::super::rules::straight_pool::StraightPoolRules;
+    use super::super::rules::{rack, Declaration, Foul};
+    use super::super::WhoseMove;
+
+    fn nine_ball() -> Box<dyn Ruleset> {
+        Box::new(NineBallRules::new(WhoseMove::Player1))
+    }
+
+    fn straight_pool(rules: StraightPoolRules) -> Box<dyn Ruleset> {
+        Box::new(StraightPoolRules { breaking: false, ..rules })
+    }
+
+    // has the table's rules judge `events` and puts the balls back as the server would
+    fn judge(table: &mut Table, events: Vec<ShotEvent>) -> ShotOutcome {
+        let outcome = table.rules_mut().evaluate(&ShotLog { events });
+        table.app.world_mut().run_system_once(
+            move |mut commands: Commands,
+                  spec: Res<TableSpec>,
+                  referee: Res<Referee>,
+                  mut rng: ResMut<RackRng>,
+                  mut balls: Query<(Entity, &mut Transform, Option<&PoolBalls>), AnyBall>| {
+                let mut outcome = outcome.clone();
+                replace_balls(&mut commands, &spec, referee.0.as_ref(), &mut outcome, &mut balls, &mut rng.0);
+                outcome
+            },
+        )
+    }
+
+    fn ball(number: u32, position: Vec3) -> BallData {
+        BallData { number, position, rotation: Quat::IDENTITY, is_cue: number == CUE_BALL, ..Default::default() }
+    }
+
+    #[test]
+    fn the_same_seed_racks_the_same_way() {
+        let rack = |seed| Table::new(TableSpec::default(), nine_ball(), seed).snapshot();
+        assert_eq!(rack(7), rack(7));
+        assert_ne!(rack(7), rack(8));
+    }
+
+    #[test]
+    fn the_same_break_on_the_same_rack_ends_the_same_way() {
+        let shot = Shot::new(10.0, Vec3::Z, CueTip::center()).unwrap();
+        let mut first = Table::new(TableSpec::default(), nine_ball(), 7);
+        let mut second = Table::new(TableSpec::default(), nine_ball(), 7);
+        assert_eq!(first.apply_shot(shot), second.apply_shot(shot));
+        assert_eq!(first.snapshot(), second.snapshot());
+    }
+
+    #[test]
+    fn a_table_set_out_from_a_snapshot_gives_the_same_snapshot() {
+        let table = Table::new(TableSpec::default(), nine_ball(), 0);
+        let snapshot = table.snapshot();
+        assert_eq!(snapshot.balls.len(), 10);
+        assert!(snapshot.balls[0].is_cue);
+        assert_eq!(Table::from_snapshot(TableSpec::default(), nine_ball(), &snapshot, 0).snapshot(), snapshot);
+    }
+
+    #[test]
+    fn a_table_set_out_from_its_snapshot_plays_on_like_the_original() {
+        // past the break, Player1 on two fouls; the copy starts from fresh rules
+        let rules = NineBallRules { breaking: false, consecutive_fouls: [2, 0], ..NineBallRules::new(WhoseMove::Player1) };
+        let mut original = Table::new(TableSpec::default(), Box::new(rules), 0);
+        let snapshot = original.snapshot();
+        assert_eq!(snapshot.spins.len(), snapshot.balls.len());
+        assert_eq!(snapshot.game.as_ref().map(|game| game.consecutive_fouls), Some([2, 0]));
+        let mut copy = Table::from_snapshot(TableSpec::default(), nine_ball(), &snapshot, 0);
+
+        // away from the rack, into the head rail: a third foul in a row
+        let shot = Shot::new(1.0, -Vec3::Z, CueTip::center()).unwrap();
+        let outcome = original.apply_shot(shot);
+        assert_eq!(outcome.fouls, vec![Foul::NoBallHit]);
+        assert!(outcome.result.is_some());
+        assert_eq!(copy.apply_shot(shot), outcome);
+        assert_eq!(copy.snapshot(), original.snapshot());
+    }
+
+    #[test]
+    fn a_ball_shot_straight_at_a_corner_drops_and_the_shooter_stays_at_the_table() {
+        let spec = TableSpec::default();
+        let pocket = Pocket::FootRight.hole_center(&spec);
+        let towards_pocket = Vec3::new(pocket.x, 0.0, pocket.z).normalize();
+        let one = towards_pocket * 0.6 + Vec3::Y * STANDARD_BALL_RADIUS;
+        let cue_ball = towards_pocket * 0.3 + Vec3::Y * CUE_BALL_RADIUS;
+        let snapshot = TableSnapshot { balls: vec![ball(CUE_BALL, cue_ball), ball(1, one)], ..Default::default() };
+        let mut table = Table::from_snapshot(spec, nine_ball(), &snapshot, 0);
+
+        let outcome = table.apply_shot(Shot::new(3.0, towards_pocket, CueTip::center()).unwrap());
+        assert_eq!(outcome.pocketed.iter().map(|p| (p.number, p.pocket)).collect::<Vec<_>>(), vec![(1, Pocket::FootRight)]);
+        assert!(!outcome.is_foul());
+        assert_eq!(outcome.next_shooter, WhoseMove::Player1);
+        assert!(table.snapshot().balls.iter().all(|ball| ball.number != 1));
+    }
+
+    #[test]
+    fn a_scratch_gives_ball_in_hand_and_the_cue_ball_can_be_placed_again() {
+        let spec = TableSpec::default();
+        let pocket = Pocket::FootRight.hole_center(&spec);
+        let towards_pocket = Vec3::new(pocket.x, 0.0, pocket.z).normalize();
+        let snapshot = TableSnapshot {
+            balls: vec![ball(CUE_BALL, towards_pocket * 0.6 + Vec3::Y * CUE_BALL_RADIUS), ball(1, Vec3::new(-0.3, STANDARD_BALL_RADIUS, 0.0))],
+            ..Default::default()
+        };
+        let mut table = Table::from_snapshot(spec, nine_ball(), &snapshot, 0);
+
+        let outcome = table.apply_shot(Shot::new(3.0, towards_pocket, CueTip::center()).unwrap());
+        assert!(outcome.ball_in_hand);
+        assert!(table.snapshot().balls.iter().all(|ball| !ball.is_cue));
+        table.place_cue_ball(Vec3::new(0.3, 0.0, 0.0)).unwrap();
+        assert_eq!(table.snapshot().balls[0].number, CUE_BALL);
+    }
+
+    #[test]
+    fn a_cue_ball_in_the_way_of_a_rerack_is_taken_up_for_ball_in_hand_in_the_kitchen() {
+        let spec = TableSpec::default();
+        // not the apex, which a rerack of fourteen leaves open
+        let in_the_rack = rack::triangle(spec.foot_spot(), 5)[2];
+        let snapshot = TableSnapshot {
+            balls: vec![
+                ball(CUE_BALL, Vec3::new(in_the_rack.x, CUE_BALL_RADIUS, in_the_rack.z)),
+                ball(11, Vec3::new(0.3, STANDARD_BALL_RADIUS, 0.0)),
+            ],
+            ..Default::default()
+        };
+        let rules = StraightPoolRules { on_table: vec![4, 11], ..StraightPoolRules::new(WhoseMove::Player1, 100) };
+        let mut table = Table::from_snapshot(spec.clone(), straight_pool(rules), &snapshot, 0);
+
+        table.rules_mut().declare(Declaration::CallShot { ball: 4, pocket: Pocket::FootLeft }).unwrap();
+        let outcome = judge(&mut table, vec![ShotEvent::BallContact { first: CUE_BALL, second: 4 }, ShotEvent::Pocketed { ball: 4, pocket: Pocket::FootLeft }]);
+        assert_eq!(outcome.rerack.len(), 14);
+        assert!(outcome.ball_in_hand);
+        let balls = table.snapshot().balls;
+        assert!(balls.iter().all(|ball| !ball.is_cue), "cue ball is off the table");
+        assert_eq!(balls.len(), 15);
+
+        assert!(table.place_cue_ball(spec.foot_spot() - Vec3::Z * 0.3).is_err(), "ahead of the head string");
+        table.place_cue_ball(spec.head_spot() - Vec3::Z * 0.1).unwrap();
+    }
+
+    #[test]
+    fn a_full_rerack_takes_up_the_balls_still_on_the_table() {
+        let snapshot = TableSnapshot {
+            balls: vec![
+                ball(CUE_BALL, Vec3::new(0.0, CUE_BALL_RADIUS, -0.4)),
+                ball(5, Vec3::new(-0.3, STANDARD_BALL_RADIUS, 0.0)),
+                ball(6, Vec3::new(0.3, STANDARD_BALL_RADIUS, 0.1)),
+            ],
+            ..Default::default()
+        };
+        let rules = StraightPoolRules { consecutive_fouls: [2, 0], ..StraightPoolRules::new(WhoseMove::Player1, 100) };
+        let mut table = Table::from_snapshot(TableSpec::default(), straight_pool(rules), &snapshot, 0);
+
+        let outcome = judge(&mut table, vec![ShotEvent::BallContact { first: CUE_BALL, second: 5 }]);
+        assert_eq!(outcome.rerack.len(), 15);
+        let numbers: Vec<u32> = table.snapshot().balls.iter().map(|ball| ball.number).collect();
+        assert_eq!(numbers, (0..=15).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,318 @@
+// src/snapshot.rs
+// Game state as it goes over the wire. Ball positions, velocities and
+// rotations are quantized to integers, and each client is sent only what
+// changed since the last tick it acknowledged, with a full keyframe now and
+// then so a client that missed something catches up.
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{BallData, GameState};
+
+// Units per metre (0.1 mm), per m/s (1 mm/s) and per unit quaternion component
+pub const POSITION_SCALE: f32 = 10_000.0;
+pub const VELOCITY_SCALE: f32 = 1_000.0;
+pub const ROTATION_SCALE: f32 = i16::MAX as f32;
+
+// Ticks each side remembers to delta against. A baseline older than this
+// gets a keyframe instead.
+pub const SNAPSHOT_HISTORY: usize = 64;
+
+fn quantize(value: f32, scale: f32) -> i16 {
+    // NaN becomes 0, anything out of range is pinned to the ends
+    (value * scale).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+fn quantize_vec3(v: Vec3, scale: f32) -> [i16; 3] {
+    [quantize(v.x, scale), quantize(v.y, scale), quantize(v.z, scale)]
+}
+
+fn dequantize_vec3(v: [i16; 3], scale: f32) -> Vec3 {
+    Vec3::new(v[0] as f32, v[1] as f32, v[2] as f32) / scale
+}
+
+/// One ball as it is sent: 22 bytes instead of 45.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuantizedBall {
+    pub number: u8,
+    pub is_cue: bool,
+    pub position: [i16; 3],
+    pub velocity: [i16; 3],
+    pub rotation: [i16; 4],
+}
+
+impl QuantizedBall {
+    pub fn new(ball: &BallData) -> Self {
+        let rotation = ball.rotation.normalize();
+        QuantizedBall {
+            number: ball.number as u8,
+            is_cue: ball.is_cue,
+            position: quantize_vec3(ball.position, POSITION_SCALE),
+            velocity: quantize_vec3(ball.velocity, VELOCITY_SCALE),
+            rotation: [
+                quantize(rotation.x, ROTATION_SCALE),
+                quantize(rotation.y, ROTATION_SCALE),
+                quantize(rotation.z, ROTATION_SCALE),
+                quantize(rotation.w, ROTATION_SCALE),
+            ],
+        }
+    }
+
+    pub fn ball(&self) -> BallData {
+        let [x, y, z, w] = self.rotation.map(|c| c as f32 / ROTATION_SCALE);
+        let rotation = Quat::from_xyzw(x, y, z, w);
+        BallData {
+            number: self.number as u32,
+            position: dequantize_vec3(self.position, POSITION_SCALE),
+            velocity: dequantize_vec3(self.velocity, VELOCITY_SCALE),
+            rotation: if rotation.length_squared() > 0.0 { rotation.normalize() } else { Quat::IDENTITY },
+            is_cue: self.is_cue,
+        }
+    }
+}
+
+// The balls in `after` that are new or moved since `before`, and the
+// numbers of those in `before` that are gone
+pub fn diff(before: &[QuantizedBall], after: &[QuantizedBall]) -> (Vec<QuantizedBall>, Vec<u8>) {
+    let changed = after.iter().filter(|ball| !before.contains(ball)).copied().collect();
+    let removed = before
+        .iter()
+        .filter(|old| !after.iter().any(|ball| ball.number == old.number))
+        .map(|old| old.number)
+        .collect();
+    (changed, removed)
+}
+
+// Undoes diff: brings `balls` from `before` to `after`
+pub fn patch(balls: &mut Vec<QuantizedBall>, changed: &[QuantizedBall], removed: &[u8]) {
+    balls.retain(|ball| !removed.contains(&ball.number));
+    for ball in changed {
+        match balls.iter_mut().find(|old| old.number == ball.number) {
+            Some(old) => *old = *ball,
+            None => balls.push(*ball),
+        }
+    }
+}
+
+/// The game as clients see it at one tick: the balls quantized and
+/// everything else in `rest`, whose `balls` stay empty.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TickState {
+    pub balls: Vec<QuantizedBall>,
+    pub rest: GameState,
+}
+
+impl TickState {
+    pub fn new(state: &GameState) -> Self {
+        let mut rest = GameState { balls: Vec::new(), ..state.clone() };
+        // whole seconds are all anyone reads, and a running clock would
+        // otherwise change the state every frame
+        rest.shot_clock = rest.shot_clock.map(f32::ceil);
+        TickState { balls: state.balls.iter().map(QuantizedBall::new).collect(), rest }
+    }
+
+    pub fn game_state(&self) -> GameState {
+        GameState { balls: self.balls.iter().map(QuantizedBall::ball).collect(), ..self.rest.clone() }
+    }
+}
+
+/// What the server sends for one tick, built against a tick the client has
+/// acknowledged or, as a keyframe, against nothing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    // counts the states the server has sent, one per frame at most
+    pub tick: u32,
+    // seconds since the server started, for the client to place it in time
+    pub server_time: f64,
+    // the acknowledged tick this builds on; None for a keyframe
+    pub baseline: Option<u32>,
+    // everything but the balls, when it differs from the baseline
+    pub state: Option<GameState>,
+    // balls that are new or have moved since the baseline
+    pub balls: Vec<QuantizedBall>,
+    // balls in the baseline that have since left the table
+    pub removed: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn keyframe(tick: u32, server_time: f64, state: &TickState) -> Self {
+        Snapshot { tick, server_time, baseline: None, state: Some(state.rest.clone()), balls: state.balls.clone(), removed: Vec::new() }
+    }
+
+    pub fn delta(tick: u32, server_time: f64, baseline_tick: u32, baseline: &TickState, state: &TickState) -> Self {
+        let (balls, removed) = diff(&baseline.balls, &state.balls);
+        let rest = (state.rest != baseline.rest).then(|| state.rest.clone());
+        Snapshot { tick, server_time, baseline: Some(baseline_tick), state: rest, balls, removed }
+    }
+
+    /// The full state at this tick, given the state at `self.baseline`.
+    pub fn apply(&self, baseline: Option<&TickState>) -> Result<TickState, String> {
+        let mut state = match (self.baseline, baseline) {
+            (None, _) => TickState::default(),
+            (Some(_), Some(baseline)) => baseline.clone(),
+            (Some(tick), None) => return Err(format!("snapshot {} builds on tick {}, which is gone", self.tick, tick)),
+        };
+        if let Some(rest) = &self.state {
+            state.rest = GameState { balls: Vec::new(), ..rest.clone() };
+        } else if self.baseline.is_none() {
+            return Err(format!("keyframe {} has no game state", self.tick));
+        }
+        patch(&mut state.balls, &self.balls, &self.removed);
+        Ok(state)
+    }
+}
+
+/// The server's record of one connection: the ticks sent to it and the
+/// newest one it has acknowledged.
+#[derive(Debug, Default)]
+pub struct SentSnapshots {
+    sent: VecDeque<(u32, Arc<TickState>)>,
+    acked: Option<u32>,
+}
+
+impl SentSnapshots {
+    pub fn ack(&mut self, tick: u32) {
+        if self.acked.is_none_or(|acked| tick > acked) && self.sent.iter().any(|(sent, _)| *sent == tick) {
+            self.acked = Some(tick);
+        }
+    }
+
+    /// The snapshot for `tick`: a delta against the acknowledged tick if
+    /// there is one to hand, otherwise (or when asked) a keyframe.
+    pub fn encode(&mut self, tick: u32, server_time: f64, state: Arc<TickState>, keyframe: bool) -> Snapshot {
+        let baseline = self.acked.and_then(|acked| self.sent.iter().find(|(sent, _)| *sent == acked));
+        let snapshot = match baseline {
+            Some((baseline_tick, baseline)) if !keyframe => Snapshot::delta(tick, server_time, *baseline_tick, baseline, &state),
+            _ => Snapshot::keyframe(tick, server_time, &state),
+        };
+        self.sent.push_back((tick, state));
+        while self.sent.len() > SNAPSHOT_HISTORY {
+            if let Some((forgotten, _)) = self.sent.pop_front() {
+                if self.acked == Some(forgotten) {
+                    self.acked = None;
+                }
+            }
+        }
+        snapshot
+    }
+}
+
+/// The client's record of the ticks it has rebuilt, to apply deltas to.
+#[derive(Resource, Debug, Default)]
+pub struct ReceivedSnapshots {
+    received: VecDeque<(u32, TickState)>,
+}
+
+impl ReceivedSnapshots {
+    /// Rebuilds the state at `snapshot.tick`. The tick should be
+    /// acknowledged once this succeeds.
+    pub fn apply(&mut self, snapshot: &Snapshot) -> Result<GameState, String> {
+        let baseline = snapshot.baseline.and_then(|tick| self.received.iter().find(|(received, _)| *received == tick)).map(|(_, state)| state);
+        let state = snapshot.apply(baseline)?;
+        let game_state = state.game_state();
+        self.received.push_back((snapshot.tick, state));
+        while self.received.len() > SNAPSHOT_HISTORY {
+            self.received.pop_front();
+        }
+        Ok(game_state)
+    }
+
+    // a new connection starts from a keyframe
+    pub fn clear(&mut self) {
+        self.received.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ball(number: u32, position: Vec3) -> BallData {
+        BallData { number, position, velocity: Vec3::ZERO, rotation: Quat::IDENTITY, is_cue: number == 0 }
+    }
+
+    fn state(balls: Vec<BallData>) -> GameState {
+        GameState { balls, ..Default::default() }
+    }
+
+    #[test]
+    fn quantized_balls_come_back_within_a_tenth_of_a_millimetre() {
+        let original = BallData {
+            number: 9,
+            position: Vec3::new(0.6123, 0.0285, -1.1999),
+            velocity: Vec3::new(-3.2, 0.0, 7.75),
+            rotation: Quat::from_rotation_y(1.0) * Quat::from_rotation_x(0.3),
+            is_cue: false,
+        };
+        let restored = QuantizedBall::new(&original).ball();
+        assert!(restored.position.distance(original.position) < 1e-4);
+        assert!(restored.velocity.distance(original.velocity) < 1e-3);
+        assert!(restored.rotation.angle_between(original.rotation) < 1e-3);
+        assert_eq!(restored.number, 9);
+
+        let wild = QuantizedBall::new(&BallData { position: Vec3::new(f32::NAN, 1e9, -1e9), ..original });
+        assert_eq!(wild.position, [0, i16::MAX, i16::MIN]);
+    }
+
+    #[test]
+    fn deltas_carry_only_what_moved() {
+        let before = TickState::new(&state(vec![ball(0, Vec3::ZERO), ball(1, Vec3::X), ball(2, Vec3::Z)]));
+        let after = TickState::new(&state(vec![ball(0, Vec3::new(0.0, 0.0, 0.5)), ball(2, Vec3::Z)]));
+
+        let delta = Snapshot::delta(5, 0.5, 4, &before, &after);
+        assert_eq!(delta.balls.len(), 1);
+        assert_eq!(delta.balls[0].number, 0);
+        assert_eq!(delta.removed, vec![1]);
+        assert!(delta.state.is_none());
+        assert_eq!(delta.apply(Some(&before)).unwrap(), after);
+        assert!(delta.apply(None).is_err());
+
+        let unchanged = Snapshot::delta(6, 0.6, 5, &after, &after);
+        assert!(unchanged.balls.is_empty() && unchanged.removed.is_empty() && unchanged.state.is_none());
+    }
+
+    #[test]
+    fn a_running_shot_clock_only_changes_the_state_once_a_second() {
+        let mut clock = GameState { shot_clock: Some(12.4), ..Default::default() };
+        let first = TickState::new(&clock);
+        clock.shot_clock = Some(12.1);
+        assert_eq!(TickState::new(&clock), first);
+        clock.shot_clock = Some(11.9);
+        assert_ne!(TickState::new(&clock), first);
+    }
+
+    #[test]
+    fn deltas_follow_the_acknowledged_tick_and_fall_back_to_keyframes() {
+        let mut sent = SentSnapshots::default();
+        let mut received = ReceivedSnapshots::default();
+        let at = |z: f32| Arc::new(TickState::new(&state(vec![ball(0, Vec3::new(0.0, 0.0, z)), ball(1, Vec3::X)])));
+
+        // nothing acknowledged yet, so a keyframe
+        let first = sent.encode(1, 0.0, at(0.0), false);
+        assert!(first.baseline.is_none());
+        received.apply(&first).unwrap();
+        sent.ack(1);
+
+        // unacknowledged ticks are never built on
+        let second = sent.encode(2, 0.0, at(0.1), false);
+        assert_eq!(second.baseline, Some(1));
+        let third = sent.encode(3, 0.0, at(0.2), false);
+        assert_eq!(third.baseline, Some(1));
+        assert_eq!(third.balls.len(), 1);
+        received.apply(&second).unwrap();
+        let rebuilt = received.apply(&third).unwrap();
+        assert!((rebuilt.balls.iter().find(|b| b.number == 0).unwrap().position.z - 0.2).abs() < 1e-4);
+        assert_eq!(rebuilt.balls.len(), 2);
+
+        // a periodic keyframe ignores the baseline
+        assert!(sent.encode(4, 0.0, at(0.2), true).baseline.is_none());
+
+        // an acknowledgement that has dropped out of the history is forgotten
+        for tick in 5..5 + SNAPSHOT_HISTORY as u32 {
+            sent.encode(tick, 0.0, at(0.3), false);
+        }
+        assert!(sent.encode(100, 0.0, at(0.3), false).baseline.is_none());
+    }
+}
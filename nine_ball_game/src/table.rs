@@ -0,0 +1,437 @@
+// src/table.rs
+// Table geometry shared by the server simulation and the client renderer.
+//
+// The playing surface spans -half_width..half_width on x and
+// -half_length..half_length on z, measured to the cushion noses.
+// The head of the table (where the cue ball is spotted) is at negative z,
+// the foot (where the rack goes) is at positive z, and "left" is negative x.
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::physics::{cloth::Cloth, cushion::CushionModel};
+use super::{
+    CORNER_JAW_ANGLE, CORNER_POCKET_MOUTH, CORNER_POCKET_SHELF, CUSHION_NOSE_HEIGHT,
+    CUSHION_RESTITUTION_FALLOFF, RAIL_HEIGHT, RAIL_WIDTH, SIDE_JAW_ANGLE, SIDE_POCKET_MOUTH,
+    SIDE_POCKET_SHELF,
+};
+
+/// Everything about the table that can differ between venues. The server
+/// builds one at startup (see `--table`) and sends it to clients when they
+/// connect, so both sides simulate and draw the same table.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TableSpec {
+    pub name: String,
+    /// Playing surface, cushion nose to cushion nose (m)
+    pub length: f32,
+    pub width: f32,
+    /// Distance between the jaw points at the pocket opening (m)
+    pub corner_pocket_mouth: f32,
+    pub side_pocket_mouth: f32,
+    /// Cushion restitution for a slow ball; harder hits get less back
+    pub cushion_restitution: f32,
+    /// Height of the cushion nose above the bed (m)
+    pub cushion_nose_height: f32,
+    /// Ball on cloth friction while the ball skids (draw, follow, stun)
+    pub cloth_friction: f32,
+    /// Slows a rolling ball; higher is a slower cloth
+    pub cloth_rolling_resistance: f32,
+    /// Friction against side spin about the vertical axis
+    pub cloth_spin_friction: f32,
+}
+
+impl Default for TableSpec {
+    fn default() -> Self {
+        Self::nine_foot()
+    }
+}
+
+impl TableSpec {
+    fn regulation(name: &str, length: f32) -> Self {
+        TableSpec {
+            name: name.to_string(),
+            length,
+            width: length / 2.0,
+            corner_pocket_mouth: CORNER_POCKET_MOUTH,
+            side_pocket_mouth: SIDE_POCKET_MOUTH,
+            cushion_restitution: 0.9,
+            cushion_nose_height: CUSHION_NOSE_HEIGHT,
+            cloth_friction: 0.2,
+            cloth_rolling_resistance: 0.01,
+            cloth_spin_friction: 0.013,
+        }
+    }
+
+    pub fn seven_foot() -> Self {
+        Self::regulation("7ft", 1.98)
+    }
+
+    pub fn eight_foot() -> Self {
+        Self::regulation("8ft", 2.24)
+    }
+
+    pub fn nine_foot() -> Self {
+        Self::regulation("9ft", 2.54)
+    }
+
+    /// Look up a built-in table by name ("7ft", "8ft" or "9ft").
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "7ft" | "7" | "seven_foot" => Some(Self::seven_foot()),
+            "8ft" | "8" | "eight_foot" => Some(Self::eight_foot()),
+            "9ft" | "9" | "nine_foot" => Some(Self::nine_foot()),
+            _ => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (field, value) in [
+            ("length", self.length),
+            ("width", self.width),
+            ("corner_pocket_mouth", self.corner_pocket_mouth),
+            ("side_pocket_mouth", self.side_pocket_mouth),
+            ("cushion_nose_height", self.cushion_nose_height),
+        ] {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("{} must be a positive number, got {}", field, value));
+            }
+        }
+        for (field, value) in [
+            ("cushion_restitution", self.cushion_restitution),
+            ("cloth_friction", self.cloth_friction),
+            ("cloth_rolling_resistance", self.cloth_rolling_resistance),
+            ("cloth_spin_friction", self.cloth_spin_friction),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{} must not be negative, got {}", field, value));
+            }
+        }
+        if self.cushion_restitution > 1.0 {
+            return Err(format!("cushion_restitution must be at most 1, got {}", self.cushion_restitution));
+        }
+        if self.width > self.length {
+            return Err(format!("width {} is larger than length {}", self.width, self.length));
+        }
+        // the pocket cuts have to leave some cushion between them
+        if self.corner_cut() + self.side_pocket_mouth / 2.0 >= self.half_length()
+            || self.corner_cut() >= self.half_width()
+        {
+            return Err("pocket mouths are too large for the table".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn half_length(&self) -> f32 {
+        self.length / 2.0
+    }
+
+    pub fn half_width(&self) -> f32 {
+        self.width / 2.0
+    }
+
+    /// Where the cue ball starts, halfway between the centre and the head rail.
+    pub fn head_spot(&self) -> Vec3 {
+        Vec3::new(0.0, 0.0, -self.half_length() / 2.0)
+    }
+
+    /// Where the rack goes, halfway between the centre and the foot rail.
+    pub fn foot_spot(&self) -> Vec3 {
+        Vec3::new(0.0, 0.0, self.half_length() / 2.0)
+    }
+
+    /// Where a ball of `radius` is spotted, at rest on the bed: on `spot`
+    /// (usually the foot spot), or if a ball in `occupied` is in the way, as
+    /// close behind it on the long string as it fits, then in front of it.
+    pub fn spot_position(&self, spot: Vec3, radius: f32, occupied: &[Vec3]) -> Vec3 {
+        let fits = |z: f32| {
+            let candidate = Vec3::new(spot.x, radius, z);
+            occupied.iter().all(|ball| Vec2::new(ball.x - candidate.x, ball.z - candidate.z).length() >= 2.0 * radius)
+        };
+        // "behind" is towards the nearer end rail
+        let back = if spot.z < 0.0 { -1.0 } else { 1.0 };
+        let step = radius / 8.0;
+        let on_table = |z: &f32| z.abs() + radius <= self.half_length();
+        let behind = (0..).map(|i| spot.z + back * i as f32 * step).take_while(on_table);
+        let in_front = (1..).map(|i| spot.z - back * i as f32 * step).take_while(on_table);
+        let z = behind.chain(in_front).find(|&z| fits(z)).unwrap_or(spot.z);
+        Vec3::new(spot.x, radius, z)
+    }
+
+    /// Check a ball-in-hand placement of the cue ball (of `radius`) against
+    /// the cushions, the balls in `occupied` and, where the rules say so, the
+    /// head string. Gives back where the ball goes, at rest on the bed.
+    pub fn place_cue_ball(&self, position: Vec3, radius: f32, occupied: &[Vec3], behind_head_string: bool) -> Result<Vec3, String> {
+        if !(position.x.is_finite() && position.z.is_finite()) {
+            return Err("placement is not a position on the table".to_string());
+        }
+        let placed = Vec3::new(position.x, radius, position.z);
+        if placed.x.abs() + radius > self.half_width() || placed.z.abs() + radius > self.half_length() {
+            return Err("the cue ball has to be on the table".to_string());
+        }
+        // the head string runs across the table through the head spot
+        if behind_head_string && placed.z > self.head_spot().z {
+            return Err("the cue ball has to go behind the head string".to_string());
+        }
+        if occupied.iter().any(|ball| Vec2::new(ball.x - placed.x, ball.z - placed.z).length() < 2.0 * radius) {
+            return Err("the cue ball would overlap another ball".to_string());
+        }
+        Ok(placed)
+    }
+
+    pub fn cushion(&self) -> CushionModel {
+        CushionModel {
+            nose_height: self.cushion_nose_height,
+            restitution: self.cushion_restitution,
+            restitution_falloff: CUSHION_RESTITUTION_FALLOFF,
+        }
+    }
+
+    pub fn cloth(&self) -> Cloth {
+        Cloth {
+            sliding_friction: self.cloth_friction,
+            rolling_resistance: self.cloth_rolling_resistance,
+            spin_friction: self.cloth_spin_friction,
+        }
+    }
+
+    // distance from the corner to where the rails are cut for a corner pocket
+    fn corner_cut(&self) -> f32 {
+        self.corner_pocket_mouth / std::f32::consts::SQRT_2
+    }
+
+    /// Half extents of the slate, which runs out to the back of the rails.
+    pub fn bed_half_size(&self) -> Vec3 {
+        Vec3::new(self.half_width() + RAIL_WIDTH, 0.0, self.half_length() + RAIL_WIDTH)
+    }
+
+    /// The six straight cushion segments between the pockets.
+    pub fn rail_boxes(&self) -> Vec<TableBox> {
+        let (w, l) = (self.half_width(), self.half_length());
+        let d = self.corner_cut();
+        let half_side_mouth = self.side_pocket_mouth / 2.0;
+        let y = RAIL_HEIGHT / 2.0;
+        let mut boxes = Vec::new();
+
+        // head and foot rails
+        for sz in [-1.0, 1.0] {
+            boxes.push(TableBox {
+                center: Vec3::new(0.0, y, sz * (l + RAIL_WIDTH / 2.0)),
+                half_size: Vec3::new(w - d, y, RAIL_WIDTH / 2.0),
+                rotation: Quat::IDENTITY,
+            });
+        }
+
+        // long rails, split by the side pockets
+        let half_len = (l - d - half_side_mouth) / 2.0;
+        let mid = (l - d + half_side_mouth) / 2.0;
+        for sx in [-1.0, 1.0] {
+            for sz in [-1.0, 1.0] {
+                boxes.push(TableBox {
+                    center: Vec3::new(sx * (w + RAIL_WIDTH / 2.0), y, sz * mid),
+                    half_size: Vec3::new(RAIL_WIDTH / 2.0, y, half_len),
+                    rotation: Quat::IDENTITY,
+                });
+            }
+        }
+        boxes
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pocket {
+    HeadLeft,
+    HeadRight,
+    SideLeft,
+    SideRight,
+    FootLeft,
+    FootRight,
+}
+
+/// A ball that dropped during a shot, and the pocket it dropped into.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PocketedBall {
+    pub number: u32,
+    pub pocket: Pocket,
+}
+
+/// A fixed, axis-aligned-in-its-own-frame box (rail, jaw facing or pocket liner).
+#[derive(Debug, Clone, Copy)]
+pub struct TableBox {
+    pub center: Vec3,
+    pub half_size: Vec3,
+    pub rotation: Quat,
+}
+
+impl TableBox {
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: self.center,
+            rotation: self.rotation,
+            ..default()
+        }
+    }
+}
+
+impl Pocket {
+    pub const ALL: [Pocket; 6] = [
+        Pocket::HeadLeft,
+        Pocket::HeadRight,
+        Pocket::SideLeft,
+        Pocket::SideRight,
+        Pocket::FootLeft,
+        Pocket::FootRight,
+    ];
+
+    pub fn is_corner(self) -> bool {
+        !matches!(self, Pocket::SideLeft | Pocket::SideRight)
+    }
+
+    // (x sign, z sign) of the pocket; side pockets sit on z = 0
+    fn signs(self) -> (f32, f32) {
+        match self {
+            Pocket::HeadLeft => (-1.0, -1.0),
+            Pocket::HeadRight => (1.0, -1.0),
+            Pocket::SideLeft => (-1.0, 0.0),
+            Pocket::SideRight => (1.0, 0.0),
+            Pocket::FootLeft => (-1.0, 1.0),
+            Pocket::FootRight => (1.0, 1.0),
+        }
+    }
+
+    pub fn mouth_width(self, spec: &TableSpec) -> f32 {
+        if self.is_corner() { spec.corner_pocket_mouth } else { spec.side_pocket_mouth }
+    }
+
+    /// The two points where the cushion noses end at the pocket opening.
+    pub fn mouth_points(self, spec: &TableSpec) -> [Vec3; 2] {
+        let (sx, sz) = self.signs();
+        let (w, l) = (spec.half_width(), spec.half_length());
+        if self.is_corner() {
+            let d = spec.corner_cut();
+            [
+                Vec3::new(sx * (w - d), 0.0, sz * l), // end rail side
+                Vec3::new(sx * w, 0.0, sz * (l - d)), // side rail side
+            ]
+        } else {
+            let half = spec.side_pocket_mouth / 2.0;
+            [
+                Vec3::new(sx * w, 0.0, -half),
+                Vec3::new(sx * w, 0.0, half),
+            ]
+        }
+    }
+
+    pub fn mouth_center(self, spec: &TableSpec) -> Vec3 {
+        let [a, b] = self.mouth_points(spec);
+        (a + b) / 2.0
+    }
+
+    /// Unit vector pointing from the mouth into the pocket.
+    pub fn axis(self) -> Vec3 {
+        let (sx, sz) = self.signs();
+        Vec3::new(sx, 0.0, sz).normalize()
+    }
+
+    /// Radius of the hole in the slate; a ball drops once its centre is inside it.
+    pub fn hole_radius(self, spec: &TableSpec) -> f32 {
+        self.mouth_width(spec) * 0.4
+    }
+
+    /// Centre of the hole in the slate, on the bed plane.
+    pub fn hole_center(self, spec: &TableSpec) -> Vec3 {
+        let shelf = if self.is_corner() { CORNER_POCKET_SHELF } else { SIDE_POCKET_SHELF };
+        self.mouth_center(spec) + self.axis() * (shelf + self.hole_radius(spec))
+    }
+
+    /// Jaw facings as (start on the nose line, outward direction, length).
+    fn facings(self, spec: &TableSpec) -> [(Vec3, Vec3, f32); 2] {
+        let (sx, sz) = self.signs();
+        let [a, b] = self.mouth_points(spec);
+        if self.is_corner() {
+            let angle = (180.0 - CORNER_JAW_ANGLE).to_radians();
+            let length = RAIL_WIDTH / angle.sin();
+            [
+                (a, Vec3::new(sx * angle.cos(), 0.0, sz * angle.sin()), length),
+                (b, Vec3::new(sx * angle.sin(), 0.0, sz * angle.cos()), length),
+            ]
+        } else {
+            let angle = (180.0 - SIDE_JAW_ANGLE).to_radians();
+            let length = RAIL_WIDTH / angle.sin();
+            [
+                (a, Vec3::new(sx * angle.sin(), 0.0, angle.cos()), length),
+                (b, Vec3::new(sx * angle.sin(), 0.0, -angle.cos()), length),
+            ]
+        }
+    }
+
+    /// Jaw facings plus the liner that closes the back of the pocket.
+    pub fn jaw_boxes(self, spec: &TableSpec) -> Vec<TableBox> {
+        let center = self.mouth_center(spec);
+        let mut boxes: Vec<TableBox> = self
+            .facings(spec)
+            .iter()
+            .map(|&(start, dir, length)| {
+                // push the facing into the rail, away from the pocket opening
+                let mut away = Vec3::new(-dir.z, 0.0, dir.x);
+                if away.dot(start - center) < 0.0 {
+                    away = -away;
+                }
+                TableBox {
+                    center: start + dir * (length / 2.0) + away * (RAIL_WIDTH / 2.0) + Vec3::Y * (RAIL_HEIGHT / 2.0),
+                    half_size: Vec3::new(length / 2.0, RAIL_HEIGHT / 2.0, RAIL_WIDTH / 2.0),
+                    rotation: Quat::from_rotation_arc(Vec3::X, dir),
+                }
+            })
+            .collect();
+
+        let axis = self.axis();
+        let depth = (self.hole_center(spec) - center).length() + self.hole_radius(spec);
+        let across = Vec3::new(-axis.z, 0.0, axis.x);
+        boxes.push(TableBox {
+            center: center + axis * (depth + RAIL_WIDTH / 2.0) + Vec3::Y * (RAIL_HEIGHT / 2.0),
+            half_size: Vec3::new(self.mouth_width(spec) / 2.0 + RAIL_WIDTH, RAIL_HEIGHT / 2.0, RAIL_WIDTH / 2.0),
+            rotation: Quat::from_rotation_arc(Vec3::X, across),
+        });
+        boxes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const R: f32 = 0.028575;
+
+    #[test]
+    fn cue_ball_is_placed_at_rest_on_the_bed() {
+        let spec = TableSpec::nine_foot();
+        let placed = spec.place_cue_ball(Vec3::new(0.1, 2.0, 0.3), R, &[], false).unwrap();
+        assert_eq!(placed, Vec3::new(0.1, R, 0.3));
+    }
+
+    #[test]
+    fn cue_ball_has_to_fit_inside_the_cushions() {
+        let spec = TableSpec::nine_foot();
+        assert!(spec.place_cue_ball(Vec3::new(spec.half_width() - R, 0.0, 0.0), R, &[], false).is_ok());
+        assert!(spec.place_cue_ball(Vec3::new(spec.half_width(), 0.0, 0.0), R, &[], false).is_err());
+        assert!(spec.place_cue_ball(Vec3::new(0.0, 0.0, -spec.half_length()), R, &[], false).is_err());
+        assert!(spec.place_cue_ball(Vec3::new(f32::NAN, 0.0, 0.0), R, &[], false).is_err());
+    }
+
+    #[test]
+    fn cue_ball_may_not_overlap_a_ball() {
+        let spec = TableSpec::nine_foot();
+        let occupied = [Vec3::new(0.0, R, 0.0)];
+        assert!(spec.place_cue_ball(Vec3::new(1.5 * R, 0.0, 0.0), R, &occupied, false).is_err());
+        assert!(spec.place_cue_ball(Vec3::new(2.0 * R, 0.0, 0.0), R, &occupied, false).is_ok());
+    }
+
+    #[test]
+    fn kitchen_is_behind_the_head_string() {
+        let spec = TableSpec::nine_foot();
+        let head_string = spec.head_spot().z;
+        assert!(spec.place_cue_ball(Vec3::new(0.0, 0.0, head_string - 0.1), R, &[], true).is_ok());
+        assert!(spec.place_cue_ball(Vec3::new(0.0, 0.0, head_string + 0.1), R, &[], true).is_err());
+        assert!(spec.place_cue_ball(Vec3::new(0.0, 0.0, head_string + 0.1), R, &[], false).is_ok());
+    }
+}
@@ -0,0 +1,154 @@
+// src/trajectory.rs
+// A whole shot, recorded on the server as it is resolved faster than real
+// time and played back by the client. Frames are a fixed interval apart,
+// but for the last, and carry only the balls that moved since the frame before.
+use serde::{Deserialize, Serialize};
+
+use super::snapshot::{diff, patch, QuantizedBall};
+use super::BallData;
+
+// Seconds between recorded frames; the client interpolates between them
+pub const TRAJECTORY_INTERVAL: f32 = 1.0 / 30.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TrajectoryFrame {
+    // balls that are new or have moved since the frame before
+    pub balls: Vec<QuantizedBall>,
+    // balls in the frame before that have been pocketed or left the table
+    pub removed: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Trajectory {
+    pub interval: f32,
+    // the first frame is every ball as the cue struck
+    pub frames: Vec<TrajectoryFrame>,
+    // seconds from the strike to the last frame, which is the step the balls
+    // stopped on and so can come less than an interval after the one before
+    pub end: f32,
+}
+
+impl Trajectory {
+    /// Seconds from the strike to the last frame.
+    pub fn duration(&self) -> f32 {
+        self.end
+    }
+
+    /// Every frame in full, with its time from the strike.
+    pub fn states(&self) -> Vec<(f32, Vec<BallData>)> {
+        let mut balls = Vec::new();
+        self.frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                patch(&mut balls, &frame.balls, &frame.removed);
+                ((i as f32 * self.interval).min(self.end), balls.iter().map(QuantizedBall::ball).collect())
+            })
+            .collect()
+    }
+}
+
+/// Builds a trajectory from the balls at every physics step, keeping one
+/// step in every `TRAJECTORY_INTERVAL`.
+#[derive(Debug)]
+pub struct TrajectoryRecorder {
+    step: f32,
+    steps_per_frame: usize,
+    steps: usize,
+    last: Vec<QuantizedBall>,
+    // the newest step, if it fell between frames
+    pending: Option<Vec<QuantizedBall>>,
+    trajectory: Trajectory,
+}
+
+impl TrajectoryRecorder {
+    pub fn new(step: f32) -> Self {
+        let steps_per_frame = ((TRAJECTORY_INTERVAL / step).round() as usize).max(1);
+        TrajectoryRecorder {
+            step,
+            steps_per_frame,
+            steps: 0,
+            last: Vec::new(),
+            pending: None,
+            trajectory: Trajectory { interval: steps_per_frame as f32 * step, frames: Vec::new(), end: 0.0 },
+        }
+    }
+
+    pub fn record(&mut self, balls: &[BallData]) {
+        let balls: Vec<QuantizedBall> = balls.iter().map(QuantizedBall::new).collect();
+        if self.steps.is_multiple_of(self.steps_per_frame) {
+            self.push(balls);
+        } else {
+            self.pending = Some(balls);
+        }
+        self.steps += 1;
+    }
+
+    /// The trajectory, ending on the last step recorded.
+    pub fn finish(mut self) -> Trajectory {
+        if let Some(balls) = self.pending.take() {
+            self.push(balls);
+        }
+        self.trajectory.end = self.steps.saturating_sub(1) as f32 * self.step;
+        self.trajectory
+    }
+
+    fn push(&mut self, balls: Vec<QuantizedBall>) {
+        let (changed, removed) = diff(&self.last, &balls);
+        self.trajectory.frames.push(TrajectoryFrame { balls: changed, removed });
+        self.last = balls;
+        self.pending = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::*;
+
+    fn balls(cue_x: f32, with_nine: bool) -> Vec<BallData> {
+        let mut balls = vec![BallData { number: 0, position: Vec3::new(cue_x, 0.0, 0.0), is_cue: true, ..Default::default() }];
+        if with_nine {
+            balls.push(BallData { number: 9, position: Vec3::new(0.5, 0.0, 0.0), ..Default::default() });
+        }
+        balls
+    }
+
+    #[test]
+    fn every_other_step_is_kept_and_the_last_one_always() {
+        let mut recorder = TrajectoryRecorder::new(1.0 / 60.0);
+        for step in 0..5 {
+            recorder.record(&balls(step as f32 * 0.01, step < 3));
+        }
+        let trajectory = recorder.finish();
+
+        // steps 0, 2 and 4
+        assert_eq!(trajectory.frames.len(), 3);
+        assert!((trajectory.duration() - 2.0 / 30.0).abs() < 1e-6);
+        // the 9 sits still, so after the first frame only the cue ball is sent
+        assert_eq!(trajectory.frames[0].balls.len(), 2);
+        assert_eq!(trajectory.frames[1].balls.len(), 1);
+        assert_eq!(trajectory.frames[2].removed, vec![9]);
+
+        let states = trajectory.states();
+        assert_eq!(states[1].1.len(), 2);
+        assert!((states[2].1[0].position.x - 0.04).abs() < 1e-4);
+        assert_eq!(states[2].1.len(), 1);
+    }
+
+    #[test]
+    fn a_shot_ending_between_frames_still_ends_where_the_balls_stopped() {
+        let mut recorder = TrajectoryRecorder::new(1.0 / 60.0);
+        for step in 0..4 {
+            recorder.record(&balls(step as f32 * 0.01, true));
+        }
+        let trajectory = recorder.finish();
+        // the last step came half an interval after the frame before it
+        assert!((trajectory.duration() - 3.0 / 60.0).abs() < 1e-6);
+        let states = trajectory.states();
+        assert_eq!(states.len(), 3);
+        assert!((states[1].0 - 2.0 / 60.0).abs() < 1e-6);
+        assert!((states[2].0 - 3.0 / 60.0).abs() < 1e-6);
+        assert!((states[2].1[0].position.x - 0.03).abs() < 1e-4);
+    }
+}
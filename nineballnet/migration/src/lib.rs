@@ -4,6 +4,7 @@ pub use sea_orm_migration::prelude::*;
 mod m20220101_000001_users;
 
 mod m20251220_012429_matches;
+mod m20261018_000001_add_result_to_matches;
 pub struct Migrator;
 
 #[async_trait::async_trait]
@@ -12,6 +13,7 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20220101_000001_users::Migration),
             Box::new(m20251220_012429_matches::Migration),
+            Box::new(m20261018_000001_add_result_to_matches::Migration),
             // inject-above (do not remove this comment)
         ]
     }
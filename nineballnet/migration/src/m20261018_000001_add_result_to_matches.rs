@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        m.alter_table(
+            Table::alter()
+                .table(Matches::Table)
+                .add_column(ColumnDef::new(Matches::RacksFor).integer())       // Nullable (until the match is over)
+                .add_column(ColumnDef::new(Matches::RacksAgainst).integer())
+                .add_column(ColumnDef::new(Matches::Result).text())            // The signed report, as sent by the game server
+                .add_column(ColumnDef::new(Matches::FinishedAt).date_time())
+                .to_owned(),
+        ).await
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        m.alter_table(
+            Table::alter()
+                .table(Matches::Table)
+                .drop_column(Matches::RacksFor)
+                .drop_column(Matches::RacksAgainst)
+                .drop_column(Matches::Result)
+                .drop_column(Matches::FinishedAt)
+                .to_owned(),
+        ).await
+    }
+}
+
+#[derive(Iden)]
+pub enum Matches {
+    Table,
+    RacksFor,
+    RacksAgainst,
+    Result,
+    FinishedAt,
+}
@@ -53,6 +53,7 @@ impl Hooks for App {
         AppRoutes::with_default_routes() // controller routes below
             .add_route(controllers::lobby::routes())
             .add_route(controllers::matchmaking::routes())
+            .add_route(controllers::results::routes())
             .add_route(controllers::auth::routes())
             .add_route(controllers::web::routes())
     }
@@ -2,4 +2,5 @@ pub mod auth;
 
 pub mod matchmaking;
 pub mod lobby;
+pub mod results;
 pub mod web;
\ No newline at end of file
@@ -0,0 +1,64 @@
+use loco_rs::prelude::*;
+use axum::body::Bytes;
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::models::matches::{MatchResult, Model as MatchModel};
+
+// Set by the game server: lowercase hex HMAC-SHA256 of the body
+const SIGNATURE_HEADER: &str = "x-match-signature";
+// Shared with the game servers, which sign with the same variable
+const SECRET_ENV: &str = "MATCH_RESULT_SECRET";
+
+fn signature_is_valid(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+// POST /api/matches/result
+// Called by the game server when a match is over; not by players.
+pub async fn report(
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response> {
+    let secret = std::env::var(SECRET_ENV).unwrap_or_default();
+    if secret.is_empty() {
+        return Err(Error::Message(format!("{} is not set", SECRET_ENV)));
+    }
+
+    // 1. Only accept results signed with the shared secret
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    if !signature_is_valid(&secret, &body, signature) {
+        return unauthorized("invalid match result signature");
+    }
+
+    let result: MatchResult = serde_json::from_slice(&body)
+        .map_err(|e| Error::BadRequest(format!("Invalid match result: {}", e)))?;
+
+    // 2. Finalize both players' rows
+    let rows = match MatchModel::finalize(&ctx.db, &result, &String::from_utf8_lossy(&body)).await {
+        Ok(rows) => rows,
+        Err(ModelError::EntityNotFound) => return not_found(),
+        Err(ModelError::Message(msg)) => return bad_request(msg),
+        Err(e) => return Err(e.into()),
+    };
+    println!("RESULTS: Match {} finalized ({} players)", result.match_id, rows.len());
+
+    format::json(rows)
+}
+
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("api/matches")
+        .add("/result", post(report))
+}
@@ -14,6 +14,11 @@ pub struct Model {
     pub gateway_url: Option<String>,
     #[sea_orm(column_type = "Text", nullable)]
     pub handoff_token: Option<String>,
+    pub racks_for: Option<i32>,
+    pub racks_against: Option<i32>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub result: Option<String>,
+    pub finished_at: Option<DateTime>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
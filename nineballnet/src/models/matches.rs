@@ -1,5 +1,9 @@
+use chrono::Utc;
+use loco_rs::model::{ModelError, ModelResult};
 use sea_orm::entity::prelude::*;
-pub use super::_entities::matches::{ActiveModel, Model, Entity};
+use sea_orm::ActiveValue::Set;
+use serde::{Deserialize, Serialize};
+pub use super::_entities::matches::{self, ActiveModel, Model, Entity};
 pub type Matches = Entity;
 
 #[async_trait::async_trait]
@@ -12,8 +16,69 @@ impl ActiveModelBehavior for ActiveModel {
     }
 }
 
+/// How a match ended, as reported by the game server that hosted it
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MatchResult {
+    pub match_id: Uuid,
+    /// Empty when the match was abandoned
+    pub winner_token: String,
+    pub winner_racks: i32,
+    pub loser_racks: i32,
+    /// Nobody played for the game server's inactivity limit
+    #[serde(default)]
+    pub abandoned: bool,
+    pub shots: u32,
+    pub duration_secs: u64,
+    pub fouls: Vec<serde_json::Value>,
+}
+
 // implement your read-oriented logic here
-impl Model {}
+impl Model {
+    /// Records the result on each player's row of the match, won or lost,
+    /// or abandoned for both. Rows that are already finished are left alone,
+    /// so a retried report does no harm.
+    ///
+    /// # Errors
+    ///
+    /// When the match does not exist, the winner's token is not one of its
+    /// players', or on a DB error
+    pub async fn finalize(db: &DatabaseConnection, result: &MatchResult, report: &str) -> ModelResult<Vec<Self>> {
+        let rows = Entity::find()
+            .filter(matches::Column::MatchId.eq(result.match_id))
+            .all(db)
+            .await?;
+        if rows.is_empty() {
+            return Err(ModelError::EntityNotFound);
+        }
+        if !result.abandoned && !rows.iter().any(|row| row.handoff_token.as_deref() == Some(result.winner_token.as_str())) {
+            return Err(ModelError::msg("winner is not a player in this match"));
+        }
+
+        let now = Utc::now().naive_utc();
+        let mut finalized = Vec::new();
+        for row in rows {
+            if row.finished_at.is_some() {
+                finalized.push(row);
+                continue;
+            }
+            let won = row.handoff_token.as_deref() == Some(result.winner_token.as_str());
+            let mut record: ActiveModel = row.into();
+            if result.abandoned {
+                // the racks are in the stored report; there is no for and against
+                record.status = Set("abandoned".to_string());
+            } else {
+                record.status = Set(if won { "won" } else { "lost" }.to_string());
+                record.racks_for = Set(Some(if won { result.winner_racks } else { result.loser_racks }));
+                record.racks_against = Set(Some(if won { result.loser_racks } else { result.winner_racks }));
+            }
+            record.result = Set(Some(report.to_string()));
+            record.finished_at = Set(Some(now));
+            record.updated_at = Set(now);
+            finalized.push(record.update(db).await?);
+        }
+        Ok(finalized)
+    }
+}
 
 // implement your write-oriented logic here
 impl ActiveModel {}
@@ -1,6 +1,14 @@
 use nineballnet::app::App;
+use nineballnet::models::{
+    _entities::matches::Column,
+    matches::{self, MatchResult, Model},
+};
 use loco_rs::testing::prelude::*;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
 use serial_test::serial;
+use uuid::Uuid;
+
+use crate::requests::prepare_data::{seat_players, LOSER_TOKEN, WINNER_TOKEN};
 
 macro_rules! configure_insta {
     ($($expr:expr),*) => {
@@ -10,6 +18,29 @@ macro_rules! configure_insta {
     };
 }
 
+fn result_for(match_id: Uuid) -> MatchResult {
+    MatchResult {
+        match_id,
+        winner_token: WINNER_TOKEN.to_string(),
+        winner_racks: 5,
+        loser_racks: 3,
+        abandoned: false,
+        shots: 120,
+        duration_secs: 1800,
+        fouls: vec![],
+    }
+}
+
+async fn row_for(db: &DatabaseConnection, match_id: Uuid, token: &str) -> Model {
+    matches::Entity::find()
+        .filter(Column::MatchId.eq(match_id))
+        .filter(Column::HandoffToken.eq(token))
+        .one(db)
+        .await
+        .unwrap()
+        .expect("player row")
+}
+
 #[tokio::test]
 #[serial]
 async fn test_model() {
@@ -29,3 +60,68 @@ async fn test_model() {
     // snapshot the result:
     // assert_debug_snapshot!(item);
 }
+
+#[tokio::test]
+#[serial]
+async fn finalize_sets_the_winner_from_the_handoff_token() {
+    let boot = boot_test::<App>().await.unwrap();
+    let db = &boot.app_context.db;
+    let match_id = seat_players(db).await;
+
+    let rows = Model::finalize(db, &result_for(match_id), "{}").await.unwrap();
+    assert_eq!(rows.len(), 2);
+
+    let winner = row_for(db, match_id, WINNER_TOKEN).await;
+    assert_eq!(winner.status, "won");
+    assert_eq!(winner.racks_for, Some(5));
+    assert_eq!(winner.racks_against, Some(3));
+    assert!(winner.finished_at.is_some());
+
+    let loser = row_for(db, match_id, LOSER_TOKEN).await;
+    assert_eq!(loser.status, "lost");
+    assert_eq!(loser.racks_for, Some(3));
+    assert_eq!(loser.racks_against, Some(5));
+    assert_eq!(loser.result.as_deref(), Some("{}"));
+}
+
+#[tokio::test]
+#[serial]
+async fn finalize_twice_changes_nothing() {
+    let boot = boot_test::<App>().await.unwrap();
+    let db = &boot.app_context.db;
+    let match_id = seat_players(db).await;
+    let result = result_for(match_id);
+
+    let first = Model::finalize(db, &result, "{}").await.unwrap();
+    let retried = Model::finalize(db, &result, "{}").await.unwrap();
+    assert_eq!(first, retried);
+}
+
+#[tokio::test]
+#[serial]
+async fn finalize_rejects_a_winner_from_another_match() {
+    let boot = boot_test::<App>().await.unwrap();
+    let db = &boot.app_context.db;
+    let match_id = seat_players(db).await;
+    let result = MatchResult { winner_token: "someone-else".to_string(), ..result_for(match_id) };
+
+    assert!(Model::finalize(db, &result, "{}").await.is_err());
+    assert_eq!(row_for(db, match_id, WINNER_TOKEN).await.status, "ready");
+}
+
+#[tokio::test]
+#[serial]
+async fn finalize_an_abandoned_match_has_no_winner() {
+    let boot = boot_test::<App>().await.unwrap();
+    let db = &boot.app_context.db;
+    let match_id = seat_players(db).await;
+    let result = MatchResult { winner_token: String::new(), abandoned: true, ..result_for(match_id) };
+
+    let rows = Model::finalize(db, &result, "{}").await.unwrap();
+    assert_eq!(rows.len(), 2);
+    for row in rows {
+        assert_eq!(row.status, "abandoned");
+        assert_eq!(row.racks_for, None);
+        assert!(row.finished_at.is_some());
+    }
+}
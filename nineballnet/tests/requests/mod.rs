@@ -1,4 +1,5 @@
 mod auth;
-mod prepare_data;
+pub mod prepare_data;
+mod results;
 
-pub mod matchmaking;
\ No newline at end of file
+pub mod matchmaking;
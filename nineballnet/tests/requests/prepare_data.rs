@@ -1,10 +1,19 @@
 use axum::http::{HeaderName, HeaderValue};
+use chrono::Utc;
 use loco_rs::{app::AppContext, TestServer};
-use nineballnet::{models::users, views::auth::LoginResponse};
+use nineballnet::{
+    models::{matches, users},
+    views::auth::LoginResponse,
+};
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, DatabaseConnection};
+use uuid::Uuid;
 
 const USER_EMAIL: &str = "test@loco.com";
 const USER_PASSWORD: &str = "1234";
 
+pub const WINNER_TOKEN: &str = "winner-token";
+pub const LOSER_TOKEN: &str = "loser-token";
+
 pub struct LoggedInUser {
     pub user: users::Model,
     pub token: String,
@@ -55,3 +64,24 @@ pub fn auth_header(token: &str) -> (HeaderName, HeaderValue) {
 
     (HeaderName::from_static("authorization"), auth_header_value)
 }
+
+/// Two players' rows for a new match, as the matchmaking worker writes them
+pub async fn seat_players(db: &DatabaseConnection) -> Uuid {
+    let match_id = Uuid::new_v4();
+    let now = Utc::now().naive_utc();
+    for token in [WINNER_TOKEN, LOSER_TOKEN] {
+        matches::ActiveModel {
+            match_id: Set(match_id),
+            player_id: Set(Uuid::new_v4()),
+            status: Set("ready".to_string()),
+            handoff_token: Set(Some(token.to_string())),
+            created_at: Set(now),
+            updated_at: Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+    match_id
+}
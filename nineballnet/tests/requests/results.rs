@@ -0,0 +1,127 @@
+use hmac::{Hmac, Mac};
+use loco_rs::testing::prelude::*;
+use nineballnet::{app::App, models::matches};
+use serial_test::serial;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use super::prepare_data::{seat_players, LOSER_TOKEN, WINNER_TOKEN};
+
+const SECRET: &str = "test-match-secret";
+
+fn sign(body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn report(match_id: Uuid, winner_token: &str) -> String {
+    serde_json::json!({
+        "match_id": match_id,
+        "winner_token": winner_token,
+        "winner_racks": 5,
+        "loser_racks": 2,
+        "shots": 90,
+        "duration_secs": 1500,
+        "fouls": []
+    })
+    .to_string()
+}
+
+#[tokio::test]
+#[serial]
+async fn rejects_a_result_without_a_signature() {
+    std::env::set_var("MATCH_RESULT_SECRET", SECRET);
+    request::<App, _, _>(|request, ctx| async move {
+        let match_id = seat_players(&ctx.db).await;
+
+        let response = request
+            .post("/api/matches/result")
+            .text(report(match_id, WINNER_TOKEN))
+            .await;
+        assert_eq!(response.status_code(), 401, "Unsigned result should be rejected");
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn rejects_a_result_with_a_bad_signature() {
+    std::env::set_var("MATCH_RESULT_SECRET", SECRET);
+    request::<App, _, _>(|request, ctx| async move {
+        let match_id = seat_players(&ctx.db).await;
+        let body = report(match_id, WINNER_TOKEN);
+        // signed, but not this body
+        let signature = sign(&report(match_id, LOSER_TOKEN));
+
+        let response = request
+            .post("/api/matches/result")
+            .add_header("x-match-signature", signature)
+            .text(body)
+            .await;
+        assert_eq!(response.status_code(), 401, "Tampered result should be rejected");
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn rejects_a_result_for_an_unknown_match() {
+    std::env::set_var("MATCH_RESULT_SECRET", SECRET);
+    request::<App, _, _>(|request, _ctx| async move {
+        let body = report(Uuid::new_v4(), WINNER_TOKEN);
+
+        let response = request
+            .post("/api/matches/result")
+            .add_header("x-match-signature", sign(&body))
+            .text(body)
+            .await;
+        assert_eq!(response.status_code(), 404, "Unknown match should not be found");
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn rejects_a_winner_who_did_not_play() {
+    std::env::set_var("MATCH_RESULT_SECRET", SECRET);
+    request::<App, _, _>(|request, ctx| async move {
+        let match_id = seat_players(&ctx.db).await;
+        let body = report(match_id, "someone-else");
+
+        let response = request
+            .post("/api/matches/result")
+            .add_header("x-match-signature", sign(&body))
+            .text(body)
+            .await;
+        assert_eq!(response.status_code(), 400, "Unknown winner should be a bad request");
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_report_a_signed_result() {
+    std::env::set_var("MATCH_RESULT_SECRET", SECRET);
+    request::<App, _, _>(|request, ctx| async move {
+        let match_id = seat_players(&ctx.db).await;
+        let body = report(match_id, WINNER_TOKEN);
+
+        let response = request
+            .post("/api/matches/result")
+            .add_header("x-match-signature", sign(&body))
+            .text(body)
+            .await;
+        assert_eq!(response.status_code(), 200, "Signed result should be accepted");
+
+        let rows: Vec<matches::Model> = serde_json::from_str(&response.text()).unwrap();
+        let status_of = |token: &str| {
+            rows.iter()
+                .find(|row| row.handoff_token.as_deref() == Some(token))
+                .map(|row| row.status.clone())
+        };
+        assert_eq!(status_of(WINNER_TOKEN).as_deref(), Some("won"));
+        assert_eq!(status_of(LOSER_TOKEN).as_deref(), Some("lost"));
+    })
+    .await;
+}
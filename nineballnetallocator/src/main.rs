@@ -31,6 +31,7 @@ let reaper_state = state.clone();
     let app = Router::new()
         .route("/allocate", post(allocate_server)) // Private: Called by Loco
         .route("/play/:match_id", any(proxy_handler)) // Public: Called by Players
+        .route("/watch/:match_id", any(watch_handler)) // Public: Called by Spectators
         .with_state(state);
 
     // Render requires binding to 0.0.0.0
@@ -50,14 +51,7 @@ async fn proxy_handler(
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
     // 1. Find the internal port for this match
-    let target_port = {
-        let servers = state.active_servers.lock().unwrap();
-        // In a real app, you'd map match_id -> port efficiently. 
-        // Here we scan for simplicity.
-        servers.iter().find(|(_, p)| p.match_id == match_id).map(|(port, _)| *port)
-    };
-
-    let target_port = match target_port {
+    let target_port = match find_match_port(&state, &match_id) {
         Some(p) => p,
         None => return (StatusCode::NOT_FOUND, "Match not found").into_response(),
     };
@@ -71,6 +65,29 @@ async fn proxy_handler(
     })
 }
 
+// Spectators connect without a token, which the game server seats read-only
+async fn watch_handler(
+    ws: WebSocketUpgrade,
+    Path(match_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let target_port = match find_match_port(&state, &match_id) {
+        Some(p) => p,
+        None => return (StatusCode::NOT_FOUND, "Match not found").into_response(),
+    };
+
+    ws.on_upgrade(move |client_socket| async move {
+        handle_proxy(client_socket, target_port, String::new()).await;
+    })
+}
+
+fn find_match_port(state: &AppState, match_id: &str) -> Option<u16> {
+    let servers = state.active_servers.lock().unwrap();
+    // In a real app, you'd map match_id -> port efficiently. 
+    // Here we scan for simplicity.
+    servers.iter().find(|(_, p)| p.match_id == match_id).map(|(port, _)| *port)
+}
+
 async fn handle_proxy(mut client_socket: WebSocket, port: u16, token: String) {
     // 4. Connect internally to the Local Game Process
     // Note: The game server is running on localhost inside the same Render container
@@ -236,12 +253,19 @@ async fn allocate_server(
 
     // 2. Spawn the Game Binary
     // NOTE: In production, ensure 'game_server' is in the working directory or PATH
+    // Where the game server reports the result. It signs the report with
+    // MATCH_RESULT_SECRET, which it inherits from our environment.
+    let result_url = std::env::var("MATCH_RESULT_URL").unwrap_or_default();
+    // How many seconds spectators on /watch run behind the players
+    let spectator_delay = std::env::var("SPECTATOR_DELAY_SECS").unwrap_or_else(|_| "0".to_string());
     let spawn_result = Command::new(GAME_BINARY_PATH)
         .args(&[
             "--port", &port.to_string(),
             "--p1-token", &payload.p1_token,
             "--p2-token", &payload.p2_token,
             "--match-id", &payload.match_id,
+            "--result-url", &result_url,
+            "--spectator-delay", &spectator_delay,
         ])
         // Inherit logs so we can see game server output in the Allocator's console
         .stdout(std::process::Stdio::inherit())